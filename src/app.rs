@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 
 use wasm_bindgen::JsCast;
 
@@ -6,29 +7,205 @@ use cgmath::*;
 use egui::mutex::Mutex;
 use egui_glow::glow;
 
-use crate::camera::OrbitalCamera;
-use crate::render::RenderScene;
+use crate::camera::{OrbitalCamera, CameraState, CameraTransition};
+use crate::bindings::{InputBindings, KeyAction};
+use crate::render::{self, RenderScene, RenderTarget};
 use crate::mesh::IndexedMesh;
-use crate::simplification::Simplify;
+use crate::halfedge::HalfEdgeMesh;
+use crate::simplification::{self, Simplify};
 use crate::remesh::Remesher;
+use crate::repair::{Fix, InspectReport, PrintableReport, Repair};
+use crate::session::Session;
+use crate::gltf::Gltf;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ShadingMode {
+    Color,
+    MeanCurvature,
+    GaussianCurvature,
+    FaceColor,
+}
+
+// what one unit of mesh coordinates stands for, files don't say
+#[derive(Clone, Copy, PartialEq)]
+pub enum SceneUnits {
+    Millimeters,
+    Centimeters,
+    Meters,
+    Inches,
+}
+
+impl SceneUnits {
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            SceneUnits::Millimeters => "mm",
+            SceneUnits::Centimeters => "cm",
+            SceneUnits::Meters => "m",
+            SceneUnits::Inches => "in",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LightingPreset {
+    Studio,
+    Top,
+    Headlight,
+    Manual,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum RemeshMode {
+    // face into three at its centroid, shape stays the same
+    CentroidSplit,
+    // face into four at edge midpoints, surface gets smoother
+    LoopSubdivision,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportFilter {
+    All,
+    Visible,
+    Selected,
+}
+
+// taken from settings when loading starts, so async loads don't hold the editor
+#[derive(Clone, Copy)]
+pub struct ImportOptions {
+    // weld tolerance for stl as fraction of mesh size
+    pub stl_weld_eps: Option<f32>,
+    pub is_robust_triangulation: bool,
+    // left handed sources (directx tools) come mirrored without it
+    pub is_flip_handedness: bool,
+    // several files become frames of one animation instead of separate meshes
+    pub is_load_as_frames: bool,
+}
 
 #[derive(Clone)]
 pub struct Settings {
     pub is_cull_face: bool,
     pub is_flat_shading: bool,
+    pub is_camera_animated: bool,
+    pub is_fov_keep_framing: bool,
+    pub is_trackball: bool,
+    pub is_continuous_rendering: bool,
+    // scene goes to IndexedDB every interval seconds while changed, and soon after apply
+    pub is_autosave: bool,
+    pub autosave_interval: f32,
+    // continuous rendering and animations wait between frames to stay under max_fps
+    pub is_fps_capped: bool,
+    pub max_fps: u32,
+    pub is_interaction_lod: bool,
+    pub lod_min_faces: usize,
+    pub is_stats_graph: bool,
+    pub scene_units: SceneUnits,
+    pub is_scene_size: bool,
+    pub is_reference_cube: bool,
+    pub reference_size: f32,
+    pub reference_color: [f32; 3],
+    // center and size, placed by the app beside the scene bounds every frame
+    pub reference_cube: Option<(Vector3<f32>, f32)>,
     pub is_render_static: bool,
     pub is_render_temp: bool,
+    pub is_render_temp_on_top: bool,
+    pub is_render_fill: bool,
+    pub is_render_wireframe: bool,
+    pub is_render_points: bool,
 
+    pub fill_color: [f32; 3],
+    pub wireframe_color: [f32; 3],
+    // width in pixels of wireframe and other line overlays
+    pub line_width: f32,
+    pub points_color: [f32; 3],
+    pub point_size: f32,
+    pub shading_mode: ShadingMode,
+    pub is_outline: bool,
+    pub is_oit: bool,
+    pub outline_color: [f32; 3],
+    pub is_backface_highlight: bool,
+    pub backface_color: [f32; 3],
+    pub is_keep_original: bool,
+    // one state for the whole snapshot, it is hidden after every apply
+    pub original_state: MeshState,
+    pub original_color: [f32; 3],
+    pub occlusion_rays: usize,
+
+    pub lighting_preset: LightingPreset,
     pub light_pos: [f32; 3],
+    // placed around the scene bounds by recalculate_camera_view, so lighting doesn't depend on model scale
+    pub light_rig: render::LightRig,
+    pub rim_strength: f32,
+    pub rim_power: f32,
+    pub rim_color: [f32; 3],
+    pub is_clipping: bool,
+    pub clip_normal: [f32; 3],
+    pub clip_offset: f32,
     pub scroll_sensitivity: f32,
     pub min_camera_dist: f32,
+    // saved to eframe storage, the rest of settings resets on reload
+    pub bindings: InputBindings,
 
     pub simplification_error: f32,
+    pub simplification_error_pending: f32,
+    pub simplification_debounce_time: f32,
     pub simplification_agr: f32,
+    pub is_simplification_interactive: bool,
+    pub is_simplification_max_error_mode: bool,
+    pub simplification_max_error: f32,
+    pub simplification_cleanup_rounds: u32,
+    pub is_simplification_conservative: bool,
+    pub is_simplification_features: bool,
+    pub simplification_feature_angle: f32,
+    pub is_simplification_min_angle: bool,
+    pub simplification_min_angle: f32,
+    pub is_simplification_clustering: bool,
+    pub simplification_cluster_resolution: usize,
+    pub is_simplification_apply_guard: bool,
+    pub simplification_apply_guard: f32,
+    pub remesh_mode: RemeshMode,
     pub remesh_iterations: u32,
+    // fewest iterations done on any mesh, below requested when max faces stopped it
+    pub remesh_iterations_done: u32,
+    pub smooth_iterations: u32,
+    pub smooth_lambda: f32,
+    pub is_smooth_features: bool,
+    pub smooth_feature_angle: f32,
+    pub remesh_max_faces: usize,
+    // cells along the longest side of the mesh
+    pub voxel_resolution: usize,
+    pub is_flip_brush: bool,
+    // fraction of scene radius, zero flips just the clicked face
+    pub flip_brush_radius: f32,
+    pub transform_translate: [f32; 3],
+    // euler angles in degrees, applied in x, y, z order
+    pub transform_rotate: [f32; 3],
+    pub transform_scale: [f32; 3],
+    pub is_transform_snap: bool,
+    pub transform_snap_step: f32,
+
+    pub is_stl_weld: bool,
+    pub is_robust_triangulation: bool,
+    pub is_flip_handedness: bool,
+    pub is_load_as_frames: bool,
+    pub stl_weld_eps: f32,
+    pub frames_fps: f32,
+
+    pub is_export_ply_normals: bool,
+    pub is_export_weld: bool,
+    pub is_export_faceted: bool,
+    pub is_merge_weld: bool,
+    pub export_weld_report: Option<(usize, usize)>,
+    pub export_filter: ExportFilter,
+    pub stl_export_scale: f32,
+    // outline svg looks along this axis
+    pub silhouette_normal: [f32; 3],
+    pub export_file_name: String,
+    pub box_size: [f32; 3],
+    pub box_center: [f32; 3],
 
     pub total_num_faces: usize,
     pub total_num_faces_temp: usize,
+    pub total_num_faces_rounds: Vec<usize>,
 }
 
 impl Default for Settings {
@@ -36,27 +213,126 @@ impl Default for Settings {
         Self {
             is_cull_face: true,
             is_flat_shading: true,
+            is_camera_animated: true,
+            is_fov_keep_framing: true,
+            is_trackball: false,
+            is_continuous_rendering: true,
+            is_autosave: true,
+            autosave_interval: 60.0,
+            is_fps_capped: false,
+            max_fps: 30,
+            is_interaction_lod: true,
+            lod_min_faces: 500_000,
+            is_stats_graph: false,
+            scene_units: SceneUnits::Millimeters,
+            is_scene_size: true,
+            is_reference_cube: false,
+            reference_size: 10.0,
+            reference_color: [0.9, 0.6, 0.2],
+            reference_cube: None,
             is_render_static: true,
             is_render_temp: false,
+            is_render_temp_on_top: false,
+            is_render_fill: true,
+            is_render_wireframe: false,
+            is_render_points: false,
+
+            fill_color: [0.8, 0.8, 0.8],
+            wireframe_color: [0.1, 0.1, 0.1],
+            line_width: 1.5,
+            points_color: [0.9, 0.3, 0.1],
+            point_size: 4.0,
+            shading_mode: ShadingMode::Color,
+            is_outline: false,
+            is_oit: false,
+            outline_color: [0.05; 3],
+            is_backface_highlight: false,
+            backface_color: [0.9, 0.1, 0.6],
+            is_keep_original: false,
+            original_state: MeshState { is_visible: false, ..Default::default() },
+            original_color: [0.3, 0.5, 0.8],
+            occlusion_rays: 32,
 
+            lighting_preset: LightingPreset::Studio,
             light_pos: [0.0, 5.0, 0.0],
+            light_rig: render::LightRig::default(),
+            rim_strength: 0.0,
+            rim_power: 3.0,
+            rim_color: [1.0, 1.0, 1.0],
+            is_clipping: false,
+            clip_normal: [1.0, 0.0, 0.0],
+            clip_offset: 0.0,
             scroll_sensitivity: 0.001,
             min_camera_dist: 0.001,
+            bindings: InputBindings::default(),
 
             simplification_error: 1.0,
+            simplification_error_pending: 1.0,
+            simplification_debounce_time: 0.0,
             simplification_agr: 7.0,
+            is_simplification_interactive: false,
+            is_simplification_max_error_mode: false,
+            simplification_max_error: 0.01,
+            simplification_cleanup_rounds: 0,
+            is_simplification_conservative: false,
+            is_simplification_features: false,
+            simplification_feature_angle: 40.0,
+            is_simplification_min_angle: false,
+            simplification_min_angle: 15.0,
+            is_simplification_clustering: false,
+            simplification_cluster_resolution: 64,
+            is_simplification_apply_guard: true,
+            simplification_apply_guard: 0.9,
+            remesh_mode: RemeshMode::CentroidSplit,
             remesh_iterations: 1,
+            remesh_iterations_done: 1,
+            smooth_iterations: 3,
+            smooth_lambda: 0.5,
+            is_smooth_features: false,
+            smooth_feature_angle: 40.0,
+            remesh_max_faces: 5_000_000,
+            voxel_resolution: 64,
+            is_flip_brush: false,
+            flip_brush_radius: 0.0,
+            transform_translate: [0.0; 3],
+            transform_rotate: [0.0; 3],
+            transform_scale: [1.0; 3],
+            is_transform_snap: false,
+            transform_snap_step: 1.0,
+
+            is_stl_weld: true,
+            is_robust_triangulation: false,
+            is_flip_handedness: false,
+            is_load_as_frames: false,
+            stl_weld_eps: simplification::WELD_EPS_RELATIVE,
+            frames_fps: 24.0,
+
+            is_export_ply_normals: false,
+            is_export_weld: false,
+            is_export_faceted: false,
+            is_merge_weld: true,
+            export_weld_report: None,
+            export_filter: ExportFilter::All,
+            stl_export_scale: 1.0,
+            silhouette_normal: [0.0, 1.0, 0.0],
+            export_file_name: "file".to_string(),
+            box_size: [1.0; 3],
+            box_center: [0.0; 3],
             total_num_faces: 0,
             total_num_faces_temp: 0,
+            total_num_faces_rounds: vec![],
         }
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 enum PanelState {
     SelectionMenu,
     RemeshMenu,
     SimplificationMenu,
+    SmoothMenu,
+    TransformMenu,
+    VoxelMenu,
 }
 
 impl Default for PanelState {
@@ -65,51 +341,262 @@ impl Default for PanelState {
     }
 }
 
+#[derive(Clone, Copy)]
+pub struct MeshState {
+    pub is_visible: bool,
+    pub is_selected: bool,
+    // below 1 the mesh is drawn translucent over the others
+    pub alpha: f32,
+}
+
+impl Default for MeshState {
+    fn default() -> Self {
+        Self {
+            is_visible: true,
+            is_selected: false,
+            alpha: 1.0,
+        }
+    }
+}
+
+const BINDINGS_STORAGE_KEY: &str = "bindings";
+// apply saves sooner than the interval, but never more often than this
+const AUTOSAVE_MIN_INTERVAL: f64 = 5.0;
+
 pub struct WebEditor {
     render_scene_ref: Arc<Mutex<RenderScene>>,
     indexed_meshes: Vec<IndexedMesh>,
+    indexed_meshes_lod: Vec<Option<IndexedMesh>>,
     indexed_meshes_temp: Vec<IndexedMesh>,
+    // read only snapshot from before the last apply, drawn with settings.original_state
+    indexed_meshes_original: Vec<IndexedMesh>,
+    mesh_states: Vec<MeshState>,
+    // last plainly or ctrl clicked mesh, shift click selects range from it
+    selection_anchor: Option<usize>,
 
     settings: Settings,
     camera: OrbitalCamera,
+    camera_transition: Option<CameraTransition>,
+    scene_center: Vector3<f32>,
+    scene_radius: f32,
+    scene_aabb: (Vector3<f32>, Vector3<f32>),
+
+    // camera shared through url fragment, written once camera rests for a while,
+    // state with fov is compared every frame and the string is built only for the write
+    camera_url_state: Option<(CameraState, f32)>,
+    camera_url_idle_time: f32,
+    // delayed repaint of capped framerate is on the way, input frames don't add more
+    is_repaint_scheduled: Arc<std::sync::atomic::AtomicBool>,
+    pending_url_camera: Option<String>,
+
+    // surface point under the cursor, picked only when pointer or camera moves
+    hover_pos: Option<egui::Pos2>,
+    hover_hit: Option<Vector3<f32>>,
+
+    // apply of heavy simplification waits for confirmation
+    is_apply_confirm_open: bool,
+    // operation to start on the result once confirmed simplification is applied
+    apply_confirm_next: Option<PanelState>,
+    // result of make printable per repaired mesh, window is open while not empty
+    printable_reports: Vec<(String, PrintableReport)>,
+    // checks of inspected meshes by mesh index, redone after every fix
+    inspect_reports: Vec<(usize, InspectReport)>,
+    is_inspect_open: bool,
+    is_bindings_open: bool,
 
     state: PanelState,
+    simplifiers: Vec<Simplify>,
+    // faces count and surface area of the scene after every load and applied operation
+    edit_history: Vec<(usize, f32)>,
+
+    // animation frames, shown one lives in indexed_meshes and its slot here is empty
+    frames: Vec<IndexedMesh>,
+    frame_idx: usize,
+    frame_time: f32,
+    is_frames_playing: bool,
+
+    // file name, meshes and whether they are animation frames
+    receiver: Option<oneshot::Receiver<(String, Vec<IndexedMesh>, bool)>>,
 
-    receiver: Option<oneshot::Receiver<Vec<IndexedMesh>>>,
+    // session stored by the previous page, autosave waits until it's restored or discarded
+    session_receiver: Option<oneshot::Receiver<Option<Vec<u8>>>>,
+    stored_session: Option<Vec<u8>>,
+    is_session_dirty: bool,
+    is_session_applied: bool,
+    last_autosave_time: f64,
+
+    #[cfg(feature = "scripting")]
+    scripts: Vec<(String, Box<dyn FnMut(&mut Vec<IndexedMesh>)>)>,
 }
 
 impl WebEditor {
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let mut app = Self {
+        let mut settings = Settings::default();
+        if let Some(bindings) = cc.storage.and_then(|storage| storage.get_string(BINDINGS_STORAGE_KEY)) {
+            settings.bindings = InputBindings::from_storage_string(&bindings);
+        }
+        let (session_sender, session_receiver) = oneshot::channel();
+        Session::load(session_sender);
+
+        Self {
             render_scene_ref: Arc::new(Mutex::new(RenderScene::new(
                 cc.gl.as_ref()
             ))),
             indexed_meshes: vec![],
+            indexed_meshes_lod: vec![],
             indexed_meshes_temp: vec![],
+            indexed_meshes_original: vec![],
+            mesh_states: vec![],
+            selection_anchor: None,
 
-            settings: Settings::default(),
+            settings,
             camera: OrbitalCamera::default(),
+            camera_transition: None,
+            scene_center: Vector3::new(0.0f32, 0.0, 0.0),
+            scene_radius: 0.0,
+            scene_aabb: (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0)),
+
+            camera_url_state: None,
+            camera_url_idle_time: 0.0,
+            is_repaint_scheduled: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            pending_url_camera: None,
+
+            hover_pos: None,
+            hover_hit: None,
+
+            is_apply_confirm_open: false,
+            apply_confirm_next: None,
+            printable_reports: vec![],
+            inspect_reports: vec![],
+            is_inspect_open: false,
+            is_bindings_open: false,
 
             state: PanelState::default(),
+            simplifiers: vec![],
+            edit_history: vec![],
+
+            frames: vec![],
+            frame_idx: 0,
+            frame_time: 0.0,
+            is_frames_playing: false,
 
             receiver: None,
-        };
 
-        app.push_indexed_mesh(cc.gl.as_ref(), IndexedMesh::box3d(Vector3::new(1.0f32, 1.0, 1.0)));
-        app
+            session_receiver: Some(session_receiver),
+            stored_session: None,
+            is_session_dirty: false,
+            is_session_applied: false,
+            last_autosave_time: 0.0,
+
+            #[cfg(feature = "scripting")]
+            scripts: vec![],
+        }
+    }
+
+    // own mesh operation listed in Scripts menu, it may edit, add or remove meshes freely
+    #[cfg(feature = "scripting")]
+    pub fn register_script(&mut self, name: &str, script: impl FnMut(&mut Vec<IndexedMesh>) + 'static) {
+        self.scripts.push((name.to_string(), Box::new(script)));
+    }
+    #[cfg(feature = "scripting")]
+    fn run_script(&mut self, gl: &glow::Context, script_idx: usize) {
+        self.switch_to_selection_menu(gl);
+
+        let previous = self.indexed_meshes.clone();
+        (self.scripts[script_idx].1)(&mut self.indexed_meshes);
+
+        // script may have moved vertices without marking them, so everything derived is rebuilt
+        for mesh in self.indexed_meshes.iter_mut() {
+            let dropped_faces = mesh.validate();
+            if dropped_faces > 0 {
+                tracing::warn!("Script left {} faces with out of range indices, dropped", dropped_faces);
+            }
+            mesh.remove_unused_vertices();
+            mesh.normals_dirty = true;
+            mesh.ensure_normals();
+        }
+        self.indexed_meshes.retain(|mesh| !mesh.is_empty());
+
+        self.mesh_states.resize(self.indexed_meshes.len(), MeshState::default());
+        self.selection_anchor = None;
+        self.inspect_reports.clear();
+        self.update_lod_meshes(&previous);
+        self.settings.total_num_faces = self.indexed_meshes.iter().map(|mesh| mesh.indices.len() / 3).sum();
+        self.render_scene_ref.lock()
+            .reset_static_and_create_static_meshes(gl, &self.indexed_meshes, &self.indexed_meshes_lod);
+
+        self.recalculate_camera_view();
+        self.record_edit_history();
     }
 
+    // camera from url is applied now and once more after the first load reframes the scene
+    pub fn set_url_fragment(&mut self, fragment: &str) {
+        let camera_url = fragment
+            .trim_start_matches('#')
+            .split('&')
+            .find_map(|param| param.strip_prefix("camera="));
+
+        if let Some(camera_url) = camera_url {
+            if self.camera.set_from_url_string(camera_url) {
+                self.camera_url_state = Some((self.camera.state(), self.camera.fov));
+                self.pending_url_camera = Some(camera_url.to_string());
+            }
+        }
+    }
     pub fn reset_all(&mut self, gl: &glow::Context) {
         self.render_scene_ref.lock().reset_buffers(gl);
         self.indexed_meshes.clear();
+        self.indexed_meshes_lod.clear();
+        self.mesh_states.clear();
+        self.selection_anchor = None;
         self.settings.total_num_faces = 0;
+        self.edit_history.clear();
+        self.frames.clear();
+        self.is_frames_playing = false;
+        self.discard_original(gl);
+        self.inspect_reports.clear();
 
         self.switch_to_selection_menu(gl);
     }
+    pub fn set_frames(&mut self, gl: &glow::Context, mut frames: Vec<IndexedMesh>) {
+        self.reset_all(gl);
+        if frames.is_empty() { return; }
+
+        for frame in frames.iter_mut() {
+            frame.ensure_normals();
+        }
+        let first = std::mem::take(&mut frames[0]);
+        self.frames = frames;
+        self.frame_idx = 0;
+        self.frame_time = 0.0;
+        self.push_indexed_mesh(gl, first);
+    }
+    // operations that add or remove meshes end the animation
+    pub fn is_frames_shown(&self) -> bool {
+        self.frames.len() > 1 && self.indexed_meshes.len() == 1
+    }
+    // edits of the shown frame stay with it, swapped in mesh goes to dynamic buffers without lod
+    pub fn show_frame(&mut self, gl: &glow::Context, frame_idx: usize) {
+        if frame_idx == self.frame_idx || frame_idx >= self.frames.len() || !self.is_frames_shown() { return; }
+
+        std::mem::swap(&mut self.indexed_meshes[0], &mut self.frames[self.frame_idx]);
+        std::mem::swap(&mut self.indexed_meshes[0], &mut self.frames[frame_idx]);
+        self.frame_idx = frame_idx;
+
+        self.indexed_meshes_lod[0] = None;
+        self.settings.total_num_faces = self.indexed_meshes[0].indices.len() / 3;
+        // reports were about the previous frame
+        self.inspect_reports.clear();
+        self.render_scene_ref.lock().replace_static_mesh(gl, 0, &self.indexed_meshes[0]);
+    }
     pub fn switch_to_selection_menu(&mut self, gl: &glow::Context) {
         self.indexed_meshes_temp.clear();
         self.render_scene_ref.lock().reset_temp_buffers(gl);
         self.settings.total_num_faces_temp = 0;
+        self.settings.total_num_faces_rounds.clear();
+        self.simplifiers.clear();
+        self.is_apply_confirm_open = false;
+        self.apply_confirm_next = None;
 
         self.settings.is_render_static = true;
         self.settings.is_render_temp = false;
@@ -117,185 +604,987 @@ impl WebEditor {
         self.state = PanelState::SelectionMenu;
     }
     pub fn apply_temp_mehes(&mut self, gl: &glow::Context) {
-        self.indexed_meshes = self.indexed_meshes_temp.clone();
-        self.render_scene_ref.lock().reset_static_and_create_static_meshes(gl, &self.indexed_meshes);
+        // single snapshot, the previous one is replaced
+        let previous = std::mem::replace(&mut self.indexed_meshes, self.indexed_meshes_temp.clone());
+        self.update_lod_meshes(&previous);
+        if self.settings.is_keep_original {
+            self.indexed_meshes_original = previous;
+            self.render_scene_ref.lock().keep_static_as_original(gl);
+            self.settings.original_state.is_visible = false;
+        }
+        self.render_scene_ref.lock()
+            .reset_static_and_create_static_meshes(gl, &self.indexed_meshes, &self.indexed_meshes_lod);
         self.settings.total_num_faces = self.settings.total_num_faces_temp;
         self.settings.total_num_faces_temp = 0;
+        self.recompute_bounds_keep_view();
+        self.record_edit_history();
+        self.is_session_applied = true;
     }
-    pub fn clone_static_to_temp(&mut self, gl: &glow::Context) {
-        self.indexed_meshes_temp = self.indexed_meshes.clone();
-        self.render_scene_ref.lock().reset_temp_and_create_temp_meshes(gl, &self.indexed_meshes_temp);
-        self.settings.total_num_faces_temp = self.settings.total_num_faces;
+    pub fn discard_original(&mut self, gl: &glow::Context) {
+        self.indexed_meshes_original.clear();
+        self.render_scene_ref.lock().reset_original_buffers(gl);
     }
-    pub fn push_indexed_mesh(&mut self, gl: &glow::Context, mesh: IndexedMesh) {
-        self.render_scene_ref.lock().push_static_mesh(gl, &mesh);
-        self.indexed_meshes.push(mesh);
-        self.settings.total_num_faces += self.indexed_meshes.last().unwrap().indices.len() / 3;
+    fn record_edit_history(&mut self) {
+        let area = self.indexed_meshes.iter().map(|mesh| mesh.calculate_area()).sum();
+        self.edit_history.push((self.settings.total_num_faces, area));
+        self.is_session_dirty = true;
     }
-    pub fn recalculate_camera_view(&mut self) {
-        let mut center_point = Vector3::new(0.0f32, 0.0, 0.0);
-        let (mut min, mut max) = (
-            Vector3::new(std::f32::MAX, std::f32::MAX, std::f32::MAX),
-            Vector3::new(std::f32::MIN, std::f32::MIN, std::f32::MIN)
-        );
+    pub fn restore_session(&mut self, gl: &glow::Context, bytes: &[u8]) {
+        let scene = match Session::from_bytes(bytes) {
+            Ok(scene) => scene,
+            Err(err) => {
+                tracing::warn!("Stored session not restored: {}", err);
+                return;
+            }
+        };
 
-        for mesh in self.indexed_meshes.iter() {
-            center_point += mesh.calculate_center_point() / self.indexed_meshes.len() as f32;
+        self.reset_all(gl);
+        for mesh in scene.meshes {
+            self.push_indexed_mesh(gl, mesh);
+        }
+        self.mesh_states = scene.mesh_states;
+        if !scene.name.is_empty() {
+            self.settings.export_file_name = scene.name;
+        }
+        self.recalculate_camera_view();
+        // same as stored, nothing to save until next edit
+        self.is_session_dirty = false;
+    }
+    // waits for stored session, then saves when due, returns seconds until next save or None
+    fn autosave(&mut self, time: f64) -> Option<f32> {
+        // sessions live in IndexedDB, natively there is nowhere to save them
+        if !cfg!(target_arch = "wasm32") {
+            return None;
+        }
+        if let Some(receiver) = self.session_receiver.as_ref() {
+            match receiver.try_recv() {
+                Ok(bytes) => {
+                    self.stored_session = bytes.filter(|bytes| !bytes.is_empty());
+                    self.session_receiver = None;
+                }
+                Err(oneshot::TryRecvError::Disconnected) => self.session_receiver = None,
+                Err(oneshot::TryRecvError::Empty) => {}
+            }
+        }
+        if !self.settings.is_autosave || !self.is_session_dirty
+            || self.session_receiver.is_some() || self.stored_session.is_some()
+        {
+            return None;
+        }
 
-            let (min_local, max_local) = mesh.calculate_aabb();
-            min.x = min.x.min(min_local.x);
-            min.y = min.y.min(min_local.y);
-            min.z = min.z.min(min_local.z);
+        let interval = if self.is_session_applied { AUTOSAVE_MIN_INTERVAL } else { self.settings.autosave_interval as f64 };
+        let elapsed = time - self.last_autosave_time;
+        if elapsed < interval {
+            return Some((interval - elapsed) as f32);
+        }
 
-            max.x = max.x.max(max_local.x);
-            max.y = max.y.max(max_local.y);
-            max.z = max.z.max(max_local.z);
+        match Session::to_bytes(&self.settings.export_file_name, &self.indexed_meshes, &self.mesh_states) {
+            Ok(bytes) => Session::save(bytes),
+            Err(err) => tracing::warn!("Session not serialized: {}", err),
         }
+        self.last_autosave_time = time;
+        self.is_session_dirty = false;
+        self.is_session_applied = false;
 
-        self.camera.center = center_point;
+        None
+    }
+    // operation menus start from a copy of static meshes
+    fn enter_operation_menu(&mut self, gl: &glow::Context, state: PanelState) {
+        self.clone_static_to_temp(gl);
+        self.settings.is_render_static = false;
+        self.settings.is_render_temp = true;
 
-        let scene_dist = max - min;
-        let max_scene_dist_half = scene_dist.magnitude() / 2.0;
-        let tan_half = (self.camera.fov / 180.0 * std::f32::consts::PI).tan() / 2.0;
-        self.camera.dist = max_scene_dist_half / tan_half;
+        match state {
+            PanelState::RemeshMenu => {
+                self.settings.remesh_iterations = 0;
+            }
+            PanelState::SimplificationMenu => {
+                self.settings.simplification_error = 1.0;
+                self.settings.simplification_error_pending = 1.0;
+                if self.settings.is_simplification_clustering {
+                    self.cluster_temp_meshes(gl);
+                }
+            }
+            PanelState::SmoothMenu => {
+                self.smooth_temp_meshes(gl);
+            }
+            PanelState::TransformMenu => {
+                self.settings.transform_translate = [0.0; 3];
+                self.settings.transform_rotate = [0.0; 3];
+                self.settings.transform_scale = [1.0; 3];
+            }
+            PanelState::VoxelMenu => {
+                self.voxel_temp_meshes(gl);
+            }
+            PanelState::SelectionMenu => {}
+        }
+        self.state = state;
+    }
+    // chains operations, result of the current one becomes the base of the next one
+    fn apply_and_continue(&mut self, gl: &glow::Context, next: PanelState) {
+        self.apply_temp_mehes(gl);
+        self.switch_to_selection_menu(gl);
+        self.enter_operation_menu(gl, next);
+    }
+    fn continue_menu_button(ui: &mut egui::Ui, current: PanelState) -> Option<PanelState> {
+        let mut next = None;
+        ui.menu_button("Apply and continue", |ui| {
+            for (state, name) in [
+                (PanelState::RemeshMenu, "Remesh"),
+                (PanelState::SimplificationMenu, "Simplification"),
+                (PanelState::SmoothMenu, "Smooth"),
+                (PanelState::TransformMenu, "Transform"),
+                (PanelState::VoxelMenu, "Voxel remesh"),
+            ] {
+                if state != current && ui.button(name).clicked() {
+                    next = Some(state);
+                    ui.close_menu();
+                }
+            }
+        }).response.on_hover_text("Apply changes and start next operation on the result");
 
-        self.settings.scroll_sensitivity = max_scene_dist_half * 0.001;
+        next
     }
-}
+    // big reductions ask for confirmation before replacing the original
+    fn is_simplification_apply_guarded(&self) -> bool {
+        let removed_fraction = 1.0 - self.settings.total_num_faces_temp as f32
+            / self.settings.total_num_faces.max(1) as f32;
 
-impl eframe::App for WebEditor {
-    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
-        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
-            egui::menu::bar(ui, |ui| {
-                egui::widgets::global_dark_light_mode_switch(ui);
-                ui.menu_button("File", |ui| {
-                    if ui.button("Open").clicked() {
+        self.settings.is_simplification_apply_guard && removed_fraction > self.settings.simplification_apply_guard
+    }
+    pub fn clone_static_to_temp(&mut self, gl: &glow::Context) {
+        self.indexed_meshes_temp = self.indexed_meshes.clone();
+        self.render_scene_ref.lock().reset_temp_and_create_temp_meshes(gl, &self.indexed_meshes_temp);
+        self.settings.total_num_faces_temp = self.settings.total_num_faces;
+    }
+    // operations change only selected meshes, or all of them when nothing is selected
+    pub fn operation_targets(&self) -> Vec<bool> {
+        let is_any_selected = self.mesh_states.iter().any(|mesh_state| mesh_state.is_selected);
+        self.mesh_states.iter().map(|mesh_state| !is_any_selected || mesh_state.is_selected).collect()
+    }
+    // plain click selects only clicked mesh, ctrl toggles it, shift selects range from the anchor
+    pub fn click_mesh(&mut self, i: usize, modifiers: egui::Modifiers) {
+        match self.selection_anchor {
+            Some(anchor) if modifiers.shift && anchor < self.mesh_states.len() => {
+                let range = anchor.min(i)..=anchor.max(i);
+                for (j, mesh_state) in self.mesh_states.iter_mut().enumerate() {
+                    mesh_state.is_selected = range.contains(&j);
+                }
+            }
+            _ if modifiers.command => {
+                self.mesh_states[i].is_selected = !self.mesh_states[i].is_selected;
+                self.selection_anchor = Some(i);
+            }
+            _ => {
+                let is_only_selected = self.mesh_states
+                    .iter()
+                    .enumerate()
+                    .all(|(j, mesh_state)| mesh_state.is_selected == (j == i));
+                for (j, mesh_state) in self.mesh_states.iter_mut().enumerate() {
+                    mesh_state.is_selected = j == i && !is_only_selected;
+                }
+                self.selection_anchor = Some(i);
+            }
+        }
+    }
+    pub fn delete_selected_meshes(&mut self, gl: &glow::Context) {
+        let mut i = 0;
+        while i < self.mesh_states.len() {
+            if self.mesh_states[i].is_selected {
+                self.indexed_meshes.remove(i);
+                self.indexed_meshes_lod.remove(i);
+                self.mesh_states.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        self.selection_anchor = None;
+        self.settings.total_num_faces = self.indexed_meshes.iter().map(|mesh| mesh.indices.len() / 3).sum();
+        // reports are by mesh index, which shifted
+        self.inspect_reports.clear();
 
-                        let (sender, receiver) = oneshot::channel::<Vec<IndexedMesh>>();
-                        self.receiver = Some(receiver);
+        self.render_scene_ref.lock()
+            .reset_static_and_create_static_meshes(gl, &self.indexed_meshes, &self.indexed_meshes_lod);
+        self.record_edit_history();
+    }
+    // repairs targets in place for slicing, what was done is shown in report window
+    pub fn make_printable(&mut self, gl: &glow::Context) {
+        let targets = self.operation_targets();
 
-                        let task = rfd::AsyncFileDialog::new().pick_files();
-                        wasm_bindgen_futures::spawn_local(async {
-                            let files = task.await;
+        self.printable_reports.clear();
+        for (i, is_target) in targets.into_iter().enumerate() {
+            if !is_target { continue; }
 
-                            let mut loaded_indexed_meshes = vec![];
-                            if let Some(files) = files {
-                                for file in files {
-                                    let bytes = file.read();
+            let mesh = &mut self.indexed_meshes[i];
+            let report = Repair::make_printable(mesh);
+            mesh.ensure_normals();
 
-                                    let file_name = file.file_name();
-                                    let ext = std::path::Path::new(&file_name)
-                                        .extension()
-                                        .and_then(std::ffi::OsStr::to_str);
+            let name = if mesh.name.is_empty() { format!("mesh {}", i) } else { mesh.name.clone() };
+            self.printable_reports.push((name, report));
+            self.indexed_meshes_lod[i] = self.create_lod_mesh(&self.indexed_meshes[i]);
+        }
 
-                                    let bytes = std::io::Cursor::new(bytes.await);
+        self.settings.total_num_faces = self.indexed_meshes.iter().map(|mesh| mesh.indices.len() / 3).sum();
+        self.render_scene_ref.lock()
+            .reset_static_and_create_static_meshes(gl, &self.indexed_meshes, &self.indexed_meshes_lod);
+        self.record_edit_history();
+    }
+    // flip brush, works on static meshes, so only the hit one is uploaded again
+    pub fn flip_faces_at(&mut self, gl: &glow::Context, origin: Vector3<f32>, dir: Vector3<f32>) {
+        let mut closest: Option<(f32, usize, u32)> = None;
+        for (i, (mesh, mesh_state)) in self.indexed_meshes.iter_mut().zip(self.mesh_states.iter()).enumerate() {
+            if !mesh_state.is_visible { continue; }
 
-                                    if let Some(ext) = ext {
-                                        let mesh = Files::read_indexed_mesh(bytes, ext);
+            if let Some((t, face)) = mesh.ensure_aabb_tree().ray_intersect_face(origin, dir) {
+                if closest.map_or(true, |(closest, _, _)| t < closest) {
+                    closest = Some((t, i, face));
+                }
+            }
+        }
+        let (t, i, face) = match closest {
+            Some(closest) => closest,
+            None => return,
+        };
 
-                                        if let Ok(mesh) = mesh {
-                                            if !mesh.is_empty() {
-                                                loaded_indexed_meshes.push(mesh);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+        let mesh = &mut self.indexed_meshes[i];
+        Repair::flip_faces(mesh, face, origin + dir * t, self.settings.flip_brush_radius * self.scene_radius);
+        mesh.ensure_normals();
 
-                            let _err = sender.send(loaded_indexed_meshes);
-                        });
+        self.indexed_meshes_lod[i] = None;
+        self.render_scene_ref.lock().replace_static_mesh(gl, i, &self.indexed_meshes[i]);
+        self.record_edit_history();
+    }
+    // egui 0.18 has no delayed repaint, so a timer on web or a sleeping thread on native asks for it,
+    // delay counts from the end of this frame, so actual rate is a bit under the cap
+    fn request_repaint_after(&self, ctx: &egui::Context, delay: f32) {
+        if self.is_repaint_scheduled.swap(true, Ordering::Relaxed) { return; }
 
-                    }
-                    ui.menu_button("Save", |ui| {
-                        if ui.button("stl").clicked() {
-                            let mut stl_mesh = vec![];
-                            for mesh in self.indexed_meshes.iter() {
-                                for face_idxs in mesh.indices.windows(3).step_by(3) {
-                                    let v0 = mesh.positions[face_idxs[0] as usize];
-                                    let v1 = mesh.positions[face_idxs[1] as usize];
-                                    let v2 = mesh.positions[face_idxs[2] as usize];
+        let (scheduled_ctx, is_repaint_scheduled) = (ctx.clone(), self.is_repaint_scheduled.clone());
+        let repaint = move || {
+            is_repaint_scheduled.store(false, Ordering::Relaxed);
+            scheduled_ctx.request_repaint();
+        };
 
-                                    let face_normal = (v1 - v0).cross(v2 - v0);
+        if cfg!(target_arch = "wasm32") {
+            let callback = wasm_bindgen::closure::Closure::once_into_js(repaint);
+            let is_set = web_sys::window().map_or(false, |window| {
+                window
+                    .set_timeout_with_callback_and_timeout_and_arguments_0(callback.unchecked_ref(), (delay * 1000.0) as i32)
+                    .is_ok()
+            });
+            // without timer frames would stop for good
+            if !is_set {
+                self.is_repaint_scheduled.store(false, Ordering::Relaxed);
+                ctx.request_repaint();
+            }
+        } else {
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_secs_f32(delay));
+                repaint();
+            });
+        }
+    }
+    pub fn inspect_meshes(&mut self) {
+        self.inspect_reports = self.operation_targets()
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, is_target)| is_target)
+            .map(|(i, _)| (i, Repair::inspect(&self.indexed_meshes[i])))
+            .collect();
+    }
+    pub fn fix_mesh(&mut self, gl: &glow::Context, i: usize, fix: Fix) {
+        let mesh = &mut self.indexed_meshes[i];
+        let changed_cnt = Repair::apply_fix(mesh, fix);
+        mesh.ensure_normals();
+        tracing::info!("Fix changed {} elements", changed_cnt);
 
-                                    stl_mesh.push(
-                                        stl_io::Triangle {
-                                            normal: stl_io::Normal::new([face_normal.x, face_normal.y, face_normal.z]),
-                                            vertices:
-                                            [
-                                                stl_io::Vertex::new([v0.x, v0.y, v0.z]),
-                                                stl_io::Vertex::new([v1.x, v1.y, v1.z]),
-                                                stl_io::Vertex::new([v2.x, v2.y, v2.z]),
-                                            ]
-                                        }
-                                    );
-                                }
-                            }
+        self.indexed_meshes_lod[i] = self.create_lod_mesh(&self.indexed_meshes[i]);
+        self.settings.total_num_faces = self.indexed_meshes.iter().map(|mesh| mesh.indices.len() / 3).sum();
+        self.render_scene_ref.lock()
+            .reset_static_and_create_static_meshes(gl, &self.indexed_meshes, &self.indexed_meshes_lod);
+        self.record_edit_history();
 
-                            let mut binary_stl = Vec::<u8>::new();
-                            let write_result = stl_io::write_stl(&mut binary_stl, stl_mesh.iter());
-                            if !write_result.is_ok() {
-                                panic!("Error when create binary stl!");
-                            }
+        if let Some((_, report)) = self.inspect_reports.iter_mut().find(|(j, _)| *j == i) {
+            *report = Repair::inspect(&self.indexed_meshes[i]);
+        }
+    }
+    pub fn smooth_temp_meshes(&mut self, gl: &glow::Context) {
+        let iterations = self.settings.smooth_iterations as usize;
+        let lambda = self.settings.smooth_lambda;
+        let targets = self.operation_targets();
+        for ((mesh, new_mesh), is_target) in self.indexed_meshes.iter().zip(self.indexed_meshes_temp.iter_mut()).zip(targets) {
+            *new_mesh = mesh.clone();
+            if !is_target { continue; }
 
-                            let is_ok = Files::save_file_binary("file.stl", binary_stl);
-                            if !is_ok {
-                                panic!("Error when save stl file!");
-                            }
-                        }
-                        if ui.button("ply").clicked() {
-                            use ply_rs::ply::{
-                                Ply, DefaultElement, Encoding,
-                                ElementDef, PropertyDef, PropertyType,
-                                ScalarType, Property, Addable
-                            };
-                            use ply_rs::writer::Writer;
-                            let mut binary_ply = Vec::<u8>::new();
+            if self.settings.is_smooth_features {
+                let feature_angle = Some(self.settings.smooth_feature_angle);
+                Remesher::smooth_laplacian_preserving(new_mesh, iterations, lambda, feature_angle);
+            } else {
+                Remesher::smooth_laplacian(new_mesh, iterations, lambda);
+            }
+            new_mesh.ensure_normals();
+        }
 
-                            let mut ply = {
-                                let mut ply = Ply::<DefaultElement>::new();
-                                ply.header.encoding = Encoding::Ascii;
-                                ply.header.comments.push("ply export from Web Editor".to_string());
+        self.render_scene_ref.lock().reset_temp_and_create_temp_meshes(gl, &self.indexed_meshes_temp);
+    }
+    pub fn voxel_temp_meshes(&mut self, gl: &glow::Context) {
+        let targets = self.operation_targets();
+        for ((mesh, new_mesh), is_target) in self.indexed_meshes.iter().zip(self.indexed_meshes_temp.iter_mut()).zip(targets) {
+            *new_mesh = if is_target {
+                Remesher::voxel_remesh(mesh, self.settings.voxel_resolution)
+            } else {
+                mesh.clone()
+            };
+            new_mesh.ensure_normals();
+        }
+        self.settings.total_num_faces_temp = self.indexed_meshes_temp.iter().map(|mesh| mesh.indices.len() / 3).sum();
 
-                                let mut vertex_element = ElementDef::new("vertex".to_string());
-                                let v = PropertyDef::new("x".to_string(), PropertyType::Scalar(ScalarType::Float));
-                                vertex_element.properties.add(v);
-                                let v = PropertyDef::new("y".to_string(), PropertyType::Scalar(ScalarType::Float));
-                                vertex_element.properties.add(v);
-                                let v = PropertyDef::new("z".to_string(), PropertyType::Scalar(ScalarType::Float));
-                                vertex_element.properties.add(v);
-                                ply.header.elements.add(vertex_element);
+        self.render_scene_ref.lock().reset_temp_and_create_temp_meshes(gl, &self.indexed_meshes_temp);
+    }
+    // rotation and scale are around center of the changed meshes, so parts stay in place
+    pub fn transform_temp_meshes(&mut self, gl: &glow::Context) {
+        let targets = self.operation_targets();
 
-                                let mut face_element = ElementDef::new("face".to_string());
-                                let face_type = PropertyType::List(ScalarType::UChar, ScalarType::Int);
-                                let v = PropertyDef::new("vertex_indices".to_string(), face_type);
-                                face_element.properties.add(v);
-                                ply.header.elements.add(face_element);
+        let (mut min, mut max) = (
+            Vector3::new(std::f32::MAX, std::f32::MAX, std::f32::MAX),
+            Vector3::new(std::f32::MIN, std::f32::MIN, std::f32::MIN)
+        );
+        for (mesh, _) in self.indexed_meshes.iter().zip(targets.iter()).filter(|(_, &is_target)| is_target) {
+            if mesh.positions.is_empty() { continue; }
 
-                                let mut vertices = Vec::new();
-                                for mesh in self.indexed_meshes.iter() {
-                                    for v in mesh.positions.iter() {
+            let (mesh_min, mesh_max) = mesh.calculate_aabb();
+            min = Vector3::new(min.x.min(mesh_min.x), min.y.min(mesh_min.y), min.z.min(mesh_min.z));
+            max = Vector3::new(max.x.max(mesh_max.x), max.y.max(mesh_max.y), max.z.max(mesh_max.z));
+        }
+        let pivot = if min.x <= max.x { (min + max) / 2.0 } else { Vector3::new(0.0, 0.0, 0.0) };
 
-                                        let mut vertex = DefaultElement::new();
-                                        vertex.insert("x".to_string(), Property::Float(v.x));
-                                        vertex.insert("y".to_string(), Property::Float(v.y));
-                                        vertex.insert("z".to_string(), Property::Float(v.z));
+        let [tx, ty, tz] = self.settings.transform_translate;
+        let [rx, ry, rz] = self.settings.transform_rotate;
+        let [sx, sy, sz] = self.settings.transform_scale;
+        let matrix = Matrix4::from_translation(Vector3::new(tx, ty, tz) + pivot)
+            * Matrix4::from_angle_z(Deg(rz))
+            * Matrix4::from_angle_y(Deg(ry))
+            * Matrix4::from_angle_x(Deg(rx))
+            * Matrix4::from_nonuniform_scale(sx, sy, sz)
+            * Matrix4::from_translation(-pivot);
 
-                                        vertices.push(vertex);
-                                    }
-                                }
-                                ply.payload.insert("vertex".to_string(), vertices);
+        for ((mesh, new_mesh), is_target) in self.indexed_meshes.iter().zip(self.indexed_meshes_temp.iter_mut()).zip(targets) {
+            *new_mesh = mesh.clone();
+            if is_target {
+                new_mesh.transform(matrix);
+            }
+        }
 
-                                let mut indices = Vec::new();
-                                for mesh in self.indexed_meshes.iter() {
-                                    for face_idxs in mesh.indices.windows(3).step_by(3) {
+        self.render_scene_ref.lock().reset_temp_and_create_temp_meshes(gl, &self.indexed_meshes_temp);
+    }
+    pub fn cluster_temp_meshes(&mut self, gl: &glow::Context) {
+        self.simplifiers.clear();
+        self.settings.total_num_faces_rounds.clear();
+        self.settings.total_num_faces_temp = 0;
 
-                                        let mut index = DefaultElement::new();
-                                        index.insert(
+        let targets = self.operation_targets();
+        for ((mesh, new_mesh), is_target) in self.indexed_meshes.iter().zip(self.indexed_meshes_temp.iter_mut()).zip(targets) {
+            *new_mesh = if is_target {
+                Simplify::vertex_cluster(mesh, self.settings.simplification_cluster_resolution)
+            } else {
+                mesh.clone()
+            };
+            self.settings.total_num_faces_temp += new_mesh.indices.len() / 3;
+        }
+
+        self.render_scene_ref.lock().reset_temp_and_create_temp_meshes(gl, &self.indexed_meshes_temp);
+    }
+    pub fn push_indexed_mesh(&mut self, gl: &glow::Context, mut mesh: IndexedMesh) {
+        mesh.ensure_normals();
+        let mesh_lod = self.create_lod_mesh(&mesh);
+        self.render_scene_ref.lock().push_static_mesh(gl, &mesh, mesh_lod.as_ref());
+        self.indexed_meshes.push(mesh);
+        self.indexed_meshes_lod.push(mesh_lod);
+        self.mesh_states.push(MeshState::default());
+        self.settings.total_num_faces += self.indexed_meshes.last().unwrap().indices.len() / 3;
+        self.record_edit_history();
+    }
+    // all meshes become one, seams are welded so touching parts share vertices
+    pub fn merge_all_meshes(&mut self, gl: &glow::Context) {
+        if self.indexed_meshes.len() < 2 { return; }
+
+        let meshes: Vec<&IndexedMesh> = self.indexed_meshes.iter().collect();
+        let mut merged = IndexedMesh::merge(&meshes);
+        merged.name = "merged".to_string();
+        if self.settings.is_merge_weld {
+            let (min, max) = merged.calculate_aabb();
+            if merged.weld_vertices((max - min).magnitude() * simplification::WELD_EPS_RELATIVE) > 0 {
+                merged.occlusion.clear();
+            }
+        }
+
+        merged.ensure_normals();
+        let merged_lod = self.create_lod_mesh(&merged);
+        self.render_scene_ref.lock().reset_buffers(gl);
+        self.render_scene_ref.lock().push_static_mesh(gl, &merged, merged_lod.as_ref());
+
+        self.settings.total_num_faces = merged.indices.len() / 3;
+        self.indexed_meshes = vec![merged];
+        self.indexed_meshes_lod = vec![merged_lod];
+        self.mesh_states = vec![MeshState::default()];
+        self.inspect_reports.clear();
+        self.record_edit_history();
+    }
+    // pointer moved by delta in viewport pixels and is now at pos
+    pub fn orbit_camera(&mut self, pos: egui::Pos2, delta: egui::Vec2, rect: egui::Rect) {
+        if self.settings.is_trackball {
+            let to = Vector2::new(pos.x - rect.center().x, rect.center().y - pos.y);
+            let from = to - Vector2::new(delta.x, -delta.y);
+            self.camera.rotate_trackball(from, to);
+        } else {
+            let right = self.camera.up.cross(self.camera.dir_from_center).normalize();
+            self.camera.up = self.camera.dir_from_center.cross(right).normalize();
+
+            let r_xz = Matrix3::from_axis_angle(self.camera.up, Deg(-delta.x));
+            let r_yz = Matrix3::from_axis_angle(right, Deg(-delta.y));
+            self.camera.dir_from_center = r_yz * r_xz * self.camera.dir_from_center;
+        }
+    }
+    pub fn run_key_action(&mut self, action: KeyAction) {
+        match action {
+            KeyAction::PivotToCenter => self.camera.set_pivot(self.scene_center),
+            KeyAction::Fit => self.fit_camera_view(),
+            KeyAction::ResetView => self.reset_camera_view(),
+            KeyAction::Front => self.move_camera_to_preset(Vector3::unit_z(), Vector3::unit_y()),
+            KeyAction::Back => self.move_camera_to_preset(-Vector3::unit_z(), Vector3::unit_y()),
+            KeyAction::Right => self.move_camera_to_preset(Vector3::unit_x(), Vector3::unit_y()),
+            KeyAction::Left => self.move_camera_to_preset(-Vector3::unit_x(), Vector3::unit_y()),
+            KeyAction::Top => self.move_camera_to_preset(Vector3::unit_y(), -Vector3::unit_z()),
+            KeyAction::Bottom => self.move_camera_to_preset(-Vector3::unit_y(), Vector3::unit_z()),
+            KeyAction::Iso => self.move_camera_to_preset(Vector3::new(1.0f32, 1.0, 1.0), Vector3::unit_y()),
+            KeyAction::Wireframe => self.settings.is_render_wireframe = !self.settings.is_render_wireframe,
+            KeyAction::Points => self.settings.is_render_points = !self.settings.is_render_points,
+            KeyAction::FlatShading => self.settings.is_flat_shading = !self.settings.is_flat_shading,
+            KeyAction::Outline => self.settings.is_outline = !self.settings.is_outline,
+            KeyAction::Clipping => self.settings.is_clipping = !self.settings.is_clipping,
+        }
+    }
+    // moves center so the point under fingers at center depth follows them
+    pub fn pan_camera(&mut self, delta: egui::Vec2, rect: egui::Rect) {
+        if delta == egui::Vec2::ZERO || rect.height() <= 0.0 { return; }
+
+        let world_per_pixel = 2.0 * self.camera.dist * Deg(self.camera.fov / 2.0).tan() / rect.height();
+        let right = self.camera.up.cross(self.camera.dir_from_center).normalize();
+        let up = self.camera.dir_from_center.cross(right).normalize();
+        self.camera.center -= (right * delta.x - up * delta.y) * world_per_pixel;
+    }
+    // contours at clipping plane flattened to plane coordinates
+    pub fn section_svg(&self) -> Option<String> {
+        let normal = Vector3::from(self.settings.clip_normal);
+        let contours: Vec<Vec<Vector3<f32>>> = self.exported_meshes()
+            .iter()
+            .flat_map(|mesh| mesh.section_contours(normal, self.settings.clip_offset))
+            .collect();
+
+        Files::write_contours_svg(&contours, normal)
+    }
+    // full outline seen along the axis, unlike section it covers every part of the mesh
+    pub fn silhouette_svg(&self) -> Option<String> {
+        let normal = Vector3::from(self.settings.silhouette_normal);
+        let contours: Vec<Vec<Vector3<f32>>> = self.exported_meshes()
+            .iter()
+            .flat_map(|mesh| mesh.silhouette_contours(normal))
+            .collect();
+
+        Files::write_contours_svg(&contours, normal)
+    }
+    pub fn import_options(&self) -> ImportOptions {
+        ImportOptions {
+            stl_weld_eps: if self.settings.is_stl_weld { Some(self.settings.stl_weld_eps) } else { None },
+            is_robust_triangulation: self.settings.is_robust_triangulation,
+            is_flip_handedness: self.settings.is_flip_handedness,
+            is_load_as_frames: self.settings.is_load_as_frames,
+        }
+    }
+    // expensive, so only on demand, edits drop it again
+    pub fn bake_occlusion(&mut self, gl: &glow::Context) {
+        for mesh in self.indexed_meshes.iter_mut() {
+            mesh.ensure_normals();
+            mesh.occlusion = mesh.ambient_occlusion(self.settings.occlusion_rays);
+        }
+        self.render_scene_ref.lock()
+            .reset_static_and_create_static_meshes(gl, &self.indexed_meshes, &self.indexed_meshes_lod);
+    }
+    // stored normals from files can be wrong, so rebuild them from faces
+    pub fn recompute_normals(&mut self, gl: &glow::Context) {
+        for mesh in self.indexed_meshes.iter_mut().chain(self.indexed_meshes_lod.iter_mut().flatten()) {
+            mesh.recalculate_normals();
+        }
+        for mesh in self.indexed_meshes_temp.iter_mut() {
+            mesh.recalculate_normals();
+        }
+
+        let mut render_scene = self.render_scene_ref.lock();
+        render_scene.reset_static_and_create_static_meshes(gl, &self.indexed_meshes, &self.indexed_meshes_lod);
+        if !self.indexed_meshes_temp.is_empty() {
+            render_scene.reset_temp_and_create_temp_meshes(gl, &self.indexed_meshes_temp);
+        }
+    }
+    pub fn clear_occlusion(&mut self, gl: &glow::Context) {
+        for mesh in self.indexed_meshes.iter_mut() {
+            mesh.occlusion.clear();
+        }
+        self.render_scene_ref.lock()
+            .reset_static_and_create_static_meshes(gl, &self.indexed_meshes, &self.indexed_meshes_lod);
+    }
+    // heavy meshes get 10% copy to draw while camera moves
+    pub fn create_lod_mesh(&self, mesh: &IndexedMesh) -> Option<IndexedMesh> {
+        let faces_cnt = mesh.indices.len() / 3;
+        if faces_cnt < self.settings.lod_min_faces { return None; }
+
+        let mut simplify = Simplify::from(mesh);
+        simplify.simplify_mesh(faces_cnt / 10, self.settings.simplification_agr);
+
+        let mut mesh_lod = IndexedMesh::default();
+        simplify.to(&mut mesh_lod);
+        mesh_lod.ensure_normals();
+
+        Some(mesh_lod)
+    }
+    // lod of a mesh with the same geometry at the same place is kept, only changed ones are simplified again
+    fn update_lod_meshes(&mut self, previous: &[IndexedMesh]) {
+        let mut previous_lod = std::mem::take(&mut self.indexed_meshes_lod);
+        self.indexed_meshes_lod = self.indexed_meshes
+            .iter()
+            .enumerate()
+            .map(|(i, mesh)| match previous.get(i) {
+                Some(previous) if previous.positions == mesh.positions && previous.indices == mesh.indices => {
+                    previous_lod.get_mut(i).and_then(Option::take)
+                }
+                _ => self.create_lod_mesh(mesh),
+            })
+            .collect();
+    }
+    pub fn calculate_exported_largest_size(&self) -> f32 {
+        let (mut min, mut max) = (
+            Vector3::new(std::f32::MAX, std::f32::MAX, std::f32::MAX),
+            Vector3::new(std::f32::MIN, std::f32::MIN, std::f32::MIN)
+        );
+
+        for mesh in self.exported_meshes() {
+            let (min_local, max_local) = mesh.calculate_aabb();
+            min.x = min.x.min(min_local.x);
+            min.y = min.y.min(min_local.y);
+            min.z = min.z.min(min_local.z);
+
+            max.x = max.x.max(max_local.x);
+            max.y = max.y.max(max_local.y);
+            max.z = max.z.max(max_local.z);
+        }
+
+        let size = max - min;
+        size.x.max(size.y).max(size.z).max(0.0)
+    }
+    // copies for indexed formats, optionally welded so stl sourced meshes share vertices or faceted
+    pub fn prepare_indexed_export(&mut self) -> Vec<IndexedMesh> {
+        let mut meshes: Vec<IndexedMesh> = self.exported_meshes().into_iter().cloned().collect();
+
+        if self.settings.is_export_weld {
+            let vertices_before = meshes.iter().map(|mesh| mesh.positions.len()).sum();
+            for mesh in meshes.iter_mut() {
+                let (min, max) = mesh.calculate_aabb();
+                mesh.weld_vertices((max - min).magnitude() * simplification::WELD_EPS_RELATIVE);
+            }
+            let vertices_after = meshes.iter().map(|mesh| mesh.positions.len()).sum();
+
+            self.settings.export_weld_report = Some((vertices_before, vertices_after));
+        }
+        // edited meshes have stale or no normals until next upload, writers index them per vertex
+        for mesh in meshes.iter_mut() {
+            mesh.ensure_normals();
+        }
+        // after weld, which would merge the corners back
+        if self.settings.is_export_faceted {
+            for mesh in meshes.iter_mut() {
+                *mesh = mesh.to_faceted();
+            }
+        }
+
+        meshes
+    }
+    pub fn exported_meshes(&self) -> Vec<&IndexedMesh> {
+        self.indexed_meshes
+            .iter()
+            .zip(self.mesh_states.iter())
+            .filter(|(_, mesh_state)| match self.settings.export_filter {
+                ExportFilter::All => true,
+                ExportFilter::Visible => mesh_state.is_visible,
+                ExportFilter::Selected => mesh_state.is_selected,
+            })
+            .map(|(mesh, _)| mesh)
+            .collect()
+    }
+    // bounds of the static scene used for framing, pivot and clipping range
+    pub fn recalculate_scene_bounds(&mut self) {
+        let (mut min, mut max) = (
+            Vector3::new(std::f32::MAX, std::f32::MAX, std::f32::MAX),
+            Vector3::new(std::f32::MIN, std::f32::MIN, std::f32::MIN)
+        );
+        for mesh in self.indexed_meshes.iter() {
+            let (min_local, max_local) = mesh.calculate_aabb();
+            min.x = min.x.min(min_local.x);
+            min.y = min.y.min(min_local.y);
+            min.z = min.z.min(min_local.z);
+
+            max.x = max.x.max(max_local.x);
+            max.y = max.y.max(max_local.y);
+            max.z = max.z.max(max_local.z);
+        }
+        self.scene_aabb = if self.indexed_meshes.is_empty() {
+            (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0))
+        } else {
+            (min, max)
+        };
+
+        // merge per mesh bounding spheres into one enclosing the scene
+        let mut scene_sphere: Option<(Vector3<f32>, f32)> = None;
+        for mesh in self.indexed_meshes.iter() {
+            let (center, radius) = mesh.bounding_sphere();
+            scene_sphere = Some(match scene_sphere {
+                None => (center, radius),
+                Some((scene_center, scene_radius)) => {
+                    let dist = center.distance(scene_center);
+                    if dist + radius <= scene_radius {
+                        (scene_center, scene_radius)
+                    } else if dist + scene_radius <= radius {
+                        (center, radius)
+                    } else {
+                        let new_radius = (dist + radius + scene_radius) / 2.0;
+                        let new_center = scene_center + (center - scene_center) * ((new_radius - scene_radius) / dist);
+                        (new_center, new_radius)
+                    }
+                }
+            });
+        }
+
+        let (center_point, radius) = scene_sphere.unwrap_or((Vector3::new(0.0, 0.0, 0.0), 0.0));
+
+        self.scene_center = center_point;
+        self.scene_radius = radius;
+
+        self.settings.scroll_sensitivity = radius * 0.001;
+
+        self.camera.fit_clip_planes(radius);
+    }
+    // orbit around new scene center without moving the eye
+    pub fn recompute_bounds_keep_view(&mut self) {
+        self.recalculate_scene_bounds();
+        if !self.indexed_meshes.is_empty() {
+            self.camera.set_pivot(self.scene_center);
+        }
+    }
+    pub fn recalculate_camera_view(&mut self) {
+        self.recalculate_scene_bounds();
+
+        self.camera.center = self.scene_center;
+        self.camera.dist = self.camera.calculate_fit_dist(self.scene_radius);
+        self.settings.light_rig = render::LightRig::from_bounds(self.scene_center, self.scene_radius);
+
+        if let Some(camera_url) = self.pending_url_camera.take() {
+            self.camera.set_from_url_string(&camera_url);
+        }
+    }
+    pub fn move_camera_to(&mut self, target: CameraState) {
+        const TRANSITION_DURATION: f32 = 0.3;
+
+        if self.settings.is_camera_animated {
+            self.camera_transition = Some(CameraTransition::new(self.camera.state(), target, TRANSITION_DURATION));
+        } else {
+            self.camera.set_state(&target);
+            self.camera_transition = None;
+        }
+    }
+    pub fn move_camera_to_preset(&mut self, dir_from_center: Vector3<f32>, up: Vector3<f32>) {
+        let mut target = self.camera.state();
+        target.dir_from_center = dir_from_center.normalize();
+        target.up = up;
+        self.move_camera_to(target);
+    }
+    pub fn fit_camera_view(&mut self) {
+        let from = self.camera.state();
+        self.recalculate_camera_view();
+        let to = self.camera.state();
+
+        self.camera.set_state(&from);
+        self.move_camera_to(to);
+    }
+    // fit from the starting direction, for when the scene got lost after orbiting and scrolling
+    pub fn reset_camera_view(&mut self) {
+        let from = self.camera.state();
+        self.recalculate_camera_view();
+        let default_camera = OrbitalCamera::default();
+        let mut to = self.camera.state();
+        to.dir_from_center = default_camera.dir_from_center;
+        to.up = default_camera.up;
+
+        self.camera.set_state(&from);
+        self.move_camera_to(to);
+    }
+    // closest hit point among the currently rendered meshes
+    // spatial trees are built on the first pick after load or edit
+    pub fn pick(&mut self, origin: Vector3<f32>, dir: Vector3<f32>) -> Option<Vector3<f32>> {
+        let mut closest: Option<f32> = None;
+
+        let mut meshes = vec![];
+        if self.settings.is_render_static {
+            meshes.extend(self.indexed_meshes.iter_mut().zip(self.mesh_states.iter()));
+        }
+        if self.settings.is_render_temp {
+            meshes.extend(self.indexed_meshes_temp.iter_mut().zip(self.mesh_states.iter()));
+        }
+
+        for (mesh, mesh_state) in meshes {
+            if !mesh_state.is_visible { continue; }
+
+            if let Some(t) = mesh.ensure_aabb_tree().ray_intersect(origin, dir) {
+                if closest.map_or(true, |closest| t < closest) {
+                    closest = Some(t);
+                }
+            }
+        }
+
+        closest.map(|t| origin + dir * t)
+    }
+}
+
+impl eframe::App for WebEditor {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if render::IS_CONTEXT_LOST.swap(false, Ordering::Relaxed) {
+            tracing::warn!("WebGL context lost");
+            self.render_scene_ref.lock().invalidate();
+        }
+        if render::IS_CONTEXT_RESTORED.swap(false, Ordering::Relaxed) {
+            tracing::info!("WebGL context restored, reuploading meshes");
+            self.render_scene_ref.lock()
+                .recreate(
+                    frame.gl(),
+                    &self.indexed_meshes,
+                    &self.indexed_meshes_lod,
+                    &self.indexed_meshes_temp,
+                    &self.indexed_meshes_original
+                );
+        }
+
+        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                egui::widgets::global_dark_light_mode_switch(ui);
+                ui.menu_button("File", |ui| {
+                    if ui.button("Open").clicked() {
+
+                        let (sender, receiver) = oneshot::channel::<(String, Vec<IndexedMesh>, bool)>();
+                        self.receiver = Some(receiver);
+
+                        let import_options = self.import_options();
+                        let task = rfd::AsyncFileDialog::new().pick_files();
+                        wasm_bindgen_futures::spawn_local(async move {
+                            let files = task.await;
+
+                            let mut loaded_file_name = String::new();
+                            let mut loaded_files = vec![];
+                            if let Some(files) = files {
+                                for file in files {
+                                    let bytes = file.read();
+
+                                    let file_name = file.file_name();
+                                    if loaded_file_name.is_empty() {
+                                        loaded_file_name = Files::file_stem(&file_name);
+                                    }
+                                    let ext = std::path::Path::new(&file_name)
+                                        .extension()
+                                        .and_then(std::ffi::OsStr::to_str);
+
+                                    let bytes = bytes.await;
+                                    let format = Files::detect_format(&bytes, ext);
+                                    let bytes = std::io::Cursor::new(bytes);
+
+                                    match format {
+                                        Some(format) => {
+                                            let meshes = Files::read_indexed_meshes(bytes, format, import_options);
+
+                                            if let Ok(meshes) = meshes {
+                                                loaded_files.push((file_name, meshes));
+                                            }
+                                        }
+                                        None => {
+                                            tracing::warn!("Unknown format of `{}`", file_name);
+                                        }
+                                    }
+                                }
+                            }
+
+                            let is_frames = import_options.is_load_as_frames && loaded_files.len() > 1;
+                            let loaded_indexed_meshes = if is_frames {
+                                Files::frames_from_files(loaded_files)
+                            } else {
+                                loaded_files.into_iter().flat_map(|(_, meshes)| meshes).collect()
+                            };
+
+                            let _err = sender.send((loaded_file_name, loaded_indexed_meshes, is_frames));
+                        });
+
+                    }
+                    ui.checkbox(&mut self.settings.is_stl_weld, "weld stl on import")
+                        .on_hover_text("Merge repeated stl vertices so faces share edges");
+                    if self.settings.is_stl_weld {
+                        ui.add(egui::Slider::new(&mut self.settings.stl_weld_eps, 0.00000001..=0.01)
+                            .logarithmic(true)
+                            .text("tolerance"))
+                            .on_hover_text("Fraction of mesh size, lower keeps close vertices separate");
+                    }
+                    ui.checkbox(&mut self.settings.is_robust_triangulation, "robust triangulation")
+                        .on_hover_text("Ear clipping for every polygon, otherwise only for ones with more than 4 corners");
+                    ui.checkbox(&mut self.settings.is_flip_handedness, "flip handedness")
+                        .on_hover_text("Negate Z and fix winding, for models from left handed tools that load mirrored");
+                    ui.checkbox(&mut self.settings.is_load_as_frames, "load as frames")
+                        .on_hover_text("Several files become frames of an animation, ordered by file name");
+                    ui.menu_button("Save", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("name:");
+                            ui.text_edit_singleline(&mut self.settings.export_file_name);
+                        });
+                        ui.radio_value(&mut self.settings.export_filter, ExportFilter::All, "all meshes");
+                        ui.radio_value(&mut self.settings.export_filter, ExportFilter::Visible, "visible only");
+                        ui.radio_value(&mut self.settings.export_filter, ExportFilter::Selected, "selected only");
+                        ui.separator();
+
+                        // stl has no units and slicers read it as millimeters
+                        const STL_MIN_SIZE: f32 = 1.0;
+                        const STL_MAX_SIZE: f32 = 10_000.0;
+                        const STL_SUGGESTED_SIZE: f32 = 100.0;
+
+                        let largest_size = self.calculate_exported_largest_size();
+                        let scaled_size = largest_size * self.settings.stl_export_scale;
+                        if largest_size > 0.0 && !(STL_MIN_SIZE..=STL_MAX_SIZE).contains(&scaled_size) {
+                            ui.colored_label(
+                                egui::Color32::from_rgb(230, 160, 30),
+                                format!("stl will be {:.3} mm large", scaled_size)
+                            );
+                            if ui.button(format!("scale to {} mm", STL_SUGGESTED_SIZE)).clicked() {
+                                self.settings.stl_export_scale = STL_SUGGESTED_SIZE / largest_size;
+                            }
+                        }
+                        ui.horizontal(|ui| {
+                            ui.add(egui::DragValue::new(&mut self.settings.stl_export_scale).speed(0.01).clamp_range(0.000001..=1_000_000.0));
+                            ui.label("stl scale");
+                        });
+
+                        if ui.button("stl").clicked() {
+                            let scale = self.settings.stl_export_scale;
+
+                            let mut stl_mesh = vec![];
+                            for mesh in self.exported_meshes() {
+                                for face_idxs in mesh.faces() {
+                                    let v0 = mesh.positions[face_idxs[0] as usize] * scale;
+                                    let v1 = mesh.positions[face_idxs[1] as usize] * scale;
+                                    let v2 = mesh.positions[face_idxs[2] as usize] * scale;
+
+                                    let face_normal = (v1 - v0).cross(v2 - v0);
+
+                                    stl_mesh.push(
+                                        stl_io::Triangle {
+                                            normal: stl_io::Normal::new([face_normal.x, face_normal.y, face_normal.z]),
+                                            vertices:
+                                            [
+                                                stl_io::Vertex::new([v0.x, v0.y, v0.z]),
+                                                stl_io::Vertex::new([v1.x, v1.y, v1.z]),
+                                                stl_io::Vertex::new([v2.x, v2.y, v2.z]),
+                                            ]
+                                        }
+                                    );
+                                }
+                            }
+
+                            let mut binary_stl = Vec::<u8>::new();
+                            let write_result = stl_io::write_stl(&mut binary_stl, stl_mesh.iter());
+                            if !write_result.is_ok() {
+                                panic!("Error when create binary stl!");
+                            }
+
+                            let is_ok = Files::save_file_binary(&Files::export_file_name(&self.settings.export_file_name, "stl"), binary_stl);
+                            if !is_ok {
+                                panic!("Error when save stl file!");
+                            }
+                        }
+                        if ui.button("ply").clicked() {
+                            let meshes = self.prepare_indexed_export();
+
+                            use ply_rs::ply::{
+                                Ply, DefaultElement, Encoding,
+                                ElementDef, PropertyDef, PropertyType,
+                                ScalarType, Property, Addable
+                            };
+                            use ply_rs::writer::Writer;
+                            let mut binary_ply = Vec::<u8>::new();
+
+                            let mut ply = {
+                                let mut ply = Ply::<DefaultElement>::new();
+                                ply.header.encoding = Encoding::Ascii;
+                                ply.header.comments.push("ply export from Web Editor".to_string());
+
+                                let mut vertex_element = ElementDef::new("vertex".to_string());
+                                let v = PropertyDef::new("x".to_string(), PropertyType::Scalar(ScalarType::Float));
+                                vertex_element.properties.add(v);
+                                let v = PropertyDef::new("y".to_string(), PropertyType::Scalar(ScalarType::Float));
+                                vertex_element.properties.add(v);
+                                let v = PropertyDef::new("z".to_string(), PropertyType::Scalar(ScalarType::Float));
+                                vertex_element.properties.add(v);
+                                if self.settings.is_export_ply_normals {
+                                    let v = PropertyDef::new("nx".to_string(), PropertyType::Scalar(ScalarType::Float));
+                                    vertex_element.properties.add(v);
+                                    let v = PropertyDef::new("ny".to_string(), PropertyType::Scalar(ScalarType::Float));
+                                    vertex_element.properties.add(v);
+                                    let v = PropertyDef::new("nz".to_string(), PropertyType::Scalar(ScalarType::Float));
+                                    vertex_element.properties.add(v);
+                                }
+                                ply.header.elements.add(vertex_element);
+
+                                let mut face_element = ElementDef::new("face".to_string());
+                                let face_type = PropertyType::List(ScalarType::UChar, ScalarType::Int);
+                                let v = PropertyDef::new("vertex_indices".to_string(), face_type);
+                                face_element.properties.add(v);
+                                ply.header.elements.add(face_element);
+
+                                let mut vertices = Vec::new();
+                                for mesh in meshes.iter() {
+                                    for (i, v) in mesh.positions.iter().enumerate() {
+
+                                        let mut vertex = DefaultElement::new();
+                                        vertex.insert("x".to_string(), Property::Float(v.x));
+                                        vertex.insert("y".to_string(), Property::Float(v.y));
+                                        vertex.insert("z".to_string(), Property::Float(v.z));
+                                        if self.settings.is_export_ply_normals {
+                                            let n = mesh.normals[i];
+                                            vertex.insert("nx".to_string(), Property::Float(n.x));
+                                            vertex.insert("ny".to_string(), Property::Float(n.y));
+                                            vertex.insert("nz".to_string(), Property::Float(n.z));
+                                        }
+
+                                        vertices.push(vertex);
+                                    }
+                                }
+                                ply.payload.insert("vertex".to_string(), vertices);
+
+                                // all meshes share single vertex list, so indices are shifted
+                                let mut indices = Vec::new();
+                                let mut vertices_offset = 0;
+                                for mesh in meshes.iter() {
+                                    for face_idxs in mesh.faces() {
+
+                                        let mut index = DefaultElement::new();
+                                        index.insert(
                                             "vertex_indices".to_string(),
-                                            Property::ListInt([face_idxs[0] as i32, face_idxs[1] as i32, face_idxs[2] as i32].into())
+                                            Property::ListInt(
+                                                face_idxs.iter().map(|&idx| (idx + vertices_offset) as i32).collect()
+                                            )
                                         );
                                         indices.push(index);
                                     }
+                                    vertices_offset += mesh.positions.len() as u32;
                                 }
                                 ply.payload.insert("face".to_string(), indices);
 
@@ -307,37 +1596,537 @@ impl eframe::App for WebEditor {
                                 panic!("Error when create binary ply!");
                             }
 
-                            let is_ok = Files::save_file_binary("file.ply", binary_ply);
+                            let is_ok = Files::save_file_binary(&Files::export_file_name(&self.settings.export_file_name, "ply"), binary_ply);
                             if !is_ok {
                                 panic!("Error when save ply file!");
                             }
-                        }
-                    });
-                    if ui.button("Reset").clicked() {
-                        self.reset_all(frame.gl());
+                        }
+                        if ui.button("glb").on_hover_text("Binary glTF with normals").clicked() {
+                            let meshes = self.prepare_indexed_export();
+                            let glb = Gltf::write_glb(&meshes);
+
+                            let is_ok = Files::save_file_binary(&Files::export_file_name(&self.settings.export_file_name, "glb"), glb);
+                            if !is_ok {
+                                panic!("Error when save glb file!");
+                            }
+                        }
+                        if ui.button("obj").on_hover_text("Positions, normals and faces of all meshes").clicked() {
+                            let meshes = self.prepare_indexed_export();
+                            let obj = Files::write_obj(&meshes);
+
+                            let is_ok = Files::save_file_binary(&Files::export_file_name(&self.settings.export_file_name, "obj"), obj.into_bytes());
+                            if !is_ok {
+                                panic!("Error when save obj file!");
+                            }
+                        }
+                        if ui.button("obj + mtl")
+                            .on_hover_text("Zip of obj and mtl with a material for every face color, uncolored meshes get fill color")
+                            .clicked()
+                        {
+                            let meshes = self.prepare_indexed_export();
+                            // mtllib ends at whitespace in many readers
+                            let stem = Files::file_stem(self.settings.export_file_name.trim()).replace(' ', "_");
+                            let mtl_file_name = format!("{}.mtl", stem);
+                            let (obj, mtl) = Files::write_obj_with_mtl(&meshes, &mtl_file_name, self.settings.fill_color);
+
+                            let zip = Files::write_zip(&[
+                                (format!("{}.obj", stem), obj.into_bytes()),
+                                (mtl_file_name, mtl.into_bytes()),
+                            ]);
+                            let zip = match zip {
+                                Ok(zip) => zip,
+                                Err(err) => panic!("Error when create zip: {}", err),
+                            };
+
+                            let is_ok = Files::save_file_binary(&Files::export_file_name(&self.settings.export_file_name, "zip"), zip);
+                            if !is_ok {
+                                panic!("Error when save zip file!");
+                            }
+                        }
+                        if ui.button("Export connectivity")
+                            .on_hover_text("Json with vertex and face neighbours, indices match ply and glb exports")
+                            .clicked()
+                        {
+                            let meshes = self.prepare_indexed_export();
+                            let connectivity = Files::write_connectivity(&meshes);
+
+                            let is_ok = Files::save_file_binary(
+                                &Files::export_file_name(&self.settings.export_file_name, "json"),
+                                connectivity.into_bytes()
+                            );
+                            if !is_ok {
+                                panic!("Error when save json file!");
+                            }
+                        }
+                        ui.horizontal(|ui| {
+                            if ui.button("outline svg").on_hover_text("Outline of the meshes seen along the axis, for 2d templates").clicked() {
+                                match self.silhouette_svg() {
+                                    Some(svg) => {
+                                        let file_name = format!("{}_outline", self.settings.export_file_name.trim());
+                                        Files::save_file_binary(&Files::export_file_name(&file_name, "svg"), svg.into_bytes());
+                                    }
+                                    None => tracing::warn!("No outline, nothing to export"),
+                                }
+                            }
+                            let axes = [("X", [1.0, 0.0, 0.0]), ("Y", [0.0, 1.0, 0.0]), ("Z", [0.0, 0.0, 1.0])];
+                            for (axis_name, axis) in axes {
+                                ui.selectable_value(&mut self.settings.silhouette_normal, axis, axis_name);
+                            }
+                        });
+                        ui.checkbox(&mut self.settings.is_export_ply_normals, "ply normals");
+                        ui.checkbox(&mut self.settings.is_export_weld, "optimize before export (weld)")
+                            .on_hover_text("Merge duplicate vertices, makes indexed formats much smaller");
+                        if let Some((vertices_before, vertices_after)) = self.settings.export_weld_report {
+                            ui.label(format!("vertices: {} -> {}", vertices_before, vertices_after));
+                        }
+                        ui.checkbox(&mut self.settings.is_export_faceted, "export faceted")
+                            .on_hover_text("Separate vertices with face normals for every face, keeps flat shading in ply and glb");
+                    });
+                    if ui.button("Reset").clicked() {
+                        self.reset_all(frame.gl());
+                    }
+                });
+                ui.menu_button("Add Primitive", |ui| {
+                    ui.menu_button("Box", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("size");
+                            for (size, axis) in self.settings.box_size.iter_mut().zip(["x: ", "y: ", "z: "]) {
+                                ui.add(egui::DragValue::new(size).speed(0.1).clamp_range(0.001..=1_000_000.0).prefix(axis));
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("center");
+                            for (coord, axis) in self.settings.box_center.iter_mut().zip(["x: ", "y: ", "z: "]) {
+                                ui.add(egui::DragValue::new(coord).speed(0.1).prefix(axis));
+                            }
+                        });
+                        if ui.button("Add").clicked() {
+                            let box3d = IndexedMesh::box3d_at(
+                                Vector3::from(self.settings.box_size),
+                                Vector3::from(self.settings.box_center)
+                            );
+                            self.push_indexed_mesh(frame.gl(), box3d);
+                            self.recalculate_camera_view();
+                            ui.close_menu();
+                        }
+                    });
+                });
+                #[cfg(feature = "scripting")]
+                if !self.scripts.is_empty() {
+                    ui.menu_button("Scripts", |ui| {
+                        let mut clicked = None;
+                        for (i, (name, _)) in self.scripts.iter().enumerate() {
+                            if ui.button(name).clicked() {
+                                clicked = Some(i);
+                            }
+                        }
+                        if let Some(i) = clicked {
+                            self.run_script(frame.gl(), i);
+                            ui.close_menu();
+                        }
+                    });
+                }
+                ui.menu_button("View", |ui| {
+                    for (action, name) in [
+                        (KeyAction::Front, "Front"),
+                        (KeyAction::Back, "Back"),
+                        (KeyAction::Right, "Right"),
+                        (KeyAction::Left, "Left"),
+                        (KeyAction::Top, "Top"),
+                        (KeyAction::Bottom, "Bottom"),
+                        (KeyAction::Iso, "Iso"),
+                    ] {
+                        if ui.button(name).clicked() {
+                            self.run_key_action(action);
+                            ui.close_menu();
+                        }
+                    }
+                    ui.separator();
+                    if ui.button("Fit").clicked() {
+                        self.run_key_action(KeyAction::Fit);
+                        ui.close_menu();
+                    }
+                    if ui.button("Reset view").on_hover_text("Fit the scene from the starting direction").clicked() {
+                        self.run_key_action(KeyAction::ResetView);
+                        ui.close_menu();
+                    }
+                    if ui.button("Recompute bounds").on_hover_text("Update orbit center after editing").clicked() {
+                        self.recompute_bounds_keep_view();
+                        ui.close_menu();
+                    }
+                    if ui.button("Controls…").on_hover_text("Mouse buttons and keys for navigation and views").clicked() {
+                        self.is_bindings_open = true;
+                        ui.close_menu();
+                    }
+                    ui.checkbox(&mut self.settings.is_camera_animated, "animate transitions");
+                    ui.checkbox(&mut self.settings.is_trackball, "trackball rotation")
+                        .on_hover_text("Rotate as if dragging a ball, otherwise orbit around camera axes");
+                    ui.checkbox(&mut self.settings.is_continuous_rendering, "continuous rendering")
+                        .on_hover_text("Redraw every frame, otherwise only on input and animations");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.settings.is_autosave, "autosave")
+                            .on_hover_text("Keep the scene in browser storage, so it can be restored after the tab crashes");
+                        ui.add_enabled(
+                            self.settings.is_autosave,
+                            egui::DragValue::new(&mut self.settings.autosave_interval).clamp_range(10.0..=600.0).suffix(" s")
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.settings.is_fps_capped, "cap framerate")
+                            .on_hover_text("Wait between frames to save power, input is still handled at once");
+                        ui.add_enabled(
+                            self.settings.is_fps_capped,
+                            egui::DragValue::new(&mut self.settings.max_fps).clamp_range(1..=240).suffix(" fps")
+                        );
+                    });
+                    ui.checkbox(&mut self.settings.is_interaction_lod, "coarse while moving")
+                        .on_hover_text("Draw simplified copy of heavy meshes while camera moves");
+                    ui.add(egui::DragValue::new(&mut self.settings.lod_min_faces)
+                        .clamp_range(10_000..=100_000_000)
+                        .speed(1000)
+                        .prefix("coarse copy above faces: "))
+                        .on_hover_text("Applies to meshes loaded or edited after change");
+                    ui.checkbox(&mut self.settings.is_stats_graph, "statistics graph")
+                        .on_hover_text("Faces and surface area after every load and applied operation");
+                    ui.separator();
+                    egui::ComboBox::from_label("units")
+                        .selected_text(self.settings.scene_units.suffix())
+                        .show_ui(ui, |ui| {
+                            for units in [SceneUnits::Millimeters, SceneUnits::Centimeters, SceneUnits::Meters, SceneUnits::Inches] {
+                                ui.selectable_value(&mut self.settings.scene_units, units, units.suffix());
+                            }
+                        })
+                        .response
+                        .on_hover_text("What one unit of mesh coordinates stands for");
+                    ui.checkbox(&mut self.settings.is_scene_size, "scene size")
+                        .on_hover_text("Width, height and depth of the scene bounds over the viewport");
+                    ui.horizontal(|ui| {
+                        ui.color_edit_button_rgb(&mut self.settings.reference_color);
+                        ui.checkbox(&mut self.settings.is_reference_cube, "reference cube")
+                            .on_hover_text("Cube of known size beside the scene, for checking scale");
+                    });
+                    if self.settings.is_reference_cube {
+                        let speed = self.settings.reference_size * 0.01;
+                        ui.add(egui::DragValue::new(&mut self.settings.reference_size)
+                            .speed(speed)
+                            .clamp_range(0.0001..=1_000_000.0)
+                            .prefix("cube size: ")
+                            .suffix(format!(" {}", self.settings.scene_units.suffix())));
+                    }
+                });
+            });
+        });
+
+        Files::check_dropped_files_then_preview_load(ctx, frame.gl(), self);
+        if let Some(receiver) = self.receiver.as_ref() {
+            match receiver.try_recv() {
+                Ok((loaded_file_name, loaded_indexed_meshes, is_frames)) => {
+                    if is_frames {
+                        self.set_frames(frame.gl(), loaded_indexed_meshes);
+                    } else {
+                        self.reset_all(frame.gl());
+                        for indexed_mesh in loaded_indexed_meshes {
+                            self.push_indexed_mesh(frame.gl(), indexed_mesh);
+                        }
+                    }
+                    if !loaded_file_name.is_empty() {
+                        self.settings.export_file_name = loaded_file_name;
+                    }
+
+                    self.recalculate_camera_view();
+                    self.receiver = None;
+                }
+                Err(oneshot::TryRecvError::Disconnected) => {
+                    self.receiver = None;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(delay) = self.autosave(ctx.input().time) {
+            // without input no frame would come to save
+            self.request_repaint_after(ctx, delay);
+        }
+        if let Some(bytes) = self.stored_session.as_ref() {
+            let mut is_restore = None;
+            egui::Window::new("Restore session")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label(format!("Scene autosaved by the previous page is kept ({:.1} MB compressed).", bytes.len() as f32 / 1e6));
+                    ui.horizontal(|ui| {
+                        if ui.button("Restore").clicked() {
+                            is_restore = Some(true);
+                        }
+                        if ui.button("Discard").on_hover_text("Delete it, autosave starts over").clicked() {
+                            is_restore = Some(false);
+                        }
+                    });
+                });
+
+            if let Some(is_restore) = is_restore {
+                let bytes = self.stored_session.take().unwrap();
+                if is_restore {
+                    self.restore_session(frame.gl(), &bytes);
+                } else {
+                    Session::clear();
+                }
+            }
+        }
+
+        if self.settings.is_stats_graph {
+            use egui::plot::{Line, Plot, Value, Values};
+
+            let faces = Values::from_values_iter(self.edit_history
+                .iter()
+                .enumerate()
+                .map(|(step, &(faces_cnt, _))| Value::new(step as f64, faces_cnt as f64)));
+            let area = Values::from_values_iter(self.edit_history
+                .iter()
+                .enumerate()
+                .map(|(step, &(_, area))| Value::new(step as f64, area)));
+
+            // counts and areas differ by orders of magnitude, so each gets own plot
+            egui::Window::new("Statistics")
+                .open(&mut self.settings.is_stats_graph)
+                .default_width(300.0)
+                .show(ctx, |ui| {
+                    ui.label("faces");
+                    Plot::new("faces_history")
+                        .height(100.0)
+                        .include_y(0.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .show(ui, |plot_ui| plot_ui.line(Line::new(faces)));
+                    ui.label("surface area");
+                    Plot::new("area_history")
+                        .height(100.0)
+                        .include_y(0.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .show(ui, |plot_ui| plot_ui.line(Line::new(area)));
+                });
+        }
+
+        let shader_error = self.render_scene_ref.lock().shader_error().map(|err| err.to_string());
+        if let Some(shader_error) = shader_error {
+            egui::Window::new("Shader error")
+                .collapsible(false)
+                .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+                .show(ctx, |ui| {
+                    ui.label("Shaders failed to build, so the scene can't be drawn properly.");
+                    egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                        ui.monospace(&shader_error);
+                    });
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Retry").clicked() {
+                            self.render_scene_ref.lock().retry_shaders(frame.gl());
+                        }
+                        if ui.button("Use basic shader").on_hover_text("Flat colors without lighting").clicked() {
+                            self.render_scene_ref.lock().use_basic_shader(frame.gl());
+                        }
+                    });
+                });
+        }
+
+        if self.is_apply_confirm_open {
+            egui::Window::new("Apply simplification?")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+                .show(ctx, |ui| {
+                    let (faces_before, faces_after) = (self.settings.total_num_faces, self.settings.total_num_faces_temp);
+                    let removed_percent = 100.0 - faces_after as f32 / faces_before.max(1) as f32 * 100.0;
+                    ui.label(&format!("{:.1}% of faces will be removed", removed_percent));
+                    ui.label(&format!("faces before: {}", faces_before));
+                    ui.label(&format!("faces after: {}", faces_after));
+                    ui.label("The original mesh is replaced.");
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Apply").clicked() {
+                            self.is_apply_confirm_open = false;
+                            match self.apply_confirm_next.take() {
+                                Some(next) => self.apply_and_continue(frame.gl(), next),
+                                None => {
+                                    self.apply_temp_mehes(frame.gl());
+                                    self.switch_to_selection_menu(frame.gl());
+                                }
+                            }
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.is_apply_confirm_open = false;
+                            self.apply_confirm_next = None;
+                        }
+                    });
+                });
+        }
+
+        if !self.printable_reports.is_empty() {
+            egui::Window::new("Make printable")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+                .show(ctx, |ui| {
+                    egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                        for (name, report) in self.printable_reports.iter() {
+                            let (before, after) = (report.before, report.after);
+                            ui.strong(name);
+                            ui.label(match (after.is_watertight(), after.is_manifold()) {
+                                (true, true) => "watertight and manifold, ready for slicing",
+                                (false, true) => "manifold, but not watertight",
+                                (true, false) => "watertight, but not manifold",
+                                (false, false) => "neither watertight nor manifold, needs manual cleanup",
+                            });
+                            ui.label(format!(
+                                "welded vertices: {}, degenerate faces: {}, unused vertices: {}, flipped faces: {}, filled holes: {}",
+                                report.welded_cnt, report.degenerate_cnt, report.unused_cnt, report.flipped_faces_cnt,
+                                report.filled_holes_cnt
+                            ));
+                            egui::Grid::new(name).striped(true).show(ui, |ui| {
+                                ui.label("");
+                                ui.label("before");
+                                ui.label("after");
+                                ui.end_row();
+                                for (label, before, after) in [
+                                    ("faces", before.faces_cnt, after.faces_cnt),
+                                    ("border edges", before.border_edges_cnt, after.border_edges_cnt),
+                                    ("non manifold edges", before.non_manifold_edges_cnt, after.non_manifold_edges_cnt),
+                                    ("flipped edges", before.flipped_edges_cnt, after.flipped_edges_cnt),
+                                ] {
+                                    ui.label(label);
+                                    ui.label(before.to_string());
+                                    ui.label(after.to_string());
+                                    ui.end_row();
+                                }
+                            });
+                            ui.separator();
+                        }
+                    });
+                    if ui.button("Close").clicked() {
+                        self.printable_reports.clear();
+                    }
+                });
+        }
+
+        let mut is_inspect_open = self.is_inspect_open;
+        egui::Window::new("Inspect")
+            .open(&mut is_inspect_open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if self.inspect_reports.is_empty() {
+                    ui.label("Nothing to inspect");
+                }
+
+                let mut fix = None;
+                let mut is_voxel = false;
+                egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                    for &(i, report) in self.inspect_reports.iter() {
+                        let mesh = &self.indexed_meshes[i];
+                        let name = if mesh.name.is_empty() { format!("mesh {}", i) } else { mesh.name.clone() };
+                        ui.strong(&name);
+                        ui.label(match (report.stats.is_watertight(), report.stats.is_manifold()) {
+                            (true, true) => "watertight and manifold",
+                            (false, true) => "manifold, but not watertight",
+                            (true, false) => "watertight, but not manifold",
+                            (false, false) => "neither watertight nor manifold",
+                        });
+
+                        egui::Grid::new(("inspect", i)).striped(true).show(ui, |ui| {
+                            for (label, cnt, row_fix) in [
+                                ("degenerate faces", report.degenerate_faces_cnt, Fix::RemoveDegenerate),
+                                ("duplicate faces", report.duplicate_faces_cnt, Fix::RemoveDuplicateFaces),
+                                ("duplicate vertices", report.duplicate_vertices_cnt, Fix::Weld),
+                                ("inverted faces", report.stats.flipped_edges_cnt, Fix::UnifyWinding),
+                                ("holes", report.boundary_loops_cnt, Fix::FillHoles),
+                                ("isolated vertices", report.isolated_vertices_cnt, Fix::RemoveUnusedVertices),
+                            ] {
+                                ui.label(label);
+                                ui.label(cnt.to_string());
+                                if ui.add_enabled(cnt > 0, egui::Button::new("Fix")).clicked() {
+                                    fix = Some((i, row_fix));
+                                }
+                                ui.end_row();
+                            }
+
+                            // no local fix, rebuilding the surface is the way out
+                            ui.label("non manifold edges");
+                            ui.label(report.stats.non_manifold_edges_cnt.to_string());
+                            if ui.add_enabled(report.stats.non_manifold_edges_cnt > 0, egui::Button::new("Voxel remesh")).clicked() {
+                                is_voxel = true;
+                            }
+                            ui.end_row();
+                        });
+                        ui.separator();
                     }
                 });
+
+                if ui.button("Refresh").on_hover_text("Check again after other edits").clicked() {
+                    self.inspect_meshes();
+                }
+                if let Some((i, fix)) = fix {
+                    self.fix_mesh(frame.gl(), i, fix);
+                }
+                if is_voxel && self.state == PanelState::SelectionMenu {
+                    self.enter_operation_menu(frame.gl(), PanelState::VoxelMenu);
+                }
             });
-        });
+        self.is_inspect_open = is_inspect_open;
 
-        Files::check_dropped_files_then_preview_load(ctx, frame.gl(), self);
-        if let Some(receiver) = self.receiver.as_ref() {
-            match receiver.try_recv() {
-                Ok(loaded_indexed_meshes) => {
-                    self.reset_all(frame.gl());
-                    for indexed_mesh in loaded_indexed_meshes {
-                        self.push_indexed_mesh(frame.gl(), indexed_mesh);
+        let mut is_bindings_open = self.is_bindings_open;
+        egui::Window::new("Controls")
+            .open(&mut is_bindings_open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let bindings = &mut self.settings.bindings;
+                egui::Grid::new("bindings_buttons").show(ui, |ui| {
+                    for (label, button) in [
+                        ("orbit", &mut bindings.orbit_button),
+                        ("pan", &mut bindings.pan_button),
+                        ("zoom", &mut bindings.zoom_button),
+                    ] {
+                        ui.label(label);
+                        egui::ComboBox::from_id_source(label)
+                            .selected_text(InputBindings::button_name(*button))
+                            .show_ui(ui, |ui| {
+                                for option in [
+                                    Some(egui::PointerButton::Primary),
+                                    Some(egui::PointerButton::Secondary),
+                                    Some(egui::PointerButton::Middle),
+                                    None,
+                                ] {
+                                    ui.selectable_value(button, option, InputBindings::button_name(option));
+                                }
+                            });
+                        ui.end_row();
+                    }
+                });
+                ui.label("Wheel always zooms, touch navigation is fixed");
+                ui.separator();
+
+                egui::Grid::new("bindings_keys").show(ui, |ui| {
+                    for (action, key) in bindings.keys.iter_mut() {
+                        ui.label(action.name());
+                        egui::ComboBox::from_id_source(action.name())
+                            .selected_text(InputBindings::key_name(*key))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(key, None, InputBindings::key_name(None));
+                                for &option in InputBindings::bindable_keys() {
+                                    ui.selectable_value(key, Some(option), InputBindings::key_name(Some(option)));
+                                }
+                            });
+                        ui.end_row();
                     }
+                });
+                ui.separator();
 
-                    self.recalculate_camera_view();
-                    self.receiver = None;
-                }
-                Err(oneshot::TryRecvError::Disconnected) => {
-                    self.receiver = None;
+                if ui.button("Reset to defaults").clicked() {
+                    *bindings = InputBindings::default();
                 }
-                _ => {}
-            }
-        }
+            });
+        self.is_bindings_open = is_bindings_open;
 
         egui::SidePanel::left("side_panel").resizable(false).show(ctx, |ui| {
             ui.heading("Side Panel");
@@ -347,45 +2136,256 @@ impl eframe::App for WebEditor {
                 PanelState::SelectionMenu => {
                     ui.with_layout(egui::Layout::top_down(egui::Align::Center).with_cross_justify(true), |ui| {
                         if ui.button("Remesh").on_hover_text("Remesh operation").clicked() {
-                            self.clone_static_to_temp(frame.gl());
-                            self.settings.is_render_static = false;
-                            self.settings.is_render_temp = true;
-                            self.settings.remesh_iterations = 0;
-                            self.state = PanelState::RemeshMenu;
+                            self.enter_operation_menu(frame.gl(), PanelState::RemeshMenu);
                         }
                         if ui.button("Simplification").on_hover_text("Decimation operation").clicked() {
-                            self.clone_static_to_temp(frame.gl());
-                            self.settings.is_render_static = false;
-                            self.settings.is_render_temp = true;
-                            self.settings.simplification_error = 1.0;
-                            self.state = PanelState::SimplificationMenu;
+                            self.enter_operation_menu(frame.gl(), PanelState::SimplificationMenu);
+                        }
+                        if ui.button("Smooth").on_hover_text("Laplacian smoothing").clicked() {
+                            self.enter_operation_menu(frame.gl(), PanelState::SmoothMenu);
+                        }
+                        if ui.button("Transform").on_hover_text("Move, rotate and scale by typed values").clicked() {
+                            self.enter_operation_menu(frame.gl(), PanelState::TransformMenu);
+                        }
+                        if ui.button("Voxel remesh")
+                            .on_hover_text("Rebuild closed surface from a voxel grid, for scans with holes and self intersections")
+                            .clicked()
+                        {
+                            self.enter_operation_menu(frame.gl(), PanelState::VoxelMenu);
+                        }
+                        if ui.button("Inspect").on_hover_text("List mesh problems with fixes for them").clicked() {
+                            self.inspect_meshes();
+                            self.is_inspect_open = true;
+                        }
+                        if ui.button("Make printable")
+                            .on_hover_text("Weld, remove degenerate faces, unify winding and fill holes, then check the result")
+                            .clicked()
+                        {
+                            self.make_printable(frame.gl());
+                        }
+                        if ui.selectable_label(self.settings.is_flip_brush, "Flip faces")
+                            .on_hover_text("Click on surface to flip winding of faces around, back faces are highlighted")
+                            .clicked()
+                        {
+                            self.settings.is_flip_brush = !self.settings.is_flip_brush;
+                            self.settings.is_backface_highlight = self.settings.is_flip_brush;
+                        }
+                        if self.settings.is_flip_brush {
+                            ui.add(egui::Slider::new(&mut self.settings.flip_brush_radius, 0.0..=0.5).text("brush radius"))
+                                .on_hover_text("Fraction of scene radius, zero flips only the clicked face");
                         }
 
                         //let input = ui.input().clone();
                         //input.ui(ui);
                     });
+
+                    egui::CollapsingHeader::new("Meshes").default_open(true).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            let is_mergeable = self.indexed_meshes.len() > 1;
+                            if ui.add_enabled(is_mergeable, egui::Button::new("Merge all"))
+                                .on_hover_text("Join all meshes into one")
+                                .clicked()
+                            {
+                                self.merge_all_meshes(frame.gl());
+                            }
+                            ui.checkbox(&mut self.settings.is_merge_weld, "weld seams");
+                        });
+                        let selected_cnt = self.mesh_states.iter().filter(|mesh_state| mesh_state.is_selected).count();
+                        if selected_cnt > 0 {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("{} selected", selected_cnt))
+                                    .on_hover_text("Operations change only selected meshes");
+                                if ui.button("hide").clicked() {
+                                    for mesh_state in self.mesh_states.iter_mut().filter(|mesh_state| mesh_state.is_selected) {
+                                        mesh_state.is_visible = false;
+                                    }
+                                }
+                                if ui.button("delete").clicked() {
+                                    self.delete_selected_meshes(frame.gl());
+                                }
+                            });
+                        }
+                        let mut clicked = None;
+                        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                            for (i, (mesh, mesh_state)) in self.indexed_meshes.iter_mut().zip(self.mesh_states.iter_mut()).enumerate() {
+                                ui.horizontal(|ui| {
+                                    ui.checkbox(&mut mesh_state.is_visible, "").on_hover_text("Visible");
+
+                                    let name = if mesh.name.is_empty() { format!("mesh {}", i) } else { mesh.name.clone() };
+                                    let label = ui.selectable_label(mesh_state.is_selected, name)
+                                        .on_hover_ui(|ui| {
+                                            let info = mesh.ensure_info();
+                                            ui.label(format!("vertices: {}", info.vertices_cnt));
+                                            ui.label(format!("triangles: {}", info.faces_cnt));
+                                            ui.label(format!("size: {:.3} x {:.3} x {:.3}", info.size.x, info.size.y, info.size.z));
+                                            ui.label(if info.is_watertight { "watertight" } else { "not watertight" });
+                                        });
+                                    if label.clicked() {
+                                        clicked = Some(i);
+                                    }
+                                    ui.add(egui::DragValue::new(&mut mesh_state.alpha)
+                                        .speed(0.01)
+                                        .clamp_range(0.0..=1.0)
+                                        .max_decimals(2))
+                                        .on_hover_text("Opacity");
+                                });
+                            }
+                        });
+                        if let Some(i) = clicked {
+                            self.click_mesh(i, ui.input().modifiers);
+                        }
+
+                        if !self.indexed_meshes_original.is_empty() {
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                let original_state = &mut self.settings.original_state;
+                                ui.checkbox(&mut original_state.is_visible, "").on_hover_text("Visible");
+                                ui.color_edit_button_rgb(&mut self.settings.original_color);
+
+                                let faces_cnt: usize = self.indexed_meshes_original.iter().map(|mesh| mesh.indices.len() / 3).sum();
+                                ui.label("original")
+                                    .on_hover_text(format!("Meshes before the last apply, {} triangles, read only", faces_cnt));
+                                ui.add(egui::DragValue::new(&mut original_state.alpha)
+                                    .speed(0.01)
+                                    .clamp_range(0.0..=1.0)
+                                    .max_decimals(2))
+                                    .on_hover_text("Opacity");
+                                if ui.button("discard").clicked() {
+                                    self.discard_original(frame.gl());
+                                }
+                            });
+                        }
+                    });
                 }
                 PanelState::RemeshMenu => {
-                    let mut iter = self.settings.remesh_iterations;
-                    ui.add(egui::Slider::new(&mut iter, 1..=5).integer().text("Iterations"));
+                    const MAX_ITERATIONS: u32 = 5;
+
+                    let is_max_faces_changed = ui.add(egui::DragValue::new(&mut self.settings.remesh_max_faces)
+                        .speed(10_000.0)
+                        .clamp_range(1..=100_000_000)
+                        .prefix("max faces: "))
+                        .on_hover_text("Too many faces can freeze the browser, splitting stops before exceeding it")
+                        .changed();
+
+                    let mut is_mode_changed = false;
+                    is_mode_changed |= ui.radio_value(&mut self.settings.remesh_mode, RemeshMode::CentroidSplit, "Centroid split")
+                        .on_hover_text("Every face into three, shape stays exactly the same")
+                        .changed();
+                    is_mode_changed |= ui.radio_value(&mut self.settings.remesh_mode, RemeshMode::LoopSubdivision, "Loop subdivision")
+                        .on_hover_text("Every face into four, surface gets smoother and rounder")
+                        .changed();
+
+                    let mut iter = self.settings.remesh_iterations.clamp(1, MAX_ITERATIONS);
+                    ui.add(egui::Slider::new(&mut iter, 1..=MAX_ITERATIONS).integer().text("Iterations"));
 
-                    if self.settings.remesh_iterations != iter {
+                    if self.settings.remesh_iterations != iter || is_max_faces_changed || is_mode_changed {
 
                         self.settings.total_num_faces_temp = 0;
-                        for (mesh, new_mesh) in self.indexed_meshes.iter().zip(self.indexed_meshes_temp.iter_mut()) {
+                        self.settings.remesh_iterations_done = iter;
+                        // faces of meshes not processed yet are reserved in the cap
+                        let mut faces_ahead = self.settings.total_num_faces;
+                        let targets = self.operation_targets();
+                        for ((mesh, new_mesh), is_target) in self.indexed_meshes.iter().zip(self.indexed_meshes_temp.iter_mut()).zip(targets) {
                             *new_mesh = mesh.clone();
+                            faces_ahead = faces_ahead.saturating_sub(mesh.indices.len() / 3);
 
-                            Remesher::split_faces(new_mesh, iter as usize);
+                            if is_target {
+                                let max_faces = self.settings.remesh_max_faces
+                                    .saturating_sub(self.settings.total_num_faces_temp + faces_ahead);
+                                let done = match self.settings.remesh_mode {
+                                    RemeshMode::CentroidSplit => Remesher::split_faces(new_mesh, iter as usize, max_faces),
+                                    RemeshMode::LoopSubdivision => Remesher::loop_subdivide(new_mesh, iter as usize, max_faces),
+                                } as u32;
+                                self.settings.remesh_iterations_done = self.settings.remesh_iterations_done.min(done);
+                            }
+                            new_mesh.ensure_normals();
                             self.settings.total_num_faces_temp += new_mesh.indices.len() / 3;
                         }
+                        if self.settings.remesh_iterations_done < iter {
+                            tracing::warn!(
+                                "Remesh stopped at {} of {} iterations, max faces reached",
+                                self.settings.remesh_iterations_done,
+                                iter
+                            );
+                        }
 
                         self.settings.remesh_iterations = iter;
                         self.render_scene_ref.lock()
                             .reset_temp_and_create_temp_meshes(frame.gl(), &self.indexed_meshes_temp);
                     }
+                    if self.settings.remesh_iterations_done < self.settings.remesh_iterations {
+                        ui.colored_label(
+                            egui::Color32::from_rgb(230, 160, 30),
+                            format!(
+                                "Limited by max faces, done {} of {} iterations",
+                                self.settings.remesh_iterations_done,
+                                self.settings.remesh_iterations
+                            )
+                        );
+                    }
+
+                    ui.label(&format!("faces before: {}", self.settings.total_num_faces));
+                    ui.label(&format!("faces after: {}", self.settings.total_num_faces_temp));
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Apply").on_hover_text("Apply changes and return to selection menu").clicked() {
+                            self.apply_temp_mehes(frame.gl());
+                            self.switch_to_selection_menu(frame.gl());
+                        }
+                        if ui.button("Back").on_hover_text("Reset changes and return to selection menu").clicked() {
+                            self.switch_to_selection_menu(frame.gl());
+                        }
+                    });
+                    if let Some(next) = WebEditor::continue_menu_button(ui, PanelState::RemeshMenu) {
+                        self.apply_and_continue(frame.gl(), next);
+                    }
+                }
+                PanelState::SmoothMenu => {
+                    let mut is_changed = false;
+                    is_changed |= ui.add(egui::Slider::new(&mut self.settings.smooth_iterations, 1..=10).text("Iterations")).changed();
+                    is_changed |= ui.add(egui::Slider::new(&mut self.settings.smooth_lambda, 0.0..=1.0).text("Lambda"))
+                        .on_hover_text("How far each vertex moves toward its neighbours per iteration")
+                        .changed();
+                    is_changed |= ui.checkbox(&mut self.settings.is_smooth_features, "preserve features")
+                        .on_hover_text("Keep creases sharper than the angle")
+                        .changed();
+                    if self.settings.is_smooth_features {
+                        is_changed |= ui.add(egui::Slider::new(&mut self.settings.smooth_feature_angle, 1.0..=180.0)
+                            .text("angle")
+                            .suffix("°"))
+                            .changed();
+                    }
+
+                    if is_changed {
+                        self.smooth_temp_meshes(frame.gl());
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Apply").on_hover_text("Apply changes and return to selection menu").clicked() {
+                            self.apply_temp_mehes(frame.gl());
+                            self.switch_to_selection_menu(frame.gl());
+                        }
+                        if ui.button("Back").on_hover_text("Reset changes and return to selection menu").clicked() {
+                            self.switch_to_selection_menu(frame.gl());
+                        }
+                    });
+                    if let Some(next) = WebEditor::continue_menu_button(ui, PanelState::SmoothMenu) {
+                        self.apply_and_continue(frame.gl(), next);
+                    }
+                }
+                PanelState::VoxelMenu => {
+                    // rebuilt once the slider is released, every step takes a while on big grids
+                    let response = ui.add(egui::Slider::new(&mut self.settings.voxel_resolution, 8..=256)
+                        .logarithmic(true)
+                        .text("resolution"))
+                        .on_hover_text("Cells along the longest side, details and holes smaller than a cell are lost");
+                    if response.drag_released() || (response.changed() && !response.dragged()) {
+                        self.voxel_temp_meshes(frame.gl());
+                    }
 
                     ui.label(&format!("faces before: {}", self.settings.total_num_faces));
                     ui.label(&format!("faces after: {}", self.settings.total_num_faces_temp));
+                    ui.label("Result is dense, simplify it afterwards");
 
                     ui.horizontal(|ui| {
                         if ui.button("Apply").on_hover_text("Apply changes and return to selection menu").clicked() {
@@ -396,88 +2396,743 @@ impl eframe::App for WebEditor {
                             self.switch_to_selection_menu(frame.gl());
                         }
                     });
+                    if let Some(next) = WebEditor::continue_menu_button(ui, PanelState::VoxelMenu) {
+                        self.apply_and_continue(frame.gl(), next);
+                    }
+                }
+                PanelState::TransformMenu => {
+                    const ROTATE_SNAP: f32 = 15.0;
+
+                    let mut is_changed = false;
+                    let is_snap = self.settings.is_transform_snap;
+                    let snap_step = self.settings.transform_snap_step;
+                    ui.horizontal(|ui| {
+                        ui.label("move");
+                        for (value, axis) in self.settings.transform_translate.iter_mut().zip(["x: ", "y: ", "z: "]) {
+                            let speed = if is_snap { snap_step } else { 0.01 };
+                            is_changed |= ui.add(egui::DragValue::new(value).speed(speed).prefix(axis)).changed();
+                            // typed values and values from before snap was enabled land on the grid too
+                            let snapped = (*value / snap_step).round() * snap_step;
+                            if is_snap && (snapped - *value).abs() > std::f32::EPSILON {
+                                *value = snapped;
+                                is_changed = true;
+                            }
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("rotate");
+                        for (value, axis) in self.settings.transform_rotate.iter_mut().zip(["x: ", "y: ", "z: "]) {
+                            let speed = if is_snap { ROTATE_SNAP } else { 1.0 };
+                            is_changed |= ui.add(egui::DragValue::new(value).speed(speed).prefix(axis).suffix("°")).changed();
+                            let snapped = (*value / ROTATE_SNAP).round() * ROTATE_SNAP;
+                            if is_snap && (snapped - *value).abs() > std::f32::EPSILON {
+                                *value = snapped;
+                                is_changed = true;
+                            }
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("scale");
+                        for (value, axis) in self.settings.transform_scale.iter_mut().zip(["x: ", "y: ", "z: "]) {
+                            is_changed |= ui.add(egui::DragValue::new(value).speed(0.01).prefix(axis)).changed();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        is_changed |= ui.checkbox(&mut self.settings.is_transform_snap, "snap")
+                            .on_hover_text("Move by grid step, rotate by 15°")
+                            .changed();
+                        if self.settings.is_transform_snap {
+                            is_changed |= ui.add(egui::DragValue::new(&mut self.settings.transform_snap_step)
+                                .speed(0.1)
+                                .clamp_range(0.001..=std::f32::MAX)
+                                .prefix("grid step: "))
+                                .changed();
+                        }
+                    });
+
+                    if is_changed {
+                        self.transform_temp_meshes(frame.gl());
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Bake").on_hover_text("Apply transform to vertices and return to selection menu").clicked() {
+                            self.apply_temp_mehes(frame.gl());
+                            self.switch_to_selection_menu(frame.gl());
+                        }
+                        if ui.button("Reset").on_hover_text("Set transform back to identity").clicked() {
+                            self.settings.transform_translate = [0.0; 3];
+                            self.settings.transform_rotate = [0.0; 3];
+                            self.settings.transform_scale = [1.0; 3];
+                            self.transform_temp_meshes(frame.gl());
+                        }
+                        if ui.button("Back").on_hover_text("Reset changes and return to selection menu").clicked() {
+                            self.switch_to_selection_menu(frame.gl());
+                        }
+                    });
+                    if let Some(next) = WebEditor::continue_menu_button(ui, PanelState::TransformMenu) {
+                        self.apply_and_continue(frame.gl(), next);
+                    }
                 }
                 PanelState::SimplificationMenu => {
                     let mut error = self.settings.simplification_error;
                     let mut agr = self.settings.simplification_agr;
-                    ui.add(egui::Slider::new(&mut error, 0.001..=1.0).text("Error"));
-                    ui.add(egui::Slider::new(&mut agr, 1.0..=20.0).text("Agresiveness"));
+                    let mut is_max_error_mode = self.settings.is_simplification_max_error_mode;
+                    let mut max_error = self.settings.simplification_max_error;
+                    let mut cleanup_rounds = self.settings.simplification_cleanup_rounds;
+                    let mut is_conservative = self.settings.is_simplification_conservative;
+                    let mut is_features = self.settings.is_simplification_features;
+                    let mut feature_angle = self.settings.simplification_feature_angle;
+                    let mut is_min_angle = self.settings.is_simplification_min_angle;
+                    let mut min_angle = self.settings.simplification_min_angle;
 
-                    if (self.settings.simplification_error - error).abs() > std::f32::EPSILON
-                        || (self.settings.simplification_agr - agr).abs() > std::f32::EPSILON {
-
-                        self.settings.total_num_faces_temp = 0;
-                        for (mesh, new_mesh) in self.indexed_meshes.iter().zip(self.indexed_meshes_temp.iter_mut()) {
-                            *new_mesh = mesh.clone();
+                    let mut is_clustering = self.settings.is_simplification_clustering;
+                    ui.checkbox(&mut is_clustering, "fast (clustering)")
+                        .on_hover_text("Merge vertices on a uniform grid, rough but near instant on huge scans");
+                    if is_clustering {
+                        let mut resolution = self.settings.simplification_cluster_resolution;
+                        ui.add(egui::Slider::new(&mut resolution, 4..=1024).logarithmic(true).text("grid resolution"))
+                            .on_hover_text("Cells along the longest side of every mesh");
+                        if !self.settings.is_simplification_clustering || resolution != self.settings.simplification_cluster_resolution {
+                            self.settings.is_simplification_clustering = true;
+                            self.settings.simplification_cluster_resolution = resolution;
+                            self.cluster_temp_meshes(frame.gl());
+                        }
+                    } else {
+                        ui.checkbox(&mut is_max_error_mode, "limit by max error")
+                            .on_hover_text("Collapse as much as possible while surface moves less than max error");
+                        if is_max_error_mode {
+                            ui.add(egui::DragValue::new(&mut max_error)
+                                .speed(0.001)
+                                .clamp_range(0.0..=std::f32::MAX)
+                                .prefix("max error (units): "));
+                        } else {
+                            const DRAG_DEBOUNCE: f32 = 0.2;
 
-                            let mut simp = Simplify::from(new_mesh);
-                            simp.simplify_mesh((error * (new_mesh.indices.len() / 3) as f32) as usize, agr);
-                            simp.to(new_mesh);
+                            // fraction of faces to keep, shown as percent
+                            let mut keep_percent = self.settings.simplification_error_pending * 100.0;
+                            let response = ui.add(egui::Slider::new(&mut keep_percent, 1.0..=100.0)
+                                .suffix("%")
+                                .text("keep faces"));
 
-                            self.settings.total_num_faces_temp += new_mesh.indices.len() / 3;
+                            // while dragging recompute only once value rests for a moment
+                            let pending = keep_percent / 100.0;
+                            if (pending - self.settings.simplification_error_pending).abs() > std::f32::EPSILON {
+                                self.settings.simplification_error_pending = pending;
+                                self.settings.simplification_debounce_time = 0.0;
+                            } else {
+                                self.settings.simplification_debounce_time += ui.input().unstable_dt;
+                            }
+                            if !response.dragged() || self.settings.simplification_debounce_time >= DRAG_DEBOUNCE {
+                                error = pending;
+                            }
                         }
+                        ui.add(egui::Slider::new(&mut agr, 1.0..=20.0).text("Agresiveness"));
+                        ui.add(egui::DragValue::new(&mut cleanup_rounds).clamp_range(0..=10).prefix("cleanup rounds: "))
+                            .on_hover_text("Remove degenerate faces, weld and simplify again after the first pass");
+                        ui.checkbox(&mut is_conservative, "conservative placement")
+                            .on_hover_text("Keep collapsed vertices close to the original edge, reduces spikes on scans");
+                        ui.checkbox(&mut is_features, "preserve feature edges")
+                            .on_hover_text("Keep sharp edges, chamfers and holes of CAD parts");
+                        if is_features {
+                            ui.add(egui::Slider::new(&mut feature_angle, 1.0..=180.0).suffix("°").text("feature angle"));
+                        }
+                        ui.checkbox(&mut is_min_angle, "limit triangle quality")
+                            .on_hover_text("Skip collapses leaving thin triangles, ends with more faces but better shaped ones");
+                        if is_min_angle {
+                            ui.add(egui::Slider::new(&mut min_angle, 1.0..=50.0).suffix("°").text("min angle"));
+                        }
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.settings.is_simplification_apply_guard, "confirm above")
+                                .on_hover_text("Ask before applying when more faces than this are removed");
+                            if self.settings.is_simplification_apply_guard {
+                                let mut guard_percent = self.settings.simplification_apply_guard * 100.0;
+                                ui.add(egui::DragValue::new(&mut guard_percent).clamp_range(1.0..=99.0).suffix("% removed"));
+                                self.settings.simplification_apply_guard = guard_percent / 100.0;
+                            }
+                        });
+                        ui.checkbox(&mut self.settings.is_simplification_interactive, "interactive mode")
+                            .on_hover_text("Continue collapsing previous result while error decreases instead of restarting");
 
-                        self.settings.simplification_error = error;
-                        self.settings.simplification_agr = agr;
-                        self.render_scene_ref.lock()
-                            .reset_temp_and_create_temp_meshes(frame.gl(), &self.indexed_meshes_temp);
+                        let is_mode_changed = is_max_error_mode != self.settings.is_simplification_max_error_mode;
+                        let is_agr_changed = (self.settings.simplification_agr - agr).abs() > std::f32::EPSILON;
+                        let is_rounds_changed = cleanup_rounds != self.settings.simplification_cleanup_rounds;
+                        let is_conservative_changed = is_conservative != self.settings.is_simplification_conservative;
+                        let is_features_changed = is_features != self.settings.is_simplification_features
+                            || (is_features && (feature_angle - self.settings.simplification_feature_angle).abs() > std::f32::EPSILON);
+                        let is_min_angle_changed = is_min_angle != self.settings.is_simplification_min_angle
+                            || (is_min_angle && (min_angle - self.settings.simplification_min_angle).abs() > std::f32::EPSILON);
+                        // temp meshes hold clustering result after switching back
+                        let is_clustering_changed = self.settings.is_simplification_clustering;
+                        let is_target_changed = if is_max_error_mode {
+                            (self.settings.simplification_max_error - max_error).abs() > std::f32::EPSILON
+                        } else {
+                            (self.settings.simplification_error - error).abs() > std::f32::EPSILON
+                        };
+
+                        if is_mode_changed || is_agr_changed || is_target_changed || is_rounds_changed || is_conservative_changed
+                            || is_features_changed || is_min_angle_changed || is_clustering_changed
+                        {
+
+                            let targets = self.operation_targets();
+
+                            // only a lower target can continue from previous result, cleanup rebuilds simplifiers
+                            let is_keep_simplifiers = self.settings.is_simplification_interactive
+                                && !is_max_error_mode
+                                && cleanup_rounds == 0;
+                            let is_continue = is_keep_simplifiers
+                                && !is_mode_changed
+                                && !is_agr_changed
+                                && !is_rounds_changed
+                                && !is_conservative_changed
+                                && !is_features_changed
+                                && !is_min_angle_changed
+                                && !is_clustering_changed
+                                && self.simplifiers.len() == targets.iter().filter(|&&is_target| is_target).count()
+                                && error < self.settings.simplification_error;
+                            if !is_continue {
+                                self.simplifiers.clear();
+                            }
+
+                            self.settings.total_num_faces_temp = 0;
+                            // first pass and every cleanup round, only reported when there are rounds
+                            self.settings.total_num_faces_rounds = if cleanup_rounds > 0 {
+                                vec![0; cleanup_rounds as usize + 1]
+                            } else {
+                                vec![]
+                            };
+                            let simp_feature_angle = if is_features { Some(feature_angle) } else { None };
+                            let simp_min_angle = if is_min_angle { Some(min_angle) } else { None };
+                            // simplifiers exist only for targets, so they are counted separately
+                            let mut simplifier_idx = 0;
+                            for ((mesh, new_mesh), is_target) in self.indexed_meshes.iter().zip(self.indexed_meshes_temp.iter_mut()).zip(targets) {
+                                if !is_target {
+                                    *new_mesh = mesh.clone();
+                                    self.settings.total_num_faces_temp += new_mesh.indices.len() / 3;
+                                    for faces_cnt in self.settings.total_num_faces_rounds.iter_mut() {
+                                        *faces_cnt += new_mesh.indices.len() / 3;
+                                    }
+                                    continue;
+                                }
+
+                                let target_count = (error * (mesh.indices.len() / 3) as f32) as usize;
+
+                                if is_continue {
+                                    self.simplifiers[simplifier_idx].simplify_mesh(target_count, agr);
+                                    self.simplifiers[simplifier_idx].to(new_mesh);
+                                    simplifier_idx += 1;
+                                } else {
+                                    let mut simp = Simplify::from(mesh);
+                                    simp.is_conservative_placement = is_conservative;
+                                    simp.feature_angle = simp_feature_angle;
+                                    simp.min_angle = simp_min_angle;
+                                    if is_max_error_mode {
+                                        simp.simplify_mesh_to_error(max_error, agr);
+                                    } else {
+                                        simp.simplify_mesh(target_count, agr);
+                                    }
+                                    simp.to(new_mesh);
+                                    if let Some(faces_cnt) = self.settings.total_num_faces_rounds.first_mut() {
+                                        *faces_cnt += new_mesh.indices.len() / 3;
+                                    }
+
+                                    // every round starts from cleaned result of the previous one
+                                    for round in 0..cleanup_rounds as usize {
+                                        new_mesh.remove_degenerate_faces();
+                                        let (min, max) = new_mesh.calculate_aabb();
+                                        new_mesh.weld_vertices((max - min).magnitude() * simplification::WELD_EPS_RELATIVE);
+
+                                        simp = Simplify::from(new_mesh);
+                                        simp.is_conservative_placement = is_conservative;
+                                        simp.feature_angle = simp_feature_angle;
+                                        simp.min_angle = simp_min_angle;
+                                        if is_max_error_mode {
+                                            simp.simplify_mesh_to_error(max_error, agr);
+                                        } else {
+                                            simp.simplify_mesh(target_count, agr);
+                                        }
+                                        simp.to(new_mesh);
+
+                                        self.settings.total_num_faces_rounds[round + 1] += new_mesh.indices.len() / 3;
+                                    }
+
+                                    if is_keep_simplifiers {
+                                        self.simplifiers.push(simp);
+                                    }
+                                }
+                                new_mesh.ensure_normals();
+
+                                self.settings.total_num_faces_temp += new_mesh.indices.len() / 3;
+                            }
+
+                            self.settings.simplification_error = error;
+                            self.settings.simplification_agr = agr;
+                            self.settings.is_simplification_max_error_mode = is_max_error_mode;
+                            self.settings.simplification_max_error = max_error;
+                            self.settings.simplification_cleanup_rounds = cleanup_rounds;
+                            self.settings.is_simplification_conservative = is_conservative;
+                            self.settings.is_simplification_features = is_features;
+                            self.settings.simplification_feature_angle = feature_angle;
+                            self.settings.is_simplification_min_angle = is_min_angle;
+                            self.settings.simplification_min_angle = min_angle;
+                            self.settings.is_simplification_clustering = false;
+                            self.render_scene_ref.lock()
+                                .reset_temp_and_create_temp_meshes(frame.gl(), &self.indexed_meshes_temp);
+                        }
                     }
 
                     ui.label(&format!("faces before: {}", self.settings.total_num_faces));
-                    ui.label(&format!("faces after: {}", self.settings.total_num_faces_temp));
+                    for (pass, faces_cnt) in self.settings.total_num_faces_rounds.iter().enumerate() {
+                        ui.label(&format!("faces after pass {}: {}", pass + 1, faces_cnt));
+                    }
+                    if !is_clustering && !is_max_error_mode
+                        && (error - self.settings.simplification_error_pending).abs() > std::f32::EPSILON
+                    {
+                        let estimated = (self.settings.simplification_error_pending * self.settings.total_num_faces as f32) as usize;
+                        ui.label(&format!("faces after: ~{}", estimated));
+                    } else {
+                        ui.label(&format!("faces after: {}", self.settings.total_num_faces_temp));
+                    }
 
                     ui.horizontal(|ui| {
                         if ui.button("Apply").on_hover_text("Apply changes and return to selection menu").clicked() {
-                            self.apply_temp_mehes(frame.gl());
-                            self.switch_to_selection_menu(frame.gl());
+                            if self.is_simplification_apply_guarded() {
+                                self.is_apply_confirm_open = true;
+                            } else {
+                                self.apply_temp_mehes(frame.gl());
+                                self.switch_to_selection_menu(frame.gl());
+                            }
                         }
                         if ui.button("Back").on_hover_text("Reset changes and return to selection menu").clicked() {
                             self.switch_to_selection_menu(frame.gl());
                         }
                     });
+                    if let Some(next) = WebEditor::continue_menu_button(ui, PanelState::SimplificationMenu) {
+                        if self.is_simplification_apply_guarded() {
+                            self.is_apply_confirm_open = true;
+                            self.apply_confirm_next = Some(next);
+                        } else {
+                            self.apply_and_continue(frame.gl(), next);
+                        }
+                    }
                 }
             }
 
             ui.with_layout(egui::Layout::bottom_up(egui::Align::Min).with_cross_justify(true), |ui| {
+                if self.state != PanelState::SelectionMenu {
+                    ui.checkbox(&mut self.settings.is_render_temp_on_top, "set result on top");
+                    ui.checkbox(&mut self.settings.is_render_static, "set render original");
+                    ui.checkbox(&mut self.settings.is_keep_original, "keep original on apply")
+                        .on_hover_text("Applying keeps the meshes from before as hidden layer to compare with");
+                }
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgb(&mut self.settings.points_color);
+                    ui.checkbox(&mut self.settings.is_render_points, "points");
+                    ui.add(egui::DragValue::new(&mut self.settings.point_size).clamp_range(1.0..=16.0).speed(0.1));
+                });
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgb(&mut self.settings.wireframe_color);
+                    ui.checkbox(&mut self.settings.is_render_wireframe, "wireframe");
+                    ui.add(egui::DragValue::new(&mut self.settings.line_width).clamp_range(1.0..=8.0).speed(0.1));
+                });
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgb(&mut self.settings.outline_color);
+                    ui.checkbox(&mut self.settings.is_outline, "outlines")
+                        .on_hover_text("Lines at silhouettes and sharp creases");
+                });
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgb(&mut self.settings.fill_color);
+                    ui.checkbox(&mut self.settings.is_render_fill, "fill");
+                });
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgb(&mut self.settings.backface_color);
+                    ui.checkbox(&mut self.settings.is_backface_highlight, "back faces")
+                        .on_hover_text("Color back sides of faces, so inverted patches stand out");
+                });
+                ui.checkbox(&mut self.settings.is_oit, "order independent transparency")
+                    .on_hover_text("Overlapping translucent meshes blend correctly, costs an extra render target");
+                egui::ComboBox::from_label("shading")
+                    .selected_text(match self.settings.shading_mode {
+                        ShadingMode::Color => "color",
+                        ShadingMode::MeanCurvature => "mean curvature",
+                        ShadingMode::GaussianCurvature => "gaussian curvature",
+                        ShadingMode::FaceColor => "face color",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.settings.shading_mode, ShadingMode::Color, "color");
+                        ui.selectable_value(&mut self.settings.shading_mode, ShadingMode::MeanCurvature, "mean curvature");
+                        ui.selectable_value(&mut self.settings.shading_mode, ShadingMode::GaussianCurvature, "gaussian curvature");
+                        ui.selectable_value(&mut self.settings.shading_mode, ShadingMode::FaceColor, "face color")
+                            .on_hover_text("Colors stored on faces of ply files, fill color for meshes without them");
+                    });
+                ui.horizontal(|ui| {
+                    if ui.button("bake occlusion").on_hover_text("Darken cavities, slow on big meshes").clicked() {
+                        self.bake_occlusion(frame.gl());
+                    }
+                    if ui.button("clear").clicked() {
+                        self.clear_occlusion(frame.gl());
+                    }
+                    ui.add(egui::DragValue::new(&mut self.settings.occlusion_rays).clamp_range(4..=256).suffix(" rays"));
+                });
+                ui.separator();
                 ui.checkbox(&mut self.settings.is_cull_face, "set cull faces");
                 ui.checkbox(&mut self.settings.is_flat_shading, "set flat shading");
+                if ui.button("recompute normals").on_hover_text("Replace stored normals with ones computed from faces").clicked() {
+                    self.recompute_normals(frame.gl());
+                }
+                ui.add(egui::Slider::new(&mut self.settings.rim_power, 0.5..=8.0).text("rim power"));
+                egui::ComboBox::from_label("lighting")
+                    .selected_text(match self.settings.lighting_preset {
+                        LightingPreset::Studio => "studio",
+                        LightingPreset::Top => "top",
+                        LightingPreset::Headlight => "headlight",
+                        LightingPreset::Manual => "manual",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.settings.lighting_preset, LightingPreset::Studio, "studio")
+                            .on_hover_text("Key, fill and rim lights following the camera");
+                        ui.selectable_value(&mut self.settings.lighting_preset, LightingPreset::Top, "top")
+                            .on_hover_text("Key light from above the scene, weak fill from the camera");
+                        ui.selectable_value(&mut self.settings.lighting_preset, LightingPreset::Headlight, "headlight")
+                            .on_hover_text("Single light at the camera");
+                        ui.selectable_value(&mut self.settings.lighting_preset, LightingPreset::Manual, "manual")
+                            .on_hover_text("Single light at fixed world position");
+                    });
+                if self.settings.lighting_preset == LightingPreset::Manual {
+                    let speed = self.settings.light_rig.radius * 0.01;
+                    ui.horizontal(|ui| {
+                        ui.label("light");
+                        ui.add(egui::DragValue::new(&mut self.settings.light_pos[0]).speed(speed).prefix("x: "));
+                        ui.add(egui::DragValue::new(&mut self.settings.light_pos[1]).speed(speed).prefix("y: "));
+                        ui.add(egui::DragValue::new(&mut self.settings.light_pos[2]).speed(speed).prefix("z: "));
+                    });
+                }
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgb(&mut self.settings.rim_color);
+                    ui.add(egui::Slider::new(&mut self.settings.rim_strength, 0.0..=1.0).text("rim"));
+                });
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    let mut fov = self.camera.fov;
+                    // parallel projection has no field of view
+                    let is_perspective = !self.camera.is_orthographic;
+                    ui.add_enabled(is_perspective, egui::Slider::new(&mut fov, 20.0..=90.0).suffix("°").text("fov"));
+                    ui.add_enabled(is_perspective, egui::Checkbox::new(&mut self.settings.is_fov_keep_framing, "keep framing"))
+                        .on_hover_text("Move camera so model keeps its size on screen");
+
+                    if fov != self.camera.fov {
+                        let fit_dist_before = self.camera.calculate_fit_dist(1.0);
+                        self.camera.fov = fov;
+                        if self.settings.is_fov_keep_framing {
+                            self.camera.dist *= self.camera.calculate_fit_dist(1.0) / fit_dist_before;
+                        }
+                    }
+                });
+                ui.checkbox(&mut self.camera.is_orthographic, "orthographic")
+                    .on_hover_text("Parallel projection, sizes don't shrink with distance");
+                ui.separator();
+
+                // slider spans the scene along the plane normal
+                if self.settings.is_clipping {
+                    let clip_normal = Vector3::from(self.settings.clip_normal);
+                    let (offset_min, offset_max) = (clip_normal.dot(self.scene_aabb.0), clip_normal.dot(self.scene_aabb.1));
+                    let range = offset_min.min(offset_max)..=offset_min.max(offset_max);
+                    ui.add(egui::Slider::new(&mut self.settings.clip_offset, range).text("offset"));
+                    if ui.button("export section").on_hover_text("Save contours at the plane as svg").clicked() {
+                        match self.section_svg() {
+                            Some(svg) => {
+                                let file_name = format!("{}_section", self.settings.export_file_name.trim());
+                                Files::save_file_binary(&Files::export_file_name(&file_name, "svg"), svg.into_bytes());
+                            }
+                            None => tracing::warn!("Clipping plane doesn't cut any exported mesh"),
+                        }
+                    }
+                }
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.settings.is_clipping, "clipping plane");
+
+                    let axes = [("X", [1.0, 0.0, 0.0]), ("Y", [0.0, 1.0, 0.0]), ("Z", [0.0, 0.0, 1.0])];
+                    for (axis_name, axis) in axes {
+                        let is_axis = self.settings.clip_normal.map(f32::abs) == axis;
+                        if ui.selectable_label(is_axis, axis_name).clicked() {
+                            self.settings.clip_normal = axis;
+                            self.settings.clip_offset = Vector3::from(axis).dot(self.scene_center);
+                        }
+                    }
+                    if ui.button("flip").clicked() {
+                        self.settings.clip_normal = self.settings.clip_normal.map(|c| -c);
+                        self.settings.clip_offset = -self.settings.clip_offset;
+                    }
+                });
             });
         });
 
+        if self.is_frames_shown() {
+            egui::TopBottomPanel::bottom("timeline").show(ctx, |ui| {
+                // operation preview is made from the shown frame
+                let is_editing = self.state != PanelState::SelectionMenu;
+                let mut frame_idx = self.frame_idx;
+
+                ui.add_enabled_ui(!is_editing, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button(if self.is_frames_playing { "pause" } else { "play" }).clicked() {
+                            self.is_frames_playing = !self.is_frames_playing;
+                            self.frame_time = 0.0;
+                        }
+                        ui.add(egui::DragValue::new(&mut self.settings.frames_fps)
+                            .speed(0.5)
+                            .clamp_range(1.0..=120.0)
+                            .suffix(" fps"));
+                        ui.spacing_mut().slider_width = (ui.available_width() - 200.0).max(100.0);
+                        ui.add(egui::Slider::new(&mut frame_idx, 0..=self.frames.len() - 1)
+                            .text(&self.indexed_meshes[0].name));
+                    });
+                });
+
+                if self.is_frames_playing && !is_editing {
+                    // slow frames skip time instead of catching up
+                    let frame_duration = 1.0 / self.settings.frames_fps;
+                    self.frame_time += ui.input().unstable_dt;
+                    if self.frame_time >= frame_duration {
+                        self.frame_time = (self.frame_time - frame_duration).min(frame_duration);
+                        frame_idx = (frame_idx + 1) % self.frames.len();
+                    }
+                }
+                self.show_frame(frame.gl(), frame_idx);
+            });
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            ctx.request_repaint();
+            let rect = ui.max_rect();
+            let response = ui.interact(rect, egui::Id::new("viewport"), egui::Sense::click_and_drag());
+
+            self.camera.set_size(rect.width(), rect.height());
+
+            // on touch screens one finger orbits, two fingers pinch to zoom and drag to pan
+            let multi_touch = ui.input().multi_touch();
+            let is_touch = ui.input().any_touches();
+            let is_touch_orbit = is_touch
+                && multi_touch.is_none()
+                && response.dragged_by(egui::PointerButton::Primary);
+
+            // mouse navigation from bindings, drags have to start over the viewport
+            let bindings = &self.settings.bindings;
+            let is_dragged_by = |button: Option<egui::PointerButton>| {
+                !is_touch && button.map_or(false, |button| response.dragged_by(button))
+            };
+            let is_orbit = is_dragged_by(bindings.orbit_button) || is_touch_orbit;
+            let is_pan = is_dragged_by(bindings.pan_button);
+            let is_zoom = is_dragged_by(bindings.zoom_button);
+            let is_navigating = is_orbit || is_pan || is_zoom;
+
+            // any manual navigation cancels the transition
+            if ui.input().scroll_delta.y != 0.0 || is_navigating || multi_touch.is_some() {
+                self.camera_transition = None;
+            }
+            if let Some(transition) = self.camera_transition.as_mut() {
+                // frames can be far apart when not repainting continuously
+                if !transition.step(&mut self.camera, ui.input().unstable_dt.min(1.0 / 30.0)) {
+                    self.camera_transition = None;
+                }
+            }
+
+            // replace instead of push, so orbiting doesn't spam history
+            const CAMERA_URL_DEBOUNCE: f32 = 0.5;
+            let camera_url_state = Some((self.camera.state(), self.camera.fov));
+            if camera_url_state != self.camera_url_state {
+                self.camera_url_state = camera_url_state;
+                self.camera_url_idle_time = 0.0;
+            } else if self.camera_url_idle_time < CAMERA_URL_DEBOUNCE {
+                self.camera_url_idle_time += ui.input().unstable_dt;
+                if self.camera_url_idle_time >= CAMERA_URL_DEBOUNCE {
+                    Files::replace_url_param("camera", &self.camera.to_url_string());
+                }
+            }
+
+            // double click on surface to orbit around it, `C` to return to scene center
+            if response.double_clicked() {
+                if let Some(pos) = response.interact_pointer_pos() {
+                    let ndc_x = (pos.x - rect.left()) / rect.width() * 2.0 - 1.0;
+                    let ndc_y = 1.0 - (pos.y - rect.top()) / rect.height() * 2.0;
+                    let (origin, dir) = self.camera.calculate_ray(ndc_x, ndc_y);
+
+                    if let Some(pivot) = self.pick(origin, dir) {
+                        self.camera.set_pivot(pivot);
+                    }
+                }
+            }
+            // single click flips faces with the brush, drags still orbit
+            if response.clicked() && self.settings.is_flip_brush && self.state == PanelState::SelectionMenu {
+                if let Some(pos) = response.interact_pointer_pos() {
+                    let ndc_x = (pos.x - rect.left()) / rect.width() * 2.0 - 1.0;
+                    let ndc_y = 1.0 - (pos.y - rect.top()) / rect.height() * 2.0;
+                    let (origin, dir) = self.camera.calculate_ray(ndc_x, ndc_y);
+
+                    self.flip_faces_at(frame.gl(), origin, dir);
+                }
+            }
+            // typing into text fields doesn't trigger shortcuts
+            if !ui.ctx().wants_keyboard_input() {
+                for action in self.settings.bindings.pressed_actions(&ui.input()) {
+                    self.run_key_action(action);
+                }
+            }
+
+            let hover_pos = response.hover_pos();
+            let is_camera_moved = self.camera_url_idle_time == 0.0;
+            match hover_pos {
+                None => self.hover_pos = None,
+                Some(pos) if !is_navigating && (hover_pos != self.hover_pos || is_camera_moved) => {
+                    let ndc_x = (pos.x - rect.left()) / rect.width() * 2.0 - 1.0;
+                    let ndc_y = 1.0 - (pos.y - rect.top()) / rect.height() * 2.0;
+                    let (origin, dir) = self.camera.calculate_ray(ndc_x, ndc_y);
+
+                    self.hover_pos = hover_pos;
+                    self.hover_hit = self.pick(origin, dir);
+                }
+                _ => {}
+            }
 
-            self.camera.set_size(ui.max_rect().width(), ui.max_rect().height());
             self.camera.dist -= ui.input().scroll_delta.y * self.settings.scroll_sensitivity;
+            if let Some(multi_touch) = multi_touch {
+                if multi_touch.zoom_delta > 0.0 {
+                    self.camera.dist /= multi_touch.zoom_delta;
+                }
+                self.pan_camera(multi_touch.translation_delta, rect);
+            }
+            // dragging down moves away, at the same rate as the wheel
+            if is_zoom {
+                self.camera.dist += ui.input().pointer.delta().y * self.settings.scroll_sensitivity;
+            }
             self.camera.dist = self.camera.dist.max(self.settings.min_camera_dist);
-            if ui.input().pointer.middle_down() {
-                let delta_from_prev_frame = ui.input().pointer.delta();
-                let right = self.camera.up.cross(self.camera.dir_from_center).normalize();
-                self.camera.up = self.camera.dir_from_center.cross(right).normalize();
-
-                let r_xz = Matrix3::from_axis_angle(self.camera.up, Deg(-delta_from_prev_frame.x));
-                let r_yz = Matrix3::from_axis_angle(right, Deg(-delta_from_prev_frame.y));
-                self.camera.dir_from_center = r_yz * r_xz * self.camera.dir_from_center;
+            if is_orbit {
+                if let Some(pos) = ui.input().pointer.interact_pos() {
+                    self.orbit_camera(pos, ui.input().pointer.delta(), rect);
+                }
             }
+            if is_pan {
+                self.pan_camera(ui.input().pointer.delta(), rect);
+            }
+
+            // stands on the scene floor, half of its size away from the bounds
+            self.settings.reference_cube = if self.settings.is_reference_cube && !self.indexed_meshes.is_empty() {
+                let (min, max) = self.scene_aabb;
+                let size = self.settings.reference_size;
+                Some((Vector3::new(max.x + size, min.y + size / 2.0, (min.z + max.z) / 2.0), size))
+            } else {
+                None
+            };
+
+            self.render_scene_ref.lock()
+                .set_shading_mode(
+                    frame.gl(),
+                    self.settings.shading_mode,
+                    &self.indexed_meshes,
+                    &self.indexed_meshes_lod,
+                    &self.indexed_meshes_temp,
+                    &self.indexed_meshes_original
+                );
+            self.render_scene_ref.lock()
+                .set_render_wireframe(
+                    frame.gl(),
+                    self.settings.is_render_wireframe,
+                    &self.indexed_meshes,
+                    &self.indexed_meshes_lod,
+                    &self.indexed_meshes_temp,
+                    &self.indexed_meshes_original
+                );
 
             let triangle = self.render_scene_ref.clone();
             let camera = self.camera.clone();
             let settings = self.settings.clone();
+            let mesh_states = self.mesh_states.clone();
+
+            // camera counts as settled once it hasn't changed for a short moment
+            const LOD_SETTLE_TIME: f32 = 0.15;
+            let is_lod = self.settings.is_interaction_lod && self.camera_url_idle_time < LOD_SETTLE_TIME;
 
             let callback = egui::PaintCallback {
-                rect: ui.max_rect(),
-                callback: std::sync::Arc::new(move |_info, render_ctx| {
+                rect,
+                callback: std::sync::Arc::new(move |info, render_ctx| {
                     if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
-                        triangle.lock().render(painter.gl(), &settings, &camera);
+                        let viewport = info.viewport_in_pixels();
+                        let target = RenderTarget {
+                            fbo: painter.intermediate_fbo(),
+                            viewport: [
+                                viewport.left_px.round() as i32,
+                                viewport.from_bottom_px.round() as i32,
+                                viewport.width_px.round() as i32,
+                                viewport.height_px.round() as i32,
+                            ],
+                        };
+                        triangle.lock().render(painter.gl(), &settings, &camera, &mesh_states, is_lod, &target);
                     } else {
                         eprintln!("Can't do custom painting because we are not using a glow context");
                     }
                 }),
             };
             ui.painter().add(callback);
+
+            if self.indexed_meshes.is_empty() && self.indexed_meshes_temp.is_empty() {
+                ui.painter().text(
+                    rect.center(),
+                    egui::Align2::CENTER_CENTER,
+                    "Drag a .stl/.ply/.obj/.glb/.zip here, or File → Open",
+                    egui::TextStyle::Heading.resolve(ui.style()),
+                    ui.visuals().weak_text_color(),
+                );
+            } else if self.hover_pos.is_some() {
+                let text = match self.hover_hit {
+                    Some(p) => format!("x: {:.4}  y: {:.4}  z: {:.4}", p.x, p.y, p.z),
+                    None => "no hit".to_string(),
+                };
+                ui.painter().text(
+                    rect.left_bottom() + egui::vec2(8.0, -8.0),
+                    egui::Align2::LEFT_BOTTOM,
+                    text,
+                    egui::TextStyle::Monospace.resolve(ui.style()),
+                    ui.visuals().text_color(),
+                );
+            }
+
+            if self.settings.is_scene_size && !self.indexed_meshes.is_empty() {
+                let size = self.scene_aabb.1 - self.scene_aabb.0;
+                let suffix = self.settings.scene_units.suffix();
+                let mut text = format!("{:.3} x {:.3} x {:.3} {}", size.x, size.y, size.z, suffix);
+                if self.settings.is_reference_cube {
+                    text += &format!("\ncube: {} {}", self.settings.reference_size, suffix);
+                }
+                ui.painter().text(
+                    rect.left_top() + egui::vec2(8.0, 8.0),
+                    egui::Align2::LEFT_TOP,
+                    text,
+                    egui::TextStyle::Heading.resolve(ui.style()),
+                    ui.visuals().text_color(),
+                );
+            }
+
+            // egui repaints on input by itself, keep going only while something is in progress
+            let is_animating = self.camera_transition.is_some()
+                || self.receiver.is_some()
+                || (self.is_frames_playing && self.is_frames_shown() && self.state == PanelState::SelectionMenu)
+                || self.camera_url_idle_time < CAMERA_URL_DEBOUNCE
+                || (self.state == PanelState::SimplificationMenu
+                    && !self.settings.is_simplification_clustering
+                    && (self.settings.simplification_error - self.settings.simplification_error_pending).abs() > std::f32::EPSILON);
+            if self.settings.is_continuous_rendering || is_animating {
+                if self.settings.is_fps_capped {
+                    self.request_repaint_after(ctx, 1.0 / self.settings.max_fps.max(1) as f32);
+                } else {
+                    ctx.request_repaint();
+                }
+            }
         });
     }
 
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        storage.set_string(BINDINGS_STORAGE_KEY, self.settings.bindings.to_storage_string());
+    }
+
     fn on_exit(&mut self, gl: &glow::Context) {
         self.render_scene_ref.lock().destroy(gl);
     }
@@ -550,6 +3205,270 @@ impl Files {
         true
     }
 
+    // polylines flattened to the plane with given normal, y goes down in svg
+    fn write_contours_svg(contours: &[Vec<Vector3<f32>>], normal: Vector3<f32>) -> Option<String> {
+        if contours.is_empty() { return None; }
+
+        let helper = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+        let u = (helper - normal * normal.dot(helper)).normalize();
+        let v = normal.cross(u);
+        let contours: Vec<Vec<Vector2<f32>>> = contours
+            .iter()
+            .map(|contour| contour.iter().map(|p| Vector2::new(u.dot(*p), -v.dot(*p))).collect())
+            .collect();
+
+        let (mut min, mut max) = (Vector2::new(std::f32::MAX, std::f32::MAX), Vector2::new(std::f32::MIN, std::f32::MIN));
+        for p in contours.iter().flatten() {
+            min = Vector2::new(min.x.min(p.x), min.y.min(p.y));
+            max = Vector2::new(max.x.max(p.x), max.y.max(p.y));
+        }
+        let size = (max - min).map(|c| c.max(std::f32::EPSILON));
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}mm\" height=\"{}mm\" viewBox=\"{} {} {} {}\">\n",
+            size.x, size.y, min.x, min.y, size.x, size.y
+        );
+        let stroke_width = size.x.max(size.y) * 0.001;
+        for contour in contours.iter() {
+            let points: Vec<String> = contour.iter().map(|p| format!("{},{}", p.x, p.y)).collect();
+            svg += &format!(
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"black\" stroke-width=\"{}\"/>\n",
+                points.join(" "), stroke_width
+            );
+        }
+        svg += "</svg>\n";
+
+        Some(svg)
+    }
+
+    // plain obj, positions and normals of all meshes, faces refer to both
+    fn write_obj(meshes: &[IndexedMesh]) -> String {
+        Files::write_obj_text(meshes, None).0
+    }
+
+    // obj with one material per distinct face color, meshes without face colors get default_color,
+    // returns obj and mtl text, obj refers to mtl by mtl_file_name
+    fn write_obj_with_mtl(meshes: &[IndexedMesh], mtl_file_name: &str, default_color: [f32; 3]) -> (String, String) {
+        Files::write_obj_text(meshes, Some((mtl_file_name, default_color)))
+    }
+
+    // without mtl faces get no usemtl and returned mtl is empty
+    fn write_obj_text(meshes: &[IndexedMesh], mtl: Option<(&str, [f32; 3])>) -> (String, String) {
+        use std::fmt::Write as _;
+
+        // exact bits, so colors that only look the same still get own materials
+        let mut materials: Vec<[f32; 3]> = vec![];
+        let mut material_of = |color: [f32; 3]| {
+            let key = color.map(f32::to_bits);
+            match materials.iter().position(|material| material.map(f32::to_bits) == key) {
+                Some(k) => k,
+                None => {
+                    materials.push(color);
+                    materials.len() - 1
+                }
+            }
+        };
+
+        let mut obj = "# obj export from Web Editor\n".to_string();
+        if let Some((mtl_file_name, _)) = mtl {
+            writeln!(obj, "mtllib {}", mtl_file_name).unwrap();
+        }
+        let mut vertices_offset = 1;
+        for (i, mesh) in meshes.iter().enumerate() {
+            let name = if mesh.name.is_empty() { format!("mesh_{}", i) } else { mesh.name.clone() };
+            writeln!(obj, "o {}", name).unwrap();
+            for p in mesh.positions.iter() {
+                writeln!(obj, "v {} {} {}", p.x, p.y, p.z).unwrap();
+            }
+            let is_normals = mesh.normals.len() == mesh.positions.len();
+            if is_normals {
+                for n in mesh.normals.iter() {
+                    writeln!(obj, "vn {} {} {}", n.x, n.y, n.z).unwrap();
+                }
+            }
+
+            // usemtl only where color changes, ply faces of one color usually come in runs
+            let is_face_colors = mesh.has_face_colors();
+            let mut current_material = None;
+            for (f, face_idxs) in mesh.faces().enumerate() {
+                if let Some((_, default_color)) = mtl {
+                    let color = if is_face_colors { mesh.face_colors[f].into() } else { default_color };
+                    let material = material_of(color);
+                    if current_material != Some(material) {
+                        writeln!(obj, "usemtl material_{}", material).unwrap();
+                        current_material = Some(material);
+                    }
+                }
+
+                let [a, b, c] = face_idxs.map(|idx| idx + vertices_offset);
+                if is_normals {
+                    writeln!(obj, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c).unwrap();
+                } else {
+                    writeln!(obj, "f {} {} {}", a, b, c).unwrap();
+                }
+            }
+            vertices_offset += mesh.positions.len() as u32;
+        }
+
+        if mtl.is_none() {
+            return (obj, String::new());
+        }
+        let mut mtl = "# mtl export from Web Editor\n".to_string();
+        for (k, color) in materials.iter().enumerate() {
+            writeln!(
+                mtl, "newmtl material_{}\nKa 0 0 0\nKd {} {} {}\nKs 0 0 0\nd 1\nillum 1\n",
+                k, color[0], color[1], color[2]
+            ).unwrap();
+        }
+
+        (obj, mtl)
+    }
+
+    fn write_zip(entries: &[(String, Vec<u8>)]) -> zip::result::ZipResult<Vec<u8>> {
+        use std::io::Write;
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (name, content) in entries {
+            zip.start_file(name, options)?;
+            zip.write_all(content)?;
+        }
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    // topology sidecar for graph tools, per mesh sorted neighbours of every vertex and for every face
+    // neighbours across edges v0-v1, v1-v2 and v2-v0, null on border or where edge isn't manifold
+    fn write_connectivity(meshes: &[IndexedMesh]) -> String {
+        let json_meshes: Vec<String> = meshes
+            .iter()
+            .map(|mesh| {
+                let half_edge_mesh = HalfEdgeMesh::from(mesh);
+
+                let vertex_neighbors: Vec<String> = (0..half_edge_mesh.vertices_cnt() as u32)
+                    .map(|v| {
+                        let neighbors: Vec<String> = half_edge_mesh.vertex_neighbors(v)
+                            .iter()
+                            .map(|neighbor| neighbor.to_string())
+                            .collect();
+                        format!("[{}]", neighbors.join(","))
+                    })
+                    .collect();
+                let face_neighbors: Vec<String> = (0..half_edge_mesh.faces_cnt() as u32)
+                    .map(|f| {
+                        let neighbors: Vec<String> = half_edge_mesh.face_edges(f)
+                            .iter()
+                            .map(|&h| match half_edge_mesh.twin(h) {
+                                Some(twin) => half_edge_mesh.face(twin).to_string(),
+                                None => "null".to_string(),
+                            })
+                            .collect();
+                        format!("[{}]", neighbors.join(","))
+                    })
+                    .collect();
+
+                format!(
+                    "{{\"name\":{},\"vertices\":{},\"faces\":{},\"vertex_neighbors\":[{}],\"face_neighbors\":[{}]}}",
+                    Gltf::json_string(&mesh.name),
+                    half_edge_mesh.vertices_cnt(),
+                    half_edge_mesh.faces_cnt(),
+                    vertex_neighbors.join(","),
+                    face_neighbors.join(",")
+                )
+            })
+            .collect();
+
+        format!("{{\"meshes\":[{}]}}", json_meshes.join(",\n"))
+    }
+
+    // other params of the fragment stay as they are
+    fn replace_url_param(key: &str, value: &str) -> bool {
+        let fragment = web_sys::window()
+            .and_then(|window| window.location().hash().ok())
+            .unwrap_or_default();
+
+        Files::replace_url_fragment(&Files::fragment_with_param(&fragment, key, value))
+    }
+    fn fragment_with_param(fragment: &str, key: &str, value: &str) -> String {
+        let param = format!("{}={}", key, value);
+        let mut params: Vec<&str> = fragment
+            .trim_start_matches('#')
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .collect();
+
+        match params.iter().position(|param| param.split('=').next() == Some(key)) {
+            Some(idx) => params[idx] = &param,
+            None => params.push(&param),
+        }
+        params.join("&")
+    }
+    fn replace_url_fragment(fragment: &str) -> bool {
+        let history = web_sys::window().and_then(|window| window.history().ok());
+        if history.is_none() {
+            return false;
+        }
+        let history = history.unwrap();
+
+        history
+            .replace_state_with_url(&wasm_bindgen::JsValue::NULL, "", Some(&format!("#{}", fragment)))
+            .is_ok()
+    }
+
+    fn file_stem(file_name: &str) -> String {
+        std::path::Path::new(file_name)
+            .file_stem()
+            .and_then(std::ffi::OsStr::to_str)
+            .unwrap_or("file")
+            .to_string()
+    }
+    // digit runs compare by value, so frame_2 goes before frame_10
+    fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+        let chunks = |s: &str| {
+            let mut chunks: Vec<String> = vec![];
+            let mut is_digits = false;
+            for c in s.chars() {
+                if chunks.is_empty() || c.is_ascii_digit() != is_digits {
+                    chunks.push(String::new());
+                    is_digits = c.is_ascii_digit();
+                }
+                chunks.last_mut().unwrap().push(c);
+            }
+            chunks
+        };
+
+        let (a, b) = (chunks(a), chunks(b));
+        for (a, b) in a.iter().zip(b.iter()) {
+            let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            };
+            if ordering != std::cmp::Ordering::Equal {
+                return ordering;
+            }
+        }
+        a.len().cmp(&b.len())
+    }
+    // one frame per file in natural order of names, meshes of the file are merged
+    fn frames_from_files(mut files: Vec<(String, Vec<IndexedMesh>)>) -> Vec<IndexedMesh> {
+        files.sort_by(|(a, _), (b, _)| Files::natural_cmp(a, b));
+
+        files
+            .into_iter()
+            .filter(|(_, meshes)| !meshes.is_empty())
+            .map(|(file_name, meshes)| {
+                let meshes: Vec<&IndexedMesh> = meshes.iter().collect();
+                let mut frame = IndexedMesh::merge(&meshes);
+                frame.name = Files::file_stem(&file_name);
+                frame
+            })
+            .collect()
+    }
+    // user typed name with extension of exported format, typed extension is replaced
+    fn export_file_name(file_name: &str, ext: &str) -> String {
+        format!("{}.{}", Files::file_stem(file_name.trim()), ext)
+    }
+
     fn check_dropped_files_then_preview_load(
         ctx: &egui::Context,
         gl: &glow::Context,
@@ -559,7 +3478,12 @@ impl Files {
             let dropped_files = ctx.input().raw.dropped_files.clone();
 
             web_editor.reset_all(gl);
+            if let Some(dropped_file) = dropped_files.first() {
+                web_editor.settings.export_file_name = Files::file_stem(&dropped_file.name);
+            }
 
+            let is_frames = web_editor.settings.is_load_as_frames && dropped_files.len() > 1;
+            let mut loaded_files = vec![];
             for dropped_file in dropped_files.iter() {
                 if let Some(bytes_ref) = &dropped_file.bytes {
                     let file = std::io::Cursor::new(bytes_ref);
@@ -568,33 +3492,373 @@ impl Files {
                         .extension()
                         .and_then(std::ffi::OsStr::to_str);
 
-                    if let Some(ext) = ext {
-                        let mesh = Files::read_indexed_mesh(file, ext);
+                    match Files::detect_format(bytes_ref, ext) {
+                        Some(format) => {
+                            let meshes = Files::read_indexed_meshes(file, format, web_editor.import_options());
 
-                        if let Ok(mesh) = mesh {
-                            if !mesh.is_empty() {
-                                web_editor.push_indexed_mesh(gl, mesh);
+                            if let Ok(meshes) = meshes {
+                                if is_frames {
+                                    loaded_files.push((dropped_file.name.clone(), meshes));
+                                } else {
+                                    for mesh in meshes {
+                                        web_editor.push_indexed_mesh(gl, mesh);
+                                    }
+                                }
                             }
                         }
+                        None => {
+                            tracing::warn!("Unknown format of `{}`", dropped_file.name);
+                        }
                     }
                 }
             }
+            if is_frames {
+                web_editor.set_frames(gl, Files::frames_from_files(loaded_files));
+            }
 
             web_editor.recalculate_camera_view();
         }
         Files::preview_files_being_dropped(ctx);
     }
 
-    fn read_indexed_mesh<T>(mut file: std::io::Cursor<T>, ext: &str) -> Result<IndexedMesh, std::io::Error>
+    // format from the first bytes, extension is used only when content doesn't tell
+    fn detect_format<'a>(bytes: &[u8], ext: Option<&'a str>) -> Option<&'a str> {
+        Files::sniff_format(bytes).or(ext)
+    }
+
+    fn sniff_format(bytes: &[u8]) -> Option<&'static str> {
+        if bytes.starts_with(b"PK\x03\x04") {
+            return Some("zip");
+        }
+        if bytes.starts_with(b"glTF") {
+            return Some("glb");
+        }
+        if bytes.starts_with(b"ply\n") || bytes.starts_with(b"ply\r") {
+            return Some("ply");
+        }
+        // binary stl is 80 bytes header, triangle count and 50 bytes per triangle,
+        // checked before ascii because the header may start with `solid` too
+        if bytes.len() >= 84 {
+            let triangles_cnt = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+            if triangles_cnt.checked_mul(50).and_then(|len| len.checked_add(84)) == Some(bytes.len()) {
+                return Some("stl");
+            }
+        }
+
+        // text formats, look only at the beginning of big files
+        let head = &bytes[..bytes.len().min(4096)];
+        let head = match std::str::from_utf8(head) {
+            Ok(head) => head,
+            // cut may split a multibyte character
+            Err(err) if err.error_len().is_none() => std::str::from_utf8(&head[..err.valid_up_to()]).ok()?,
+            Err(_) => return None,
+        };
+        if head.trim_start().starts_with("solid") {
+            return Some("stl");
+        }
+        let first_token = head
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .and_then(|line| line.split_whitespace().next());
+        match first_token {
+            Some("v" | "vn" | "vt" | "f" | "o" | "g" | "s" | "mtllib" | "usemtl") => Some("obj"),
+            _ => None,
+        }
+    }
+
+    // reads all meshes from file, archives may contain several of them
+    fn read_indexed_meshes<T>(
+        file: std::io::Cursor<T>,
+        ext: &str,
+        options: ImportOptions
+    ) -> Result<Vec<IndexedMesh>, std::io::Error>
     where
         T: std::convert::AsRef<[u8]>,
     {
+        use std::io::Read;
+
+        let mut meshes = vec![];
         match ext {
+            "zip" | "ZIP" => {
+                let mut archive = zip::ZipArchive::new(file)?;
+
+                for i in 0..archive.len() {
+                    let mut entry = archive.by_index(i)?;
+                    if entry.is_dir() { continue; }
+
+                    let entry_name = entry.name().to_string();
+                    let entry_ext = std::path::Path::new(&entry_name)
+                        .extension()
+                        .and_then(std::ffi::OsStr::to_str);
+
+                    let mut bytes = Vec::with_capacity(entry.size() as usize);
+                    entry.read_to_end(&mut bytes)?;
+
+                    let entry_meshes = match Files::detect_format(&bytes, entry_ext) {
+                        Some(format) => Files::read_indexed_meshes(std::io::Cursor::new(bytes), format, options),
+                        None => Err(std::io::Error::new(
+                            std::io::ErrorKind::Other, "Unknown format"
+                        )),
+                    };
+
+                    match entry_meshes {
+                        Ok(entry_meshes) => {
+                            meshes.extend(entry_meshes);
+                        }
+                        Err(err) => {
+                            tracing::warn!("Skip `{}` from archive: {}", entry_name, err);
+                        }
+                    }
+                }
+            }
+            "stl" | "STL" => {
+                for mut mesh in Files::read_stl_meshes(file)? {
+                    if mesh.is_empty() { continue; }
+
+                    // stl repeats vertex per face, weld so faces share edges
+                    if let Some(stl_weld_eps) = options.stl_weld_eps {
+                        let (min, max) = mesh.calculate_aabb();
+                        mesh.weld_vertices((max - min).magnitude() * stl_weld_eps);
+                    }
+                    meshes.push(mesh);
+                }
+            }
+            "obj" | "OBJ" => {
+                for mesh in Files::read_obj_meshes(file, options.is_robust_triangulation)? {
+                    if !mesh.is_empty() {
+                        meshes.push(mesh);
+                    }
+                }
+            }
+            "glb" | "GLB" | "gltf" | "GLTF" => {
+                for mesh in Gltf::read_meshes(file.get_ref().as_ref())? {
+                    if !mesh.is_empty() {
+                        meshes.push(mesh);
+                    }
+                }
+            }
+            _ => {
+                let mesh = Files::read_indexed_mesh(file, ext, options.is_robust_triangulation)?;
+                if !mesh.is_empty() {
+                    meshes.push(mesh);
+                }
+            }
+        }
+
+        // archive entries are flipped by their own reads, negative scale also reverses winding
+        let is_archive = matches!(ext, "zip" | "ZIP");
+        if options.is_flip_handedness && !is_archive {
+            for mesh in meshes.iter_mut() {
+                mesh.transform(Matrix4::from_nonuniform_scale(1.0, 1.0, -1.0));
+            }
+        }
+
+        Ok(meshes)
+    }
+
+    // ascii stl may hold several solids, each becomes separate mesh named after its solid
+    fn read_stl_meshes<T>(file: std::io::Cursor<T>) -> Result<Vec<IndexedMesh>, std::io::Error>
+    where
+        T: std::convert::AsRef<[u8]>,
+    {
+        let text = std::str::from_utf8(file.get_ref().as_ref())
+            .ok()
+            .filter(|text| text.trim_start().starts_with("solid"));
+
+        let mut meshes = vec![];
+        if let Some(text) = text {
+            let mut solid = String::new();
+            let mut solid_name = String::new();
+
+            for line in text.lines() {
+                let line = line.trim();
+
+                let is_solid_start = line.strip_prefix("solid")
+                    .map_or(false, |rest| rest.is_empty() || rest.starts_with(char::is_whitespace));
+                if is_solid_start {
+                    solid_name = line["solid".len()..].trim().to_string();
+                    // reader requires space after `solid` even for unnamed solids
+                    solid = format!("solid {}\n", solid_name);
+                    continue;
+                }
+
+                solid.push_str(line);
+                solid.push('\n');
+
+                // every solid is read and welded on its own
+                if line.starts_with("endsolid") {
+                    let mut mesh = Files::read_indexed_mesh(std::io::Cursor::new(solid.as_bytes()), "stl", false)?;
+                    mesh.name = solid_name.clone();
+                    meshes.push(mesh);
+                    solid.clear();
+                }
+            }
+        }
+
+        // binary stl, header may also start with `solid`
+        if meshes.is_empty() {
+            meshes.push(Files::read_indexed_mesh(file, "stl", false)?);
+        }
+
+        Ok(meshes)
+    }
+
+    // every `o`/`g` group becomes separate mesh, faces before the first group go to default one
+    fn read_obj_meshes<T>(
+        file: std::io::Cursor<T>,
+        is_robust_triangulation: bool
+    ) -> Result<Vec<IndexedMesh>, std::io::Error>
+    where
+        T: std::convert::AsRef<[u8]>,
+    {
+        use std::io::BufRead;
+
+        // corners as (position, normal) indices into global lists
+        struct Group {
+            name: String,
+            corners: Vec<(usize, Option<usize>)>,
+        }
+
+        fn parse_f32(token: Option<&str>) -> Result<f32, std::io::Error> {
+            token
+                .and_then(|token| token.parse::<f32>().ok())
+                .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Bad number in obj"))
+        }
+
+        // obj indices are 1-based, negative ones are relative to the end of the list,
+        // out of range ones map past the end so validation drops their faces
+        fn resolve_index(token: &str, len: usize) -> Option<usize> {
+            let idx = token.parse::<i64>().ok()?;
+            if idx > 0 {
+                Some(idx as usize - 1)
+            } else if idx < 0 && (-idx) as usize <= len {
+                Some(len - (-idx) as usize)
+            } else {
+                Some(usize::MAX)
+            }
+        }
+
+        let mut positions = vec![];
+        let mut normals = vec![];
+        let mut groups = vec![Group { name: String::new(), corners: vec![] }];
+
+        for line in file.lines() {
+            let line = line?;
+            let mut tokens = line.split_whitespace();
+
+            match tokens.next() {
+                Some("v") => {
+                    positions.push(Vector3::new(parse_f32(tokens.next())?, parse_f32(tokens.next())?, parse_f32(tokens.next())?));
+                }
+                Some("vn") => {
+                    normals.push(Vector3::new(parse_f32(tokens.next())?, parse_f32(tokens.next())?, parse_f32(tokens.next())?));
+                }
+                Some("o") | Some("g") => {
+                    let name = tokens.collect::<Vec<_>>().join(" ");
+                    let group = groups.last_mut().unwrap();
+                    if group.corners.is_empty() {
+                        group.name = name;
+                    } else {
+                        groups.push(Group { name, corners: vec![] });
+                    }
+                }
+                Some("f") => {
+                    let mut face = vec![];
+                    for token in tokens {
+                        let mut refs = token.split('/');
+                        let position_idx = refs.next()
+                            .and_then(|idx| resolve_index(idx, positions.len()))
+                            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Bad face in obj"))?;
+                        let normal_idx = refs.nth(1).and_then(|idx| resolve_index(idx, normals.len()));
+                        face.push((position_idx, normal_idx));
+                    }
+
+                    // fan is enough for convex triangles and quads, bigger polygons are ear clipped
+                    let points: Option<Vec<_>> = if face.len() > 4 || is_robust_triangulation {
+                        face.iter().map(|&(position_idx, _)| positions.get(position_idx).copied()).collect()
+                    } else {
+                        None
+                    };
+                    let group = groups.last_mut().unwrap();
+                    match points {
+                        Some(points) => {
+                            group.corners.extend(IndexedMesh::ear_clip(&points).into_iter().map(|k| face[k]));
+                        }
+                        _ => {
+                            for k in 2..face.len() {
+                                group.corners.extend_from_slice(&[face[0], face[k - 1], face[k]]);
+                            }
+                        }
+                    }
+                }
+                // texture coordinates, materials, smoothing groups etc. are not used
+                _ => {}
+            }
+        }
+
+        let mut meshes = vec![];
+        for group in groups {
+            if group.corners.is_empty() { continue; }
+
+            let mut mesh = IndexedMesh {
+                name: group.name,
+                ..Default::default()
+            };
+
+            let has_normals = group.corners.iter().all(|&(_, normal_idx)| normal_idx.map_or(false, |idx| idx < normals.len()));
+
+            // only vertices used by the group are copied, with indices remapped to local ones
+            let mut local_indices = std::collections::HashMap::new();
+            for corner in group.corners {
+                let (position_idx, normal_idx) = if has_normals { corner } else { (corner.0, None) };
+
+                let local_idx = match positions.get(position_idx) {
+                    Some(&position) => *local_indices.entry((position_idx, normal_idx)).or_insert_with(|| {
+                        mesh.positions.push(position);
+                        if let Some(normal_idx) = normal_idx {
+                            mesh.normals.push(normals[normal_idx]);
+                        }
+                        mesh.positions.len() as u32 - 1
+                    }),
+                    None => std::u32::MAX,
+                };
+                mesh.indices.push(local_idx);
+            }
+
+            mesh.normals_dirty = !has_normals;
+
+            let dropped_faces = mesh.validate();
+            if dropped_faces > 0 {
+                tracing::warn!("Dropped {} faces with out of range indices", dropped_faces);
+            }
+            let unused_vertices = mesh.remove_unused_vertices();
+            if unused_vertices > 0 {
+                tracing::info!("Removed {} vertices not used by any face", unused_vertices);
+            }
+
+            meshes.push(mesh);
+        }
+
+        Ok(meshes)
+    }
+
+    fn read_indexed_mesh<T>(
+        mut file: std::io::Cursor<T>,
+        ext: &str,
+        is_robust_triangulation: bool
+    ) -> Result<IndexedMesh, std::io::Error>
+    where
+        T: std::convert::AsRef<[u8]>,
+    {
+        let mut mesh = match ext {
             "stl" | "STL" => {
                 let mut stl = stl_io::create_stl_reader(&mut file)?;
                 let stl_indexed_mesh = stl.as_indexed_triangles()?;
 
-                let mut mesh = IndexedMesh {
+                IndexedMesh {
+                    name: String::new(),
+
                     positions: stl_indexed_mesh.vertices
                         .into_iter()
                         .map(|vertex| Vector3::new(vertex[0], vertex[1], vertex[2]))
@@ -608,89 +3872,267 @@ impl Files {
                             [face.vertices[0] as u32, face.vertices[1] as u32, face.vertices[2] as u32]
                         )
                         .collect(),
-                };
-                mesh.recalculate_normals();
-                Ok(mesh)
+                    occlusion: vec![],
+                    face_colors: vec![],
+                    aabb_tree: None,
+                    info: None,
+
+                    normals_dirty: true,
+                }
             }
             "ply" | "PLY" => {
                 use ply_rs::*;
 
                 struct Vertex {
                     v: [f32; 3],
+                    n: [f32; 3],
+                    normal_components: u8,
                 }
                 struct Face {
                     vertices: Vec<i32>,
+                    color: [f32; 3],
+                    color_components: u8,
                 }
 
                 impl ply::PropertyAccess for Vertex {
                     fn new() -> Self {
-                        Vertex { v: [0.0, 0.0, 0.0] }
+                        Vertex { v: [0.0, 0.0, 0.0], n: [0.0, 0.0, 0.0], normal_components: 0 }
                     }
                     fn set_property(&mut self, key: String, property: ply::Property) {
                         match (key.as_ref(), property) {
                             ("x", ply::Property::Float(v)) => self.v[0] = v,
                             ("y", ply::Property::Float(v)) => self.v[1] = v,
                             ("z", ply::Property::Float(v)) => self.v[2] = v,
+                            ("nx", ply::Property::Float(n)) => { self.n[0] = n; self.normal_components += 1; },
+                            ("ny", ply::Property::Float(n)) => { self.n[1] = n; self.normal_components += 1; },
+                            ("nz", ply::Property::Float(n)) => { self.n[2] = n; self.normal_components += 1; },
                             (_, _) => {},
                         }
                     }
                 }
                 impl ply::PropertyAccess for Face {
                     fn new() -> Self {
-                        Face { vertices: Vec::new() }
+                        Face { vertices: Vec::new(), color: [0.0, 0.0, 0.0], color_components: 0 }
                     }
                     fn set_property(&mut self, key: String, property: ply::Property) {
-                        match (key.as_ref(), property) {
-                            ("vertex_index", ply::Property::ListInt(vec)) => self.vertices = vec,
-                            ("vertex_indices", ply::Property::ListInt(vec)) => self.vertices = vec,
-                            (_, _) => {},
+                        // labeled datasets store colors on faces, as bytes or as floats in [0, 1]
+                        let component = match key.as_ref() {
+                            "red" => Some(0),
+                            "green" => Some(1),
+                            "blue" => Some(2),
+                            _ => None,
+                        };
+                        if let Some(component) = component {
+                            let value = match property {
+                                ply::Property::UChar(value) => value as f32 / 255.0,
+                                ply::Property::Float(value) => value,
+                                _ => return,
+                            };
+                            self.color[component] = value;
+                            self.color_components += 1;
+                            return;
+                        }
+
+                        let vertices: Vec<i32> = match property {
+                            ply::Property::ListChar(vec) => vec.into_iter().map(|i| i as i32).collect(),
+                            ply::Property::ListUChar(vec) => vec.into_iter().map(|i| i as i32).collect(),
+                            ply::Property::ListShort(vec) => vec.into_iter().map(|i| i as i32).collect(),
+                            ply::Property::ListUShort(vec) => vec.into_iter().map(|i| i as i32).collect(),
+                            ply::Property::ListInt(vec) => vec,
+                            ply::Property::ListUInt(vec) => vec.into_iter().map(|i| i as i32).collect(),
+                            _ => return,
+                        };
+
+                        // unknown integer lists are used only if there is nothing better
+                        match key.to_lowercase().as_ref() {
+                            "vertex_index" | "vertex_indices" | "vertex_indexes" => self.vertices = vertices,
+                            _ => if self.vertices.is_empty() { self.vertices = vertices },
                         }
                     }
                 }
                 let vertex_parser = parser::Parser::<Vertex>::new();
                 let face_parser = parser::Parser::<Face>::new();
+                let skip_parser = parser::Parser::<ply::DefaultElement>::new();
 
                 let header = vertex_parser.read_header(&mut file)?;
 
+                let is_face_name = |name: &str| matches!(name.to_lowercase().as_ref(), "face" | "faces");
+                let has_list_property = |element: &ply::ElementDef| element.properties
+                    .values()
+                    .any(|property| matches!(property.data_type, ply::PropertyType::List(_, _)));
+                let has_face_element = header.elements.values().any(|element| is_face_name(&element.name));
+
+                // big payloads log how far they got, reading blocks the page until it's done
+                const PLY_PROGRESS_MIN_ELEMENTS: usize = 1_000_000;
+                let report_progress = |element: &ply::ElementDef| {
+                    let name = element.name.clone();
+                    let is_big = element.count >= PLY_PROGRESS_MIN_ELEMENTS;
+                    move |fraction: f32| if is_big {
+                        tracing::info!("Reading ply {}: {:.0}%", name, fraction * 100.0);
+                    }
+                };
+
+                // header counts can't be trusted, every element takes at least one byte of what is left
+                let capacity = |file: &std::io::Cursor<T>, cnt: usize| {
+                    let bytes_left = (file.get_ref().as_ref().len() as u64).saturating_sub(file.position());
+                    cnt.min(bytes_left as usize)
+                };
+
                 let mut mesh = IndexedMesh::default();
+                let mut is_faces_loaded = false;
                 for (_ignore_key, element) in &header.elements {
-                    match element.name.as_ref() {
-                        "vertex" => {
-                            mesh.positions = vertex_parser
-                                .read_payload_for_element(&mut file, &element, &header)
-                                .unwrap()
-                                .into_iter()
-                                .map(|vertex| Vector3::new(vertex.v[0], vertex.v[1], vertex.v[2]))
-                                .collect();
-                            },
-                        "face" => {
-                            let ply_faces = face_parser
-                                .read_payload_for_element(&mut file, &element, &header)
-                                .unwrap();
-
-                            for face in ply_faces {
-                                for face_idx in (0..face.vertices.len()).into_iter().step_by(2) {
-                                    mesh.indices.extend_from_slice(&[
-                                        face.vertices[face_idx + 0] as u32,
-                                        face.vertices[(face_idx + 1) % face.vertices.len()] as u32,
-                                        face.vertices[(face_idx + 2) % face.vertices.len()] as u32
-                                    ]);
+                    let is_vertex = matches!(element.name.to_lowercase().as_ref(), "vertex" | "vertices");
+                    let is_face = !is_faces_loaded && !is_vertex && if has_face_element {
+                        is_face_name(&element.name)
+                    } else {
+                        has_list_property(element)
+                    };
+
+                    match (is_vertex, is_face) {
+                        (true, _) => {
+                            // sized from header counts, so big files don't reallocate while growing
+                            mesh.positions = Vec::with_capacity(capacity(&file, element.count));
+                            let mut normals = Vec::with_capacity(capacity(&file, element.count));
+                            let mut has_normals = true;
+
+                            Files::read_ply_elements(&vertex_parser, &mut file, element, &header, report_progress(element), |vertex| {
+                                mesh.positions.push(Vector3::new(vertex.v[0], vertex.v[1], vertex.v[2]));
+                                has_normals &= vertex.normal_components == 3;
+                                if has_normals {
+                                    normals.push(Vector3::new(vertex.n[0], vertex.n[1], vertex.n[2]));
                                 }
+                            })?;
+
+                            if has_normals {
+                                mesh.normals = normals;
+                            }
+                            },
+                        (_, true) => {
+                            is_faces_loaded = true;
+
+                            // mostly triangles, polygons grow it further
+                            mesh.indices = Vec::with_capacity(capacity(&file, element.count.checked_mul(3).unwrap_or(std::usize::MAX)));
+                            let is_face_colors = ["red", "green", "blue"]
+                                .iter()
+                                .all(|&component| element.properties.contains_key(component));
+                            if is_face_colors {
+                                mesh.face_colors = Vec::with_capacity(capacity(&file, element.count));
                             }
+
+                            Files::read_ply_elements(&face_parser, &mut file, element, &header, report_progress(element), |face| {
+                                // every triangle of the polygon gets its color
+                                let indices_cnt = mesh.indices.len();
+                                let face_color = Vector3::new(face.color[0], face.color[1], face.color[2]);
+                                let mut is_clipped = false;
+
+                                // concave polygons need ear clipping, out of range indices are left for validation
+                                if face.vertices.len() > 4 || is_robust_triangulation {
+                                    let points: Option<Vec<_>> = face.vertices
+                                        .iter()
+                                        .map(|&idx| mesh.positions.get(idx as usize).copied())
+                                        .collect();
+                                    if let Some(points) = points {
+                                        mesh.indices.extend(IndexedMesh::ear_clip(&points).into_iter().map(|k| face.vertices[k] as u32));
+                                        is_clipped = true;
+                                    }
+                                }
+
+                                if !is_clipped {
+                                    for k in 2..face.vertices.len() {
+                                        mesh.indices.extend_from_slice(&[
+                                            face.vertices[0] as u32,
+                                            face.vertices[k - 1] as u32,
+                                            face.vertices[k] as u32
+                                        ]);
+                                    }
+                                }
+
+                                if is_face_colors {
+                                    let triangles_cnt = (mesh.indices.len() - indices_cnt) / 3;
+                                    mesh.face_colors.resize(mesh.face_colors.len() + triangles_cnt, face_color);
+                                }
+                            })?;
+                        },
+                        // still consume payload to keep the reader in sync
+                        _ => {
+                            Files::read_ply_elements(&skip_parser, &mut file, element, &header, |_| {}, |_| {})?;
                         },
-                        _ => {},
                     }
                 }
 
-                mesh.recalculate_normals();
-                Ok(mesh)
+                // input bytes aren't needed anymore, don't hold them through validation
+                drop(file);
+
+                // keep stored normals when file has them for every vertex
+                mesh.normals_dirty = mesh.normals.len() != mesh.positions.len();
+                mesh
+            }
+            // groups are read apart by read_obj_meshes, here they end up in one mesh
+            "obj" | "OBJ" => {
+                let mut meshes = Files::read_obj_meshes(file, is_robust_triangulation)?;
+                if meshes.len() == 1 {
+                    meshes.pop().unwrap()
+                } else {
+                    IndexedMesh::merge(&meshes.iter().collect::<Vec<_>>())
+                }
             }
             _ => {
-                Err(std::io::Error::new(
+                return Err(std::io::Error::new(
                     std::io::ErrorKind::Other, format!("Not supported format `{}`", ext)
-                ))
+                ));
              }
+        };
+
+        let dropped_faces = mesh.validate();
+        if dropped_faces > 0 {
+            tracing::warn!("Dropped {} faces with out of range indices", dropped_faces);
+        }
+        let unused_vertices = mesh.remove_unused_vertices();
+        if unused_vertices > 0 {
+            tracing::info!("Removed {} vertices not used by any face", unused_vertices);
+        }
+
+        Ok(mesh)
+    }
+
+    // element by element instead of whole payload vector, so big files don't hold both at once,
+    // progress gets fraction of read elements about ten times
+    fn read_ply_elements<E, T>(
+        parser: &ply_rs::parser::Parser<E>,
+        file: &mut T,
+        element: &ply_rs::ply::ElementDef,
+        header: &ply_rs::ply::Header,
+        mut progress: impl FnMut(f32),
+        mut on_element: impl FnMut(E),
+    ) -> Result<(), std::io::Error>
+    where
+        E: ply_rs::ply::PropertyAccess,
+        T: std::io::BufRead,
+    {
+        use ply_rs::ply::Encoding;
+
+        let mut line = String::new();
+        let mut last_reported = 0;
+        for i in 0..element.count {
+            let ply_element = match header.encoding {
+                Encoding::Ascii => {
+                    line.clear();
+                    file.read_line(&mut line)?;
+                    parser.read_ascii_element(&line, element)?
+                }
+                Encoding::BinaryBigEndian => parser.read_big_endian_element(file, element)?,
+                Encoding::BinaryLittleEndian => parser.read_little_endian_element(file, element)?,
+            };
+            on_element(ply_element);
+
+            let tenths = (i + 1) * 10 / element.count;
+            if tenths > last_reported {
+                last_reported = tenths;
+                progress((i + 1) as f32 / element.count as f32);
+            }
         }
+
+        Ok(())
     }
 
     fn preview_files_being_dropped(ctx: &egui::Context) {
@@ -724,3 +4166,64 @@ impl Files {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ply_counts_past_the_end_are_error() {
+        for count in ["4294967295", "18446744073709551615"] {
+            let ply = format!(
+                "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+                element face {}\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n",
+                count
+            );
+            assert!(Files::read_indexed_mesh(std::io::Cursor::new(ply.into_bytes()), "ply", false).is_err());
+        }
+    }
+
+    #[test]
+    fn ply_face_property_spellings() {
+        for (element, property) in [("face", "vertex_indexes"), ("Face", "Vertex_Indices"), ("polygons", "vertex_index")] {
+            let ply = format!(
+                "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+                element {} 2\nproperty list uchar int {}\nend_header\n0 0 0\n1 0 0\n1 1 0\n0 1 0\n3 0 1 2\n3 0 2 3\n",
+                element, property
+            );
+            let mesh = Files::read_indexed_mesh(std::io::Cursor::new(ply.into_bytes()), "ply", false).unwrap();
+            assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3], "{} {}", element, property);
+        }
+    }
+
+    #[test]
+    fn ply_bad_indices_are_dropped() {
+        let ply = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 3\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n3 0 1 7\n3 -1 1 2\n";
+        let mesh = Files::read_indexed_mesh(std::io::Cursor::new(ply.as_bytes().to_vec()), "ply", false).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        assert_eq!(mesh.positions.len(), 3);
+    }
+
+    #[test]
+    fn fragment_param_keeps_others() {
+        assert_eq!(Files::fragment_with_param("", "camera", "1,2"), "camera=1,2");
+        assert_eq!(Files::fragment_with_param("#model=a.stl", "camera", "1,2"), "model=a.stl&camera=1,2");
+        assert_eq!(
+            Files::fragment_with_param("#model=a.stl&camera=0,0&x", "camera", "1,2"),
+            "model=a.stl&camera=1,2&x"
+        );
+        // key is matched whole, not by prefix
+        assert_eq!(Files::fragment_with_param("#cameras=3", "camera", "1,2"), "cameras=3&camera=1,2");
+    }
+
+    #[test]
+    fn ply_polygon_is_fanned() {
+        let ply = "ply\nformat ascii 1.0\nelement vertex 5\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0\n1 0 0\n1.3 1 0\n0.5 1.6 0\n-0.3 1 0\n5 0 1 2 3 4\n";
+        let mesh = Files::read_indexed_mesh(std::io::Cursor::new(ply.as_bytes().to_vec()), "ply", false).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3, 0, 3, 4]);
+    }
+}
@@ -0,0 +1,201 @@
+use egui::{Key, PointerButton};
+
+// keys offered for binding, names are their debug names so stored strings stay readable
+const BINDABLE_KEYS: [Key; 46] = [
+    Key::Num0, Key::Num1, Key::Num2, Key::Num3, Key::Num4,
+    Key::Num5, Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+    Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::Space, Key::Enter, Key::Home, Key::End, Key::PageUp, Key::PageDown,
+    Key::ArrowUp, Key::ArrowDown, Key::ArrowLeft, Key::ArrowRight,
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum KeyAction {
+    PivotToCenter,
+    Fit,
+    ResetView,
+    Front,
+    Back,
+    Right,
+    Left,
+    Top,
+    Bottom,
+    Iso,
+    Wireframe,
+    Points,
+    FlatShading,
+    Outline,
+    Clipping,
+}
+
+impl KeyAction {
+    pub const ALL: [KeyAction; 15] = [
+        KeyAction::PivotToCenter,
+        KeyAction::Fit,
+        KeyAction::ResetView,
+        KeyAction::Front,
+        KeyAction::Back,
+        KeyAction::Right,
+        KeyAction::Left,
+        KeyAction::Top,
+        KeyAction::Bottom,
+        KeyAction::Iso,
+        KeyAction::Wireframe,
+        KeyAction::Points,
+        KeyAction::FlatShading,
+        KeyAction::Outline,
+        KeyAction::Clipping,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyAction::PivotToCenter => "pivot to center",
+            KeyAction::Fit => "fit",
+            KeyAction::ResetView => "reset view",
+            KeyAction::Front => "front view",
+            KeyAction::Back => "back view",
+            KeyAction::Right => "right view",
+            KeyAction::Left => "left view",
+            KeyAction::Top => "top view",
+            KeyAction::Bottom => "bottom view",
+            KeyAction::Iso => "iso view",
+            KeyAction::Wireframe => "toggle wireframe",
+            KeyAction::Points => "toggle points",
+            KeyAction::FlatShading => "toggle flat shading",
+            KeyAction::Outline => "toggle outlines",
+            KeyAction::Clipping => "toggle clipping",
+        }
+    }
+
+    // stored names, kept apart from labels so relabeling doesn't lose saved bindings
+    fn storage_name(&self) -> &'static str {
+        match self {
+            KeyAction::PivotToCenter => "pivot_to_center",
+            KeyAction::Fit => "fit",
+            KeyAction::ResetView => "reset_view",
+            KeyAction::Front => "front",
+            KeyAction::Back => "back",
+            KeyAction::Right => "right",
+            KeyAction::Left => "left",
+            KeyAction::Top => "top",
+            KeyAction::Bottom => "bottom",
+            KeyAction::Iso => "iso",
+            KeyAction::Wireframe => "wireframe",
+            KeyAction::Points => "points",
+            KeyAction::FlatShading => "flat_shading",
+            KeyAction::Outline => "outline",
+            KeyAction::Clipping => "clipping",
+        }
+    }
+}
+
+// mouse buttons for navigation and keys for actions, None leaves it unbound
+#[derive(Clone, PartialEq)]
+pub struct InputBindings {
+    pub orbit_button: Option<PointerButton>,
+    pub pan_button: Option<PointerButton>,
+    // vertical drag zooms, wheel zooms always
+    pub zoom_button: Option<PointerButton>,
+    pub keys: Vec<(KeyAction, Option<Key>)>,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        let key = |action| match action {
+            KeyAction::PivotToCenter => Some(Key::C),
+            KeyAction::Fit => Some(Key::F),
+            KeyAction::ResetView => Some(Key::Home),
+            KeyAction::Front => Some(Key::Num1),
+            KeyAction::Right => Some(Key::Num3),
+            KeyAction::Top => Some(Key::Num7),
+            KeyAction::Iso => Some(Key::Num5),
+            KeyAction::Wireframe => Some(Key::W),
+            _ => None,
+        };
+
+        Self {
+            orbit_button: Some(PointerButton::Middle),
+            pan_button: Some(PointerButton::Secondary),
+            zoom_button: None,
+            keys: KeyAction::ALL.iter().map(|&action| (action, key(action))).collect(),
+        }
+    }
+}
+
+impl InputBindings {
+    pub fn bindable_keys() -> &'static [Key] {
+        &BINDABLE_KEYS
+    }
+
+    pub fn key_name(key: Option<Key>) -> String {
+        match key {
+            Some(key) => format!("{:?}", key).trim_start_matches("Num").to_string(),
+            None => "none".to_string(),
+        }
+    }
+
+    pub fn button_name(button: Option<PointerButton>) -> &'static str {
+        match button {
+            Some(PointerButton::Primary) => "left",
+            Some(PointerButton::Secondary) => "right",
+            Some(PointerButton::Middle) => "middle",
+            None => "none",
+        }
+    }
+
+    // actions of keys pressed this frame
+    pub fn pressed_actions(&self, input: &egui::InputState) -> Vec<KeyAction> {
+        self.keys
+            .iter()
+            .filter(|(_, key)| key.map_or(false, |key| input.key_pressed(key)))
+            .map(|&(action, _)| action)
+            .collect()
+    }
+
+    // `name=value` pairs split by `;`, unknown or broken pairs keep defaults
+    pub fn to_storage_string(&self) -> String {
+        let mut pairs = vec![
+            format!("orbit={}", InputBindings::button_name(self.orbit_button)),
+            format!("pan={}", InputBindings::button_name(self.pan_button)),
+            format!("zoom={}", InputBindings::button_name(self.zoom_button)),
+        ];
+        pairs.extend(self.keys.iter().map(|(action, key)| {
+            let key_name = key.map_or("none".to_string(), |key| format!("{:?}", key));
+            format!("{}={}", action.storage_name(), key_name)
+        }));
+
+        pairs.join(";")
+    }
+
+    pub fn from_storage_string(s: &str) -> Self {
+        let parse_button = |value: &str| match value {
+            "left" => Some(Some(PointerButton::Primary)),
+            "right" => Some(Some(PointerButton::Secondary)),
+            "middle" => Some(Some(PointerButton::Middle)),
+            "none" => Some(None),
+            _ => None,
+        };
+        let parse_key = |value: &str| match value {
+            "none" => Some(None),
+            _ => BINDABLE_KEYS.iter().find(|key| format!("{:?}", key) == value).map(|&key| Some(key)),
+        };
+
+        let mut bindings = InputBindings::default();
+        for (name, value) in s.split(';').filter_map(|pair| pair.split_once('=')) {
+            match name {
+                "orbit" => if let Some(button) = parse_button(value) { bindings.orbit_button = button },
+                "pan" => if let Some(button) = parse_button(value) { bindings.pan_button = button },
+                "zoom" => if let Some(button) = parse_button(value) { bindings.zoom_button = button },
+                _ => {
+                    let binding = bindings.keys.iter_mut().find(|(action, _)| action.storage_name() == name);
+                    if let (Some(binding), Some(key)) = (binding, parse_key(value)) {
+                        binding.1 = key;
+                    }
+                }
+            }
+        }
+
+        bindings
+    }
+}
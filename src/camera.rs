@@ -1,5 +1,54 @@
 use cgmath::*;
 
+#[derive(Clone, Copy, PartialEq)]
+pub struct CameraState {
+    pub center: Vector3<f32>,
+    pub dir_from_center: Vector3<f32>,
+    pub up: Vector3<f32>,
+    pub dist: f32,
+}
+
+impl CameraState {
+    fn rotation(&self) -> Quaternion<f32> {
+        let right = self.up.cross(self.dir_from_center).normalize();
+        let up = self.dir_from_center.cross(right).normalize();
+        Quaternion::from(Matrix3::from_cols(right, up, self.dir_from_center.normalize()))
+    }
+
+    pub fn interpolate(&self, other: &CameraState, t: f32) -> CameraState {
+        let rotation = self.rotation().slerp(other.rotation(), t);
+        CameraState {
+            center: self.center.lerp(other.center, t),
+            dir_from_center: rotation * Vector3::unit_z(),
+            up: rotation * Vector3::unit_y(),
+            dist: self.dist + (other.dist - self.dist) * t,
+        }
+    }
+}
+
+pub struct CameraTransition {
+    from: CameraState,
+    to: CameraState,
+    elapsed: f32,
+    duration: f32,
+}
+
+impl CameraTransition {
+    pub fn new(from: CameraState, to: CameraState, duration: f32) -> Self {
+        Self { from, to, elapsed: 0.0, duration }
+    }
+
+    // returns false when transition is finished
+    pub fn step(&mut self, camera: &mut OrbitalCamera, dt: f32) -> bool {
+        self.elapsed += dt;
+        let t = (self.elapsed / self.duration).min(1.0);
+        let eased = t * t * (3.0 - 2.0 * t);
+
+        camera.set_state(&self.from.interpolate(&self.to, eased));
+        t < 1.0
+    }
+}
+
 #[derive(Clone)]
 pub struct OrbitalCamera {
     render_width: f32,
@@ -8,6 +57,8 @@ pub struct OrbitalCamera {
     pub fov: f32,
     pub near: f32,
     pub far: f32,
+    // parallel projection, view keeps the size it has at center in perspective
+    pub is_orthographic: bool,
 
     pub up: Vector3<f32>,
     pub center: Vector3<f32>,
@@ -24,8 +75,28 @@ impl OrbitalCamera {
         ) 
     }
 
+    pub fn calculate_orthographic_matrix(&self) -> Matrix4<f32> {
+        let half_height = self.dist * Deg(self.fov / 2.0).tan();
+        let half_width = half_height * self.render_width / self.render_height;
+        ortho(-half_width, half_width, -half_height, half_height, self.near, self.far)
+    }
+
+    pub fn calculate_projection_matrix(&self) -> Matrix4<f32> {
+        if self.is_orthographic {
+            self.calculate_orthographic_matrix()
+        } else {
+            self.calculate_perspective_matrix()
+        }
+    }
+
     pub fn calculate_view_matrix(&self) -> Matrix4<f32> {
-        let eye_pos = self.calculate_pos();
+        // parallel projection doesn't depend on eye distance, so eye is pulled back
+        // for near plane to stay in front of the scene however close dist zooms in
+        let eye_pos = if self.is_orthographic {
+            self.center + self.dir_from_center * self.dist.max(self.far * 0.5)
+        } else {
+            self.calculate_pos()
+        };
         Matrix4::look_to_rh(Point3::from_vec(eye_pos), -self.dir_from_center, self.up)
     }
 
@@ -34,9 +105,138 @@ impl OrbitalCamera {
         self.render_height = height;
     }
 
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            center: self.center,
+            dir_from_center: self.dir_from_center,
+            up: self.up,
+            dist: self.dist,
+        }
+    }
+
+    pub fn set_state(&mut self, state: &CameraState) {
+        self.center = state.center;
+        self.dir_from_center = state.dir_from_center;
+        self.up = state.up;
+        self.dist = state.dist;
+    }
+
     pub fn calculate_pos(&self) -> Vector3<f32> {
         self.center + self.dir_from_center * self.dist
     }
+
+    // world space ray through point in normalized device coordinates
+    pub fn calculate_ray(&self, ndc_x: f32, ndc_y: f32) -> (Vector3<f32>, Vector3<f32>) {
+        let inv_proj_view = (self.calculate_projection_matrix() * self.calculate_view_matrix())
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+
+        let near = inv_proj_view * Vector4::new(ndc_x, ndc_y, -1.0, 1.0);
+        let far = inv_proj_view * Vector4::new(ndc_x, ndc_y, 1.0, 1.0);
+        let near = near.truncate() / near.w;
+        let far = far.truncate() / far.w;
+
+        (near, (far - near).normalize())
+    }
+
+    // distance from center at which sphere of radius fits the viewport from any direction
+    pub fn calculate_fit_dist(&self, radius: f32) -> f32 {
+        let half_fov_y = Deg(self.fov / 2.0);
+        let mut half_fov = Rad::from(half_fov_y);
+
+        let aspect = self.render_width / self.render_height;
+        if aspect.is_finite() && aspect < 1.0 {
+            half_fov = Rad::atan(half_fov_y.tan() * aspect);
+        }
+
+        radius / half_fov.sin()
+    }
+
+    // clip planes follow scene size, so tiny cad parts aren't cut by near plane and huge scans by far one,
+    // their ratio is kept for depth precision, empty scene keeps the previous ones
+    pub fn fit_clip_planes(&mut self, radius: f32) {
+        if radius > 0.0 {
+            self.near = radius * 0.01;
+            self.far = radius * 100.0;
+        }
+    }
+
+    // virtual trackball, points are in pixels from viewport center with y up
+    pub fn rotate_trackball(&mut self, from: Vector2<f32>, to: Vector2<f32>) {
+        let radius = self.render_width.min(self.render_height) / 2.0;
+        if radius <= 0.0 { return; }
+
+        // sphere near center, hyperbolic sheet outside so rotation stays continuous
+        let project = |p: Vector2<f32>| {
+            let p = p / radius;
+            let len2 = p.magnitude2();
+            let z = if len2 <= 0.5 { (1.0 - len2).sqrt() } else { 0.5 / len2.sqrt() };
+            Vector3::new(p.x, p.y, z).normalize()
+        };
+        let (p0, p1) = (project(from), project(to));
+
+        let axis = p0.cross(p1);
+        if axis.magnitude2() <= std::f32::EPSILON * std::f32::EPSILON { return; }
+        let angle = Rad(p0.dot(p1).clamp(-1.0, 1.0).acos());
+
+        // axis from camera space to world, camera turns opposite to the dragged model
+        let right = self.up.cross(self.dir_from_center).normalize();
+        let up = self.dir_from_center.cross(right).normalize();
+        let axis_world = (right * axis.x + up * axis.y + self.dir_from_center * axis.z).normalize();
+
+        let rotation = Matrix3::from_axis_angle(axis_world, -angle);
+        self.dir_from_center = (rotation * self.dir_from_center).normalize();
+        self.up = (rotation * up).normalize();
+    }
+
+    // compact comma separated form for sharing views: center, dir, up, dist, fov
+    pub fn to_url_string(&self) -> String {
+        let values = [
+            self.center.x, self.center.y, self.center.z,
+            self.dir_from_center.x, self.dir_from_center.y, self.dir_from_center.z,
+            self.up.x, self.up.y, self.up.z,
+            self.dist, self.fov,
+        ];
+        values.iter().map(|value| format!("{:.5}", value)).collect::<Vec<_>>().join(",")
+    }
+
+    // leaves camera untouched and returns false if string is malformed
+    pub fn set_from_url_string(&mut self, url_string: &str) -> bool {
+        let values: Vec<f32> = url_string
+            .split(',')
+            .filter_map(|value| value.parse::<f32>().ok())
+            .filter(|value| value.is_finite())
+            .collect();
+        if values.len() != 11 { return false; }
+
+        let dir_from_center = Vector3::new(values[3], values[4], values[5]);
+        let up = Vector3::new(values[6], values[7], values[8]);
+        if dir_from_center.magnitude2() == 0.0 || up.magnitude2() == 0.0 || values[9] <= 0.0 {
+            return false;
+        }
+
+        self.center = Vector3::new(values[0], values[1], values[2]);
+        self.dir_from_center = dir_from_center.normalize();
+        self.up = up.normalize();
+        self.dist = values[9];
+        self.fov = values[10].clamp(20.0, 90.0);
+
+        true
+    }
+
+    // move orbit center without moving the eye
+    pub fn set_pivot(&mut self, pivot: Vector3<f32>) {
+        let to_eye = self.calculate_pos() - pivot;
+        let dist = to_eye.magnitude();
+        if dist <= std::f32::EPSILON { return; }
+
+        self.center = pivot;
+        self.dist = dist;
+        self.dir_from_center = to_eye / dist;
+
+        let right = self.up.cross(self.dir_from_center).normalize();
+        self.up = self.dir_from_center.cross(right).normalize();
+    }
 }
 
 impl Default for OrbitalCamera {
@@ -48,6 +248,7 @@ impl Default for OrbitalCamera {
             fov: 60.0f32,
             near: 1.0f32,
             far: 1_000.0f32,
+            is_orthographic: false,
 
             up: Vector3::new(0.0f32, 1.0, 0.0),
             center: Vector3::new(0.0f32, 0.0, 0.0),
@@ -56,3 +257,43 @@ impl Default for OrbitalCamera {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orthographic_zoom_keeps_scene_in_front_of_near_plane() {
+        let mut camera = OrbitalCamera { near: 0.01, far: 100.0, is_orthographic: true, dist: 0.1, ..Default::default() };
+        camera.set_size(800.0, 600.0);
+
+        // nearest and farthest points of unit sphere scene around center
+        let proj_view = camera.calculate_projection_matrix() * camera.calculate_view_matrix();
+        for p in [camera.center + camera.dir_from_center, camera.center - camera.dir_from_center] {
+            let clip = proj_view * p.extend(1.0);
+            assert!((-1.0..=1.0).contains(&(clip.z / clip.w)));
+        }
+
+        // pulled back eye doesn't change what center pixel ray hits
+        let (origin, dir) = camera.calculate_ray(0.0, 0.0);
+        assert!((dir + camera.dir_from_center).magnitude() < 1e-5);
+        assert!((origin - camera.center).cross(dir).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn tiny_scene_fits_between_clip_planes() {
+        let scale = 0.01;
+        let mesh = crate::mesh::IndexedMesh::box3d(Vector3::new(scale, scale, scale));
+        let (_center, radius) = mesh.bounding_sphere();
+
+        let mut camera = OrbitalCamera::default();
+        camera.fit_clip_planes(radius);
+        assert!(camera.near < scale);
+        camera.dist = camera.calculate_fit_dist(radius);
+        assert!(camera.dist - radius > camera.near && camera.dist + radius < camera.far);
+
+        // empty scene keeps the previous planes
+        camera.fit_clip_planes(0.0);
+        assert_eq!(camera.near, radius * 0.01);
+    }
+}
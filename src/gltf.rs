@@ -0,0 +1,880 @@
+use std::borrow::Cow;
+
+use cgmath::*;
+
+use crate::mesh::IndexedMesh;
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+// deeper json is surely broken, and recursion must not overflow the stack
+const MAX_JSON_DEPTH: usize = 64;
+
+// primitive modes and accessor component types from the spec
+const MODE_TRIANGLES: usize = 4;
+const MODE_TRIANGLE_STRIP: usize = 5;
+const MODE_TRIANGLE_FAN: usize = 6;
+const COMPONENT_U8: usize = 5121;
+const COMPONENT_U16: usize = 5123;
+const COMPONENT_U32: usize = 5125;
+const COMPONENT_F32: usize = 5126;
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+// just enough json for gltf documents, object members keep file order
+enum Json {
+    // true, false and null, geometry never needs them
+    Literal,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Json::Number(value) if value >= 0.0 && value.fract() == 0.0 && value <= std::u32::MAX as f64 => Some(value as usize),
+            _ => None,
+        }
+    }
+
+    fn get_usize(&self, key: &str) -> Option<usize> {
+        self.get(key).and_then(Json::as_usize)
+    }
+
+    // missing or not array gives no items
+    fn items(&self, key: &str) -> &[Json] {
+        match self.get(key) {
+            Some(Json::Array(items)) => items,
+            _ => &[],
+        }
+    }
+
+    // numbers of array with exactly len items
+    fn get_f32s(&self, key: &str, len: usize) -> Option<Vec<f32>> {
+        let values: Option<Vec<f32>> = self.items(key)
+            .iter()
+            .map(|item| match item {
+                Json::Number(value) => Some(*value as f32),
+                _ => None,
+            })
+            .collect();
+        values.filter(|values| values.len() == len)
+    }
+}
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn parse(bytes: &'a [u8]) -> std::io::Result<Json> {
+        let mut parser = JsonParser { bytes, pos: 0 };
+        let value = parser.parse_value(0)?;
+
+        // glb pads json chunk with spaces, some writers with zeros
+        parser.skip_whitespace();
+        if parser.bytes[parser.pos..].iter().any(|&b| b != 0) {
+            return Err(invalid_data("Data after json document"));
+        }
+        Ok(value)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.bytes.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &[u8]) -> std::io::Result<()> {
+        if !self.bytes[self.pos..].starts_with(literal) {
+            return Err(invalid_data("Bad json"));
+        }
+        self.pos += literal.len();
+        Ok(())
+    }
+
+    fn parse_value(&mut self, depth: usize) -> std::io::Result<Json> {
+        if depth > MAX_JSON_DEPTH {
+            return Err(invalid_data("Json nested too deep"));
+        }
+
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = vec![];
+                self.skip_whitespace();
+                if self.bytes.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let name = self.parse_string()?;
+                    self.skip_whitespace();
+                    self.expect(b":")?;
+                    members.push((name, self.parse_value(depth + 1)?));
+
+                    self.skip_whitespace();
+                    match self.bytes.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(members));
+                        }
+                        _ => return Err(invalid_data("Bad json object")),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = vec![];
+                self.skip_whitespace();
+                if self.bytes.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.parse_value(depth + 1)?);
+
+                    self.skip_whitespace();
+                    match self.bytes.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => return Err(invalid_data("Bad json array")),
+                    }
+                }
+            }
+            Some(b'"') => Ok(Json::String(self.parse_string()?)),
+            Some(b't') => self.expect(b"true").map(|_| Json::Literal),
+            Some(b'f') => self.expect(b"false").map(|_| Json::Literal),
+            Some(b'n') => self.expect(b"null").map(|_| Json::Literal),
+            Some(_) => {
+                let start = self.pos;
+                while matches!(self.bytes.get(self.pos), Some(b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E')) {
+                    self.pos += 1;
+                }
+                std::str::from_utf8(&self.bytes[start..self.pos])
+                    .ok()
+                    .and_then(|number| number.parse::<f64>().ok())
+                    .map(Json::Number)
+                    .ok_or_else(|| invalid_data("Bad json number"))
+            }
+            None => Err(invalid_data("Unexpected end of json")),
+        }
+    }
+
+    fn parse_string(&mut self) -> std::io::Result<String> {
+        self.expect(b"\"")?;
+
+        let mut bytes = vec![];
+        loop {
+            match self.bytes.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'\\') => {
+                    let escaped = self.bytes.get(self.pos + 1).copied();
+                    self.pos += 2;
+                    let c = match escaped {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut code = self.parse_hex4()?;
+                            // characters outside of basic plane come as surrogate pairs
+                            if (0xD800..0xDC00).contains(&code) && self.bytes[self.pos..].starts_with(b"\\u") {
+                                self.pos += 2;
+                                let low = self.parse_hex4()?;
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return Err(invalid_data("Bad json escape")),
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0u8; 4]).as_bytes());
+                }
+                Some(&b) => {
+                    bytes.push(b);
+                    self.pos += 1;
+                }
+                None => return Err(invalid_data("Unterminated json string")),
+            }
+        }
+
+        String::from_utf8(bytes).map_err(|_| invalid_data("Json string is not utf-8"))
+    }
+
+    fn parse_hex4(&mut self) -> std::io::Result<u32> {
+        let code = self.bytes
+            .get(self.pos..self.pos + 4)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u32::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())
+            .ok_or_else(|| invalid_data("Bad json escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+// glTF 2.0 reader and glb writer for geometry only, without dependencies
+pub struct Gltf {}
+impl Gltf {
+    // mesh of every node in the scene with node transforms applied, triangle primitives of a mesh are merged,
+    // strips and fans are converted to triangles, other primitive modes, materials and textures are ignored
+    pub fn read_meshes(bytes: &[u8]) -> std::io::Result<Vec<IndexedMesh>> {
+        let (document, bin) = if bytes.starts_with(GLB_MAGIC) {
+            Gltf::split_glb(bytes)?
+        } else {
+            (JsonParser::parse(bytes)?, None)
+        };
+
+        // buffer without uri is the bin chunk of glb, .gltf can only bring its buffers as data uris
+        let buffers: Vec<Option<Cow<[u8]>>> = document.items("buffers")
+            .iter()
+            .enumerate()
+            .map(|(i, buffer)| match buffer.get("uri").and_then(Json::as_str) {
+                Some(uri) => Gltf::decode_data_uri(uri).map(Cow::Owned),
+                None if i == 0 => bin.map(Cow::Borrowed),
+                None => None,
+            })
+            .collect();
+
+        let nodes = document.items("nodes");
+        let roots: Vec<usize> = match document.items("scenes").get(document.get_usize("scene").unwrap_or(0)) {
+            Some(scene) => scene.items("nodes").iter().filter_map(Json::as_usize).collect(),
+            // without scenes every node which isn't a child is a root
+            None => {
+                let is_child: std::collections::HashSet<usize> = nodes
+                    .iter()
+                    .flat_map(|node| node.items("children").iter().filter_map(Json::as_usize))
+                    .collect();
+                (0..nodes.len()).filter(|i| !is_child.contains(i)).collect()
+            }
+        };
+
+        let mut meshes = vec![];
+        // nodes form a tree by the spec, visited ones are skipped so broken files can't loop
+        let mut is_visited = vec![false; nodes.len()];
+        let mut stack: Vec<(usize, Matrix4<f32>)> = roots.into_iter().rev().map(|i| (i, Matrix4::identity())).collect();
+        while let Some((node_idx, parent_matrix)) = stack.pop() {
+            let node = match nodes.get(node_idx) {
+                Some(node) if !is_visited[node_idx] => node,
+                _ => continue,
+            };
+            is_visited[node_idx] = true;
+
+            let matrix = parent_matrix * Gltf::node_matrix(node);
+            if let Some(mesh_idx) = node.get_usize("mesh") {
+                let mut mesh = Gltf::read_mesh(&document, &buffers, mesh_idx)?;
+                if let Some(name) = node.get("name").and_then(Json::as_str) {
+                    mesh.name = name.to_string();
+                }
+                mesh.transform(matrix);
+
+                let dropped_faces = mesh.validate();
+                if dropped_faces > 0 {
+                    tracing::warn!("Dropped {} faces with out of range indices", dropped_faces);
+                }
+                let unused_vertices = mesh.remove_unused_vertices();
+                if unused_vertices > 0 {
+                    tracing::info!("Removed {} vertices not used by any face", unused_vertices);
+                }
+                meshes.push(mesh);
+            }
+
+            let children = node.items("children").iter().rev().filter_map(Json::as_usize);
+            stack.extend(children.map(|child| (child, matrix)));
+        }
+
+        Ok(meshes)
+    }
+
+    // json document and bin chunk, chunk lengths include their padding
+    fn split_glb(bytes: &[u8]) -> std::io::Result<(Json, Option<&[u8]>)> {
+        let read_u32 = |pos: usize| bytes.get(pos..pos + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+        if read_u32(4) != Some(2) {
+            return Err(invalid_data("Only glTF 2.0 is supported"));
+        }
+
+        let end = (read_u32(8).unwrap_or(0) as usize).min(bytes.len());
+        let mut json = None;
+        let mut bin = None;
+        let mut pos = 12;
+        while pos + 8 <= end {
+            let chunk_len = read_u32(pos).unwrap() as usize;
+            let chunk_type = read_u32(pos + 4).unwrap();
+            let chunk = (pos + 8)
+                .checked_add(chunk_len)
+                .and_then(|chunk_end| bytes.get(pos + 8..chunk_end))
+                .ok_or_else(|| invalid_data("Glb chunk past the end of file"))?;
+
+            match chunk_type {
+                GLB_CHUNK_JSON if json.is_none() => json = Some(chunk),
+                GLB_CHUNK_BIN if bin.is_none() => bin = Some(chunk),
+                _ => {}
+            }
+            pos += 8 + chunk_len;
+        }
+
+        let json = json.ok_or_else(|| invalid_data("Glb without json chunk"))?;
+        Ok((JsonParser::parse(json)?, bin))
+    }
+
+    // base64 data uri, None for anything else since files next to .gltf aren't reachable
+    fn decode_data_uri(uri: &str) -> Option<Vec<u8>> {
+        let (header, data) = uri.strip_prefix("data:")?.split_once(',')?;
+        if !header.ends_with(";base64") {
+            return None;
+        }
+
+        let mut bytes = Vec::with_capacity(data.len() / 4 * 3);
+        let mut bits = 0u32;
+        let mut bits_cnt = 0;
+        for c in data.bytes() {
+            let value = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' | b'-' => 62,
+                b'/' | b'_' => 63,
+                b'=' => break,
+                _ => return None,
+            };
+            bits = ((bits << 6) | value as u32) & 0xFF_FFFF;
+            bits_cnt += 6;
+            if bits_cnt >= 8 {
+                bits_cnt -= 8;
+                bytes.push((bits >> bits_cnt) as u8);
+            }
+        }
+
+        Some(bytes)
+    }
+
+    // column major matrix, or translation, rotation and scale in this order of application from the right
+    fn node_matrix(node: &Json) -> Matrix4<f32> {
+        if let Some(m) = node.get_f32s("matrix", 16) {
+            return Matrix4::new(
+                m[0], m[1], m[2], m[3],
+                m[4], m[5], m[6], m[7],
+                m[8], m[9], m[10], m[11],
+                m[12], m[13], m[14], m[15],
+            );
+        }
+
+        let t = node.get_f32s("translation", 3).unwrap_or_else(|| vec![0.0, 0.0, 0.0]);
+        let r = node.get_f32s("rotation", 4).unwrap_or_else(|| vec![0.0, 0.0, 0.0, 1.0]);
+        let s = node.get_f32s("scale", 3).unwrap_or_else(|| vec![1.0, 1.0, 1.0]);
+        Matrix4::from_translation(Vector3::new(t[0], t[1], t[2]))
+            * Matrix4::from(Quaternion::new(r[3], r[0], r[1], r[2]))
+            * Matrix4::from_nonuniform_scale(s[0], s[1], s[2])
+    }
+
+    fn read_mesh(document: &Json, buffers: &[Option<Cow<[u8]>>], mesh_idx: usize) -> std::io::Result<IndexedMesh> {
+        let gltf_mesh = document.items("meshes").get(mesh_idx).ok_or_else(|| invalid_data("Node with missing mesh"))?;
+        let mut mesh = IndexedMesh {
+            name: gltf_mesh.get("name").and_then(Json::as_str).unwrap_or_default().to_string(),
+            ..Default::default()
+        };
+
+        // normals are kept only when every primitive has them
+        let mut has_normals = true;
+        for primitive in gltf_mesh.items("primitives") {
+            let mode = primitive.get_usize("mode").unwrap_or(MODE_TRIANGLES);
+            if !matches!(mode, MODE_TRIANGLES | MODE_TRIANGLE_STRIP | MODE_TRIANGLE_FAN) {
+                tracing::warn!("Skip primitive of mode {}, only triangles are read", mode);
+                continue;
+            }
+            let attributes = primitive.get("attributes");
+            let position_idx = match attributes.and_then(|attributes| attributes.get_usize("POSITION")) {
+                Some(position_idx) => position_idx,
+                None => continue,
+            };
+
+            let positions = Gltf::read_vectors(document, buffers, position_idx)?;
+            let normals = attributes
+                .and_then(|attributes| attributes.get_usize("NORMAL"))
+                .and_then(|normal_idx| Gltf::read_vectors(document, buffers, normal_idx).ok())
+                .filter(|normals| normals.len() == positions.len());
+            let indices = match primitive.get_usize("indices") {
+                Some(indices_idx) => Gltf::read_indices(document, buffers, indices_idx)?,
+                None => (0..positions.len() as u32).collect(),
+            };
+            let indices = match mode {
+                MODE_TRIANGLE_STRIP => IndexedMesh::triangles_from_strip(&indices),
+                MODE_TRIANGLE_FAN => IndexedMesh::triangles_from_fan(&indices),
+                _ => indices,
+            };
+
+            // out of range indices mustn't reach into the next primitive, validation drops them later
+            let (offset, len) = (mesh.positions.len() as u32, positions.len() as u32);
+            mesh.indices.extend(
+                indices
+                    .chunks_exact(3)
+                    .flatten()
+                    .map(|&idx| if idx < len { idx + offset } else { std::u32::MAX })
+            );
+            mesh.positions.extend(positions);
+            match normals {
+                Some(normals) if has_normals => mesh.normals.extend(normals),
+                _ => has_normals = false,
+            }
+        }
+
+        if !has_normals {
+            mesh.normals.clear();
+        }
+        mesh.normals_dirty = mesh.normals.len() != mesh.positions.len();
+
+        Ok(mesh)
+    }
+
+    fn read_vectors(document: &Json, buffers: &[Option<Cow<[u8]>>], accessor_idx: usize) -> std::io::Result<Vec<Vector3<f32>>> {
+        let (component_type, elements) = Gltf::read_accessor(document, buffers, accessor_idx, "VEC3")?;
+        if component_type != COMPONENT_F32 {
+            return Err(invalid_data("Quantized vertex attributes are not supported"));
+        }
+
+        let component = |bytes: &[u8], k: usize| f32::from_le_bytes([bytes[k * 4], bytes[k * 4 + 1], bytes[k * 4 + 2], bytes[k * 4 + 3]]);
+        Ok(elements
+            .into_iter()
+            .map(|bytes| Vector3::new(component(bytes, 0), component(bytes, 1), component(bytes, 2)))
+            .collect())
+    }
+
+    fn read_indices(document: &Json, buffers: &[Option<Cow<[u8]>>], accessor_idx: usize) -> std::io::Result<Vec<u32>> {
+        let (component_type, elements) = Gltf::read_accessor(document, buffers, accessor_idx, "SCALAR")?;
+        let index: fn(&[u8]) -> u32 = match component_type {
+            COMPONENT_U8 => |bytes: &[u8]| bytes[0] as u32,
+            COMPONENT_U16 => |bytes: &[u8]| u16::from_le_bytes([bytes[0], bytes[1]]) as u32,
+            COMPONENT_U32 => |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            _ => return Err(invalid_data("Indices must be unsigned integers")),
+        };
+
+        Ok(elements.into_iter().map(index).collect())
+    }
+
+    // component type of accessor and bytes of each its element, sparse accessors aren't supported
+    fn read_accessor<'a>(
+        document: &Json,
+        buffers: &'a [Option<Cow<[u8]>>],
+        accessor_idx: usize,
+        accessor_type: &str
+    ) -> std::io::Result<(usize, Vec<&'a [u8]>)> {
+        let accessor = document.items("accessors").get(accessor_idx).ok_or_else(|| invalid_data("Missing accessor"))?;
+        if accessor.get("type").and_then(Json::as_str) != Some(accessor_type) {
+            return Err(invalid_data("Unexpected accessor type"));
+        }
+
+        let component_type = accessor.get_usize("componentType").unwrap_or(0);
+        let component_size = match component_type {
+            5120 | COMPONENT_U8 => 1,
+            5122 | COMPONENT_U16 => 2,
+            COMPONENT_U32 | COMPONENT_F32 => 4,
+            _ => return Err(invalid_data("Unknown accessor component type")),
+        };
+        let element_size = component_size * if accessor_type == "VEC3" { 3 } else { 1 };
+
+        let view = accessor
+            .get_usize("bufferView")
+            .and_then(|view_idx| document.items("bufferViews").get(view_idx))
+            .ok_or_else(|| invalid_data("Accessor without buffer view"))?;
+        let buffer = view
+            .get_usize("buffer")
+            .and_then(|buffer_idx| buffers.get(buffer_idx))
+            .and_then(Option::as_ref)
+            .ok_or_else(|| invalid_data("Buffer is not embedded, only glb and data uris are supported"))?;
+
+        let view_offset = view.get_usize("byteOffset").unwrap_or(0);
+        let view_bytes = view_offset
+            .checked_add(view.get_usize("byteLength").unwrap_or(0))
+            .and_then(|view_end| buffer.get(view_offset..view_end))
+            .ok_or_else(|| invalid_data("Buffer view past the end of buffer"))?;
+        let stride = view.get_usize("byteStride").unwrap_or(element_size).max(element_size);
+        let offset = accessor.get_usize("byteOffset").unwrap_or(0);
+
+        // count is checked before allocating, elements can't be denser than packed
+        let count = accessor.get_usize("count").unwrap_or(0);
+        if count > view_bytes.len() / element_size {
+            return Err(invalid_data("Accessor past the end of buffer view"));
+        }
+        let elements = (0..count)
+            .map(|i| {
+                i.checked_mul(stride)
+                    .and_then(|start| start.checked_add(offset))
+                    .and_then(|start| view_bytes.get(start..)?.get(..element_size))
+                    .ok_or_else(|| invalid_data("Accessor past the end of buffer view"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((component_type, elements))
+    }
+
+    pub fn json_string(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len() + 2);
+        escaped.push('"');
+        for c in text.chars() {
+            match c {
+                '"' => escaped += "\\\"",
+                '\\' => escaped += "\\\\",
+                c if (c as u32) < 0x20 => escaped += &format!("\\u{:04x}", c as u32),
+                c => escaped.push(c),
+            }
+        }
+        escaped.push('"');
+        escaped
+    }
+
+    // binary gltf, every mesh gets own node with POSITION, NORMAL and u32 indices accessors,
+    // all of them have 4 byte components so 4 byte aligned buffer views keep accessors aligned too
+    pub fn write_glb(meshes: &[IndexedMesh]) -> Vec<u8> {
+        const COMPONENT_FLOAT: u32 = 5126;
+        const COMPONENT_UNSIGNED_INT: u32 = 5125;
+        const TARGET_ARRAY_BUFFER: u32 = 34962;
+        const TARGET_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+        let mut bin: Vec<u8> = vec![];
+        let mut buffer_views = vec![];
+        let mut accessors = vec![];
+        let mut gltf_meshes = vec![];
+        let mut nodes = vec![];
+
+        // appends data as new buffer view, returns its index
+        let mut push_view = |bin: &mut Vec<u8>, data: &[u8], target: u32| -> usize {
+            debug_assert_eq!(bin.len() % 4, 0);
+            buffer_views.push(format!(
+                "{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":{}}}",
+                bin.len(), data.len(), target
+            ));
+            bin.extend_from_slice(data);
+            while bin.len() % 4 != 0 {
+                bin.push(0);
+            }
+            buffer_views.len() - 1
+        };
+
+        for mesh in meshes.iter() {
+            if mesh.is_empty() { continue; }
+
+            // json has no inf or nan, bounds are taken from finite positions only
+            let (min, max) = mesh.positions
+                .iter()
+                .filter(|p| p.x.is_finite() && p.y.is_finite() && p.z.is_finite())
+                .fold(None, |bounds: Option<(Vector3<f32>, Vector3<f32>)>, &p| match bounds {
+                    Some((min, max)) => Some((
+                        Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                        Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+                    )),
+                    None => Some((p, p)),
+                })
+                .unwrap_or_else(|| (Vector3::zero(), Vector3::zero()));
+            let positions: Vec<u8> = mesh.positions
+                .iter()
+                .flat_map(|p| [p.x, p.y, p.z])
+                .flat_map(f32::to_le_bytes)
+                .collect();
+            // validators want unit normals, degenerate vertices get any valid one
+            let normals: Vec<u8> = mesh.positions
+                .iter()
+                .enumerate()
+                .map(|(i, _)| match mesh.normals.get(i) {
+                    Some(n) if n.x.is_finite() && n.y.is_finite() && n.z.is_finite() && n.magnitude2() > 0.0 => n.normalize(),
+                    _ => Vector3::unit_z(),
+                })
+                .flat_map(|n| [n.x, n.y, n.z])
+                .flat_map(f32::to_le_bytes)
+                .collect();
+            let indices: Vec<u8> = mesh.indices.iter().flat_map(|idx| idx.to_le_bytes()).collect();
+
+            let positions_view = push_view(&mut bin, &positions, TARGET_ARRAY_BUFFER);
+            accessors.push(format!(
+                "{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"VEC3\",\"min\":[{},{},{}],\"max\":[{},{},{}]}}",
+                positions_view, COMPONENT_FLOAT, mesh.positions.len(), min.x, min.y, min.z, max.x, max.y, max.z
+            ));
+            let normals_view = push_view(&mut bin, &normals, TARGET_ARRAY_BUFFER);
+            accessors.push(format!(
+                "{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"VEC3\"}}",
+                normals_view, COMPONENT_FLOAT, mesh.positions.len()
+            ));
+            let indices_view = push_view(&mut bin, &indices, TARGET_ELEMENT_ARRAY_BUFFER);
+            accessors.push(format!(
+                "{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"SCALAR\"}}",
+                indices_view, COMPONENT_UNSIGNED_INT, mesh.indices.len()
+            ));
+
+            let accessor = accessors.len() - 3;
+            let name = if mesh.name.is_empty() { String::new() } else { format!("\"name\":{},", Gltf::json_string(&mesh.name)) };
+            gltf_meshes.push(format!(
+                "{{{}\"primitives\":[{{\"attributes\":{{\"POSITION\":{},\"NORMAL\":{}}},\"indices\":{},\"mode\":4}}]}}",
+                name, accessor, accessor + 1, accessor + 2
+            ));
+            nodes.push(format!("{{{}\"mesh\":{}}}", name, gltf_meshes.len() - 1));
+        }
+
+        let mut json = format!(
+            "{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"Web Editor\"}},\"scene\":0,\"scenes\":[{{\"nodes\":[{}]}}],\"nodes\":[{}],\"meshes\":[{}]",
+            (0..nodes.len()).map(|i| i.to_string()).collect::<Vec<_>>().join(","),
+            nodes.join(","),
+            gltf_meshes.join(",")
+        );
+        if !bin.is_empty() {
+            json += &format!(
+                ",\"accessors\":[{}],\"bufferViews\":[{}],\"buffers\":[{{\"byteLength\":{}}}]",
+                accessors.join(","), buffer_views.join(","), bin.len()
+            );
+        }
+        json += "}";
+
+        // json chunk is padded with spaces, binary one with zeros
+        let mut json = json.into_bytes();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+
+        let chunk_header_len = 8;
+        let bin_chunk_len = if bin.is_empty() { 0 } else { chunk_header_len + bin.len() };
+        let total_len = 12 + chunk_header_len + json.len() + bin_chunk_len;
+
+        let mut glb = Vec::with_capacity(total_len);
+        glb.extend_from_slice(b"glTF");
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&(total_len as u32).to_le_bytes());
+
+        glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"JSON");
+        glb.extend_from_slice(&json);
+
+        if !bin.is_empty() {
+            glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+            glb.extend_from_slice(b"BIN\0");
+            glb.extend_from_slice(&bin);
+        }
+        debug_assert_eq!(glb.len(), total_len);
+
+        glb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u32(bytes: &[u8], pos: usize) -> usize {
+        u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]) as usize
+    }
+
+    // quad in xy plane facing +z, positions are followed by indices of given component type
+    const QUAD_JSON: &str = r#"{"asset":{"version":"2.0"},"scenes":[{"nodes":[0]}],
+        "nodes":[{"mesh":0,NODE}],
+        "meshes":[{"primitives":[{"attributes":{"POSITION":0},"indices":1}]}],
+        "accessors":[
+            {"bufferView":0,"componentType":5126,"count":4,"type":"VEC3"},
+            {"bufferView":1,"componentType":COMPONENT,"count":6,"type":"SCALAR"}
+        ],
+        "bufferViews":[{"buffer":0,"byteLength":48},{"buffer":0,"byteOffset":48,"byteLength":INDICES_LEN}],
+        "buffers":[BUFFER]}"#;
+
+    fn quad_bin(is_u32: bool) -> Vec<u8> {
+        let mut bin: Vec<u8> = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]]
+            .iter()
+            .flatten()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        for idx in [0u32, 1, 2, 0, 2, 3] {
+            if is_u32 {
+                bin.extend_from_slice(&idx.to_le_bytes());
+            } else {
+                bin.extend_from_slice(&(idx as u16).to_le_bytes());
+            }
+        }
+        bin
+    }
+
+    fn quad_json(is_u32: bool, node: &str, buffer: &str) -> String {
+        QUAD_JSON
+            .replace("COMPONENT", &(if is_u32 { COMPONENT_U32 } else { COMPONENT_U16 }).to_string())
+            .replace("INDICES_LEN", if is_u32 { "24" } else { "12" })
+            .replace("NODE", node)
+            .replace("BUFFER", buffer)
+    }
+
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        let mut bin = bin.to_vec();
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+
+        let mut glb = GLB_MAGIC.to_vec();
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((28 + json.len() + bin.len()) as u32).to_le_bytes());
+        glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&GLB_CHUNK_JSON.to_le_bytes());
+        glb.extend_from_slice(&json);
+        glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&GLB_CHUNK_BIN.to_le_bytes());
+        glb.extend_from_slice(&bin);
+        glb
+    }
+
+    fn base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in bytes.chunks(3) {
+            let bits = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+            for k in 0..4 {
+                text.push(if k <= chunk.len() { ALPHABET[(bits >> (18 - 6 * k) & 63) as usize] as char } else { '=' });
+            }
+        }
+        text
+    }
+
+    fn face_normal(mesh: &IndexedMesh, face: usize) -> Vector3<f32> {
+        let [a, b, c] = mesh.faces().nth(face).unwrap().map(|idx| mesh.positions[idx as usize]);
+        (b - a).cross(c - a)
+    }
+
+    #[test]
+    fn read_glb_with_u16_indices() {
+        let json = quad_json(false, r#""translation":[10,0,0]"#, r#"{"byteLength":60}"#);
+        let meshes = Gltf::read_meshes(&glb(&json, &quad_bin(false))).unwrap();
+
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(meshes[0].positions[2], Vector3::new(11.0, 1.0, 0.0));
+        assert!(meshes[0].normals_dirty);
+    }
+
+    #[test]
+    fn read_glb_with_u32_indices() {
+        let json = quad_json(true, r#""name":"quad""#, r#"{"byteLength":72}"#);
+        let meshes = Gltf::read_meshes(&glb(&json, &quad_bin(true))).unwrap();
+
+        assert_eq!(meshes[0].name, "quad");
+        assert_eq!(meshes[0].indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(meshes[0].positions.len(), 4);
+    }
+
+    #[test]
+    fn mirror_node_keeps_faces_outward() {
+        let json = quad_json(false, r#""scale":[-1,1,1]"#, r#"{"byteLength":60}"#);
+        let meshes = Gltf::read_meshes(&glb(&json, &quad_bin(false))).unwrap();
+
+        assert_eq!(meshes[0].positions[1], Vector3::new(-1.0, 0.0, 0.0));
+        for face in 0..2 {
+            assert!(face_normal(&meshes[0], face).z > 0.0);
+        }
+    }
+
+    #[test]
+    fn read_gltf_with_data_uri() {
+        let buffer = format!(r#"{{"byteLength":60,"uri":"data:application/octet-stream;base64,{}"}}"#, base64(&quad_bin(false)));
+        let meshes = Gltf::read_meshes(quad_json(false, r#""name":"quad""#, &buffer).as_bytes()).unwrap();
+        assert_eq!(meshes[0].indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(meshes[0].positions[2], Vector3::new(1.0, 1.0, 0.0));
+
+        // external files aren't reachable
+        let external = quad_json(false, r#""name":"quad""#, r#"{"byteLength":60,"uri":"quad.bin"}"#);
+        assert!(Gltf::read_meshes(external.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn glb_round_trip() {
+        let mut mesh = IndexedMesh::box3d(Vector3::new(1.0, 2.0, 3.0));
+        mesh.name = "box".to_string();
+        mesh.ensure_normals();
+        let meshes = Gltf::read_meshes(&Gltf::write_glb(&[mesh.clone()])).unwrap();
+
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].name, mesh.name);
+        assert_eq!(meshes[0].positions, mesh.positions);
+        assert_eq!(meshes[0].indices, mesh.indices);
+        assert!(!meshes[0].normals_dirty);
+    }
+
+    #[test]
+    fn malformed_input_is_error() {
+        let json = quad_json(false, r#""name":"quad""#, r#"{"byteLength":60}"#);
+        let full = glb(&json, &quad_bin(false));
+
+        // chunk longer than what is left of the file
+        assert!(Gltf::read_meshes(&full[..full.len() - 8]).is_err());
+        for len in 0..full.len() {
+            let _ = Gltf::read_meshes(&full[..len]);
+        }
+
+        let past_view = json.replace(r#""count":6"#, r#""count":7"#);
+        assert!(Gltf::read_meshes(&glb(&past_view, &quad_bin(false))).is_err());
+        let huge_count = json.replace(r#""count":6"#, r#""count":4294967295"#);
+        assert!(Gltf::read_meshes(&glb(&huge_count, &quad_bin(false))).is_err());
+
+        let nested = "[".repeat(100_000);
+        assert!(Gltf::read_meshes(nested.as_bytes()).is_err());
+        assert!(Gltf::read_meshes(&glb(&nested, &[])).is_err());
+    }
+
+    #[test]
+    fn write_glb_aligns_chunks() {
+        let mut mesh = IndexedMesh::box3d(Vector3::new(1.0, 2.0, 3.0));
+        mesh.name = "b\"ox".to_string();
+        mesh.ensure_normals();
+        let glb = Gltf::write_glb(&[mesh]);
+
+        assert_eq!(&glb[..4], GLB_MAGIC);
+        assert_eq!(read_u32(&glb, 4), 2);
+        assert_eq!(read_u32(&glb, 8), glb.len());
+
+        let json_len = read_u32(&glb, 12);
+        assert_eq!(read_u32(&glb, 16) as u32, GLB_CHUNK_JSON);
+        assert_eq!(json_len % 4, 0);
+        let bin_pos = 20 + json_len;
+        let bin_len = read_u32(&glb, bin_pos);
+        assert_eq!(read_u32(&glb, bin_pos + 4) as u32, GLB_CHUNK_BIN);
+        assert_eq!(bin_len % 4, 0);
+        assert_eq!(bin_pos + 8 + bin_len, glb.len());
+    }
+
+    #[test]
+    fn write_glb_skips_non_finite_bounds() {
+        let mut mesh = IndexedMesh::box3d(Vector3::new(1.0, 1.0, 1.0));
+        mesh.positions[0] = Vector3::new(f32::NAN, f32::INFINITY, 0.0);
+        let glb = Gltf::write_glb(&[mesh]);
+
+        let (document, _) = Gltf::split_glb(&glb).unwrap();
+        let accessor = &document.items("accessors")[0];
+        for bound in [accessor.get_f32s("min", 3).unwrap(), accessor.get_f32s("max", 3).unwrap()] {
+            assert!(bound.iter().all(|x| x.is_finite()));
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use cgmath::*;
+
+use crate::mesh::IndexedMesh;
+
+// half edges of face f are 3 * f, 3 * f + 1 and 3 * f + 2, each goes from its origin to origin of the next one
+#[derive(Clone, Copy)]
+struct HalfEdge {
+    origin: u32,
+    // opposite half edge of neighbour face, None on border and for extra faces of non manifold edge
+    twin: Option<u32>,
+}
+
+// topology only, positions stay in the mesh it was built from
+pub struct HalfEdgeMesh {
+    half_edges: Vec<HalfEdge>,
+
+    // outgoing half edges of every vertex, vertex v owns outgoing[outgoing_start[v]..outgoing_start[v + 1]]
+    outgoing_start: Vec<u32>,
+    outgoing: Vec<u32>,
+    // undirected edge of every half edge whatever its direction, unlike twins it joins
+    // faces of inconsistent winding and all faces of non manifold edge
+    edge_of: Vec<u32>,
+    edges_cnt: usize,
+}
+
+impl HalfEdgeMesh {
+    pub fn from(mesh: &IndexedMesh) -> Self {
+        let faces: Vec<[u32; 3]> = mesh.faces().collect();
+        HalfEdgeMesh::from_faces(mesh.positions.len(), &faces)
+    }
+
+    pub fn from_faces(vertices_cnt: usize, faces: &[[u32; 3]]) -> Self {
+        let half_edges: Vec<HalfEdge> = faces
+            .iter()
+            .flatten()
+            .map(|&origin| HalfEdge { origin, twin: None })
+            .collect();
+
+        let mut half_edge_mesh = HalfEdgeMesh {
+            half_edges,
+            outgoing_start: vec![0; vertices_cnt + 1],
+            outgoing: vec![],
+            edge_of: vec![],
+            edges_cnt: 0,
+        };
+
+        // undirected edges numbered in order of their first half edge, twin is the first
+        // unpaired half edge going the other way along the same edge
+        let mut edge_ids: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut edge_half_edges: Vec<Vec<u32>> = vec![];
+        half_edge_mesh.edge_of = Vec::with_capacity(half_edge_mesh.half_edges.len());
+        for h in 0..half_edge_mesh.half_edges.len() as u32 {
+            let (a, b) = (half_edge_mesh.origin(h), half_edge_mesh.target(h));
+            let edge = *edge_ids.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                edge_half_edges.push(vec![]);
+                edge_half_edges.len() as u32 - 1
+            });
+            half_edge_mesh.edge_of.push(edge);
+
+            let twin = edge_half_edges[edge as usize]
+                .iter()
+                .copied()
+                .find(|&g| half_edge_mesh.origin(g) == b && half_edge_mesh.twin(g).is_none());
+            if let Some(g) = twin {
+                half_edge_mesh.half_edges[h as usize].twin = Some(g);
+                half_edge_mesh.half_edges[g as usize].twin = Some(h);
+            }
+            edge_half_edges[edge as usize].push(h);
+        }
+        half_edge_mesh.edges_cnt = edge_half_edges.len();
+
+        for half_edge in half_edge_mesh.half_edges.iter() {
+            half_edge_mesh.outgoing_start[half_edge.origin as usize + 1] += 1;
+        }
+        for v in 0..vertices_cnt {
+            half_edge_mesh.outgoing_start[v + 1] += half_edge_mesh.outgoing_start[v];
+        }
+        let mut fill = half_edge_mesh.outgoing_start.clone();
+        half_edge_mesh.outgoing = vec![0; half_edge_mesh.half_edges.len()];
+        for (h, half_edge) in half_edge_mesh.half_edges.iter().enumerate() {
+            let slot = &mut fill[half_edge.origin as usize];
+            half_edge_mesh.outgoing[*slot as usize] = h as u32;
+            *slot += 1;
+        }
+
+        half_edge_mesh
+    }
+
+    pub fn vertices_cnt(&self) -> usize {
+        self.outgoing_start.len() - 1
+    }
+    pub fn faces_cnt(&self) -> usize {
+        self.half_edges.len() / 3
+    }
+
+    pub fn origin(&self, h: u32) -> u32 {
+        self.half_edges[h as usize].origin
+    }
+    pub fn target(&self, h: u32) -> u32 {
+        self.origin(self.next(h))
+    }
+    pub fn twin(&self, h: u32) -> Option<u32> {
+        self.half_edges[h as usize].twin
+    }
+    pub fn next(&self, h: u32) -> u32 {
+        h - h % 3 + (h + 1) % 3
+    }
+    pub fn prev(&self, h: u32) -> u32 {
+        h - h % 3 + (h + 2) % 3
+    }
+    pub fn face(&self, h: u32) -> u32 {
+        h / 3
+    }
+    pub fn face_edges(&self, f: u32) -> [u32; 3] {
+        [3 * f, 3 * f + 1, 3 * f + 2]
+    }
+
+    pub fn is_boundary(&self, h: u32) -> bool {
+        self.twin(h).is_none()
+    }
+
+    // one half edge per undirected edge, border ones included
+    pub fn edges(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.half_edges.len() as u32).filter(move |&h| self.twin(h).map_or(true, |twin| h < twin))
+    }
+
+    pub fn edge(&self, h: u32) -> u32 {
+        self.edge_of[h as usize]
+    }
+    pub fn edges_cnt(&self) -> usize {
+        self.edges_cnt
+    }
+
+    // half edges of every undirected edge, border edge has one, manifold edge two and non manifold edge more
+    pub fn edge_half_edges(&self) -> Vec<Vec<u32>> {
+        let mut edge_half_edges = vec![vec![]; self.edges_cnt];
+        for (h, &edge) in self.edge_of.iter().enumerate() {
+            edge_half_edges[edge as usize].push(h as u32);
+        }
+
+        edge_half_edges
+    }
+
+    // one half edge of every edge where faces meet at dihedral angle above feature_angle (degrees),
+    // faces with zero or broken normal never make a feature
+    pub fn feature_edges<'a>(&'a self, face_normals: &'a [Vector3<f32>], feature_angle: f32) -> impl Iterator<Item = u32> + 'a {
+        let cos_threshold = Deg(feature_angle).cos();
+        self.edges().filter(move |&h| match self.twin(h) {
+            Some(twin) => {
+                let (n1, n2) = (face_normals[self.face(h) as usize], face_normals[self.face(twin) as usize]);
+                n1.magnitude2() > 0.0 && n2.magnitude2() > 0.0 && n1.dot(n2) < cos_threshold
+            }
+            None => false,
+        })
+    }
+
+    pub fn outgoing(&self, v: u32) -> &[u32] {
+        let (start, end) = (self.outgoing_start[v as usize], self.outgoing_start[v as usize + 1]);
+        &self.outgoing[start as usize..end as usize]
+    }
+
+    // sorted and without repeats, border neighbours reached only by incoming half edges are included
+    pub fn vertex_neighbors(&self, v: u32) -> Vec<u32> {
+        let mut neighbors: Vec<u32> = self.outgoing(v)
+            .iter()
+            .flat_map(|&h| [self.target(h), self.origin(self.prev(h))])
+            .collect();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+
+        neighbors
+    }
+
+    // border half edges chained into vertex loops, a loop ends where it started or where border
+    // can't be followed further (non manifold vertex)
+    pub fn boundary_loops(&self) -> Vec<Vec<u32>> {
+        let mut is_visited = vec![false; self.half_edges.len()];
+        let mut loops = vec![];
+
+        for start in 0..self.half_edges.len() as u32 {
+            if !self.is_boundary(start) || is_visited[start as usize] { continue; }
+
+            let mut boundary_loop = vec![];
+            let mut h = start;
+            loop {
+                is_visited[h as usize] = true;
+                boundary_loop.push(self.origin(h));
+
+                let next = self.outgoing(self.target(h))
+                    .iter()
+                    .copied()
+                    .find(|&g| self.is_boundary(g) && !is_visited[g as usize]);
+                match next {
+                    Some(next) => h = next,
+                    None => break,
+                }
+            }
+            loops.push(boundary_loop);
+        }
+
+        loops
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_cube() {
+        let half_edge_mesh = HalfEdgeMesh::from(&IndexedMesh::box3d(Vector3::new(1.0, 1.0, 1.0)));
+
+        assert_eq!(half_edge_mesh.vertices_cnt(), 8);
+        assert_eq!(half_edge_mesh.faces_cnt(), 12);
+        assert_eq!(half_edge_mesh.edges_cnt(), 18);
+        assert_eq!(half_edge_mesh.edges().count(), 18);
+        assert!((0..36).all(|h| !half_edge_mesh.is_boundary(h)));
+        assert!(half_edge_mesh.boundary_loops().is_empty());
+        for h in 0..36 {
+            let twin = half_edge_mesh.twin(h).unwrap();
+            assert_eq!(half_edge_mesh.twin(twin), Some(h));
+            assert_eq!(half_edge_mesh.origin(twin), half_edge_mesh.target(h));
+            assert_eq!(half_edge_mesh.edge(twin), half_edge_mesh.edge(h));
+        }
+
+        // no face diagonal ends at corner 0, three of them end at corner 7
+        assert_eq!(half_edge_mesh.vertex_neighbors(0), vec![1, 2, 4]);
+        assert_eq!(half_edge_mesh.vertex_neighbors(7), vec![1, 2, 3, 5, 6]);
+        for v in 0..8 {
+            let one_ring = half_edge_mesh.vertex_neighbors(v);
+            assert_eq!(one_ring.len(), half_edge_mesh.outgoing(v).len());
+            assert!(!one_ring.contains(&v));
+        }
+    }
+
+    #[test]
+    fn cube_feature_edges() {
+        let mesh = IndexedMesh::box3d(Vector3::new(1.0, 1.0, 1.0));
+        let half_edge_mesh = HalfEdgeMesh::from(&mesh);
+        let face_normals: Vec<Vector3<f32>> = mesh.faces()
+            .map(|face_idxs| {
+                let [p0, p1, p2] = face_idxs.map(|idx| mesh.positions[idx as usize]);
+                (p1 - p0).cross(p2 - p0).normalize()
+            })
+            .collect();
+
+        // cube edges are at 90 degrees, face diagonals are flat
+        assert_eq!(half_edge_mesh.feature_edges(&face_normals, 45.0).count(), 12);
+        assert_eq!(half_edge_mesh.feature_edges(&face_normals, 135.0).count(), 0);
+
+        let zero_normals = vec![Vector3::zero(); half_edge_mesh.faces_cnt()];
+        assert_eq!(half_edge_mesh.feature_edges(&zero_normals, 45.0).count(), 0);
+    }
+
+    #[test]
+    fn open_plane() {
+        // 3x3 grid of vertices, 8 faces
+        let mut faces = vec![];
+        for y in 0..2 {
+            for x in 0..2 {
+                let v = y * 3 + x;
+                faces.push([v, v + 1, v + 4]);
+                faces.push([v, v + 4, v + 3]);
+            }
+        }
+        let half_edge_mesh = HalfEdgeMesh::from_faces(9, &faces);
+
+        assert_eq!(half_edge_mesh.edges_cnt(), 16);
+        assert_eq!((0..24).filter(|&h| half_edge_mesh.is_boundary(h)).count(), 8);
+
+        let loops = half_edge_mesh.boundary_loops();
+        assert_eq!(loops.len(), 1);
+        let mut border = loops[0].clone();
+        border.sort_unstable();
+        assert_eq!(border, vec![0, 1, 2, 3, 5, 6, 7, 8]);
+        // loop follows border in winding direction
+        let start = loops[0].iter().position(|&v| v == 0).unwrap();
+        assert_eq!(loops[0][(start + 1) % 8], 1);
+
+        // diagonals go from lower left to upper right
+        assert_eq!(half_edge_mesh.vertex_neighbors(4), vec![0, 1, 3, 5, 7, 8]);
+        assert_eq!(half_edge_mesh.vertex_neighbors(2), vec![1, 5]);
+    }
+
+    #[test]
+    fn flipped_face_shares_edge_without_twin() {
+        let half_edge_mesh = HalfEdgeMesh::from_faces(4, &[[0, 1, 2], [1, 0, 3], [0, 1, 3]]);
+
+        // edge 0-1 is non manifold with three faces, only one pair of them are twins
+        let edge_half_edges = half_edge_mesh.edge_half_edges();
+        assert_eq!(edge_half_edges[half_edge_mesh.edge(0) as usize], vec![0, 3, 6]);
+        assert_eq!(half_edge_mesh.twin(0), Some(3));
+        assert_eq!(half_edge_mesh.twin(6), None);
+    }
+}
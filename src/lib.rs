@@ -1,10 +1,18 @@
 mod simplification;
 mod remesh;
 mod camera;
+mod bindings;
 mod render;
 mod mesh;
+mod spatial;
+mod halfedge;
+mod repair;
+mod session;
+mod gltf;
 mod app;
 pub use app::WebEditor;
+#[cfg(feature = "scripting")]
+pub use mesh::IndexedMesh;
 
 #[cfg(target_arch = "wasm32")]
 use eframe::wasm_bindgen::{self, prelude::*};
@@ -21,7 +29,45 @@ pub fn start(canvas_id: &str) -> Result<(), eframe::wasm_bindgen::JsValue> {
 
     // ui stuff
     // https://github.com/emilk/eframe_template/
-    eframe::start_web(canvas_id, Box::new(|cc| Box::new(WebEditor::new(cc))))?;
+    // shared view, e.g. `#camera=...`
+    let url_fragment = web_sys::window()
+        .and_then(|window| window.location().hash().ok())
+        .unwrap_or_default();
+
+    eframe::start_web(canvas_id, Box::new(move |cc| {
+        let mut web_editor = WebEditor::new(cc);
+        web_editor.set_url_fragment(&url_fragment);
+        Box::new(web_editor)
+    }))?;
+
+    listen_context_loss(canvas_id)?;
+
+    Ok(())
+}
+
+// context may be lost on gpu reset or in background tab, renderer is rebuilt once it's restored
+#[cfg(target_arch = "wasm32")]
+fn listen_context_loss(canvas_id: &str) -> Result<(), eframe::wasm_bindgen::JsValue> {
+    use std::sync::atomic::Ordering;
+
+    let canvas = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.get_element_by_id(canvas_id))
+        .ok_or_else(|| JsValue::from_str("Canvas not found"))?;
+
+    let on_lost = Closure::wrap(Box::new(|event: web_sys::Event| {
+        // otherwise browser never restores the context
+        event.prevent_default();
+        render::IS_CONTEXT_LOST.store(true, Ordering::Relaxed);
+    }) as Box<dyn FnMut(_)>);
+    canvas.add_event_listener_with_callback("webglcontextlost", on_lost.as_ref().unchecked_ref())?;
+    on_lost.forget();
+
+    let on_restored = Closure::wrap(Box::new(|_event: web_sys::Event| {
+        render::IS_CONTEXT_RESTORED.store(true, Ordering::Relaxed);
+    }) as Box<dyn FnMut(_)>);
+    canvas.add_event_listener_with_callback("webglcontextrestored", on_restored.as_ref().unchecked_ref())?;
+    on_restored.forget();
 
     Ok(())
 }
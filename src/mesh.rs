@@ -1,38 +1,176 @@
+use std::sync::Arc;
+
 use cgmath::*;
 
+use crate::spatial::AabbTree;
+use crate::halfedge::HalfEdgeMesh;
+
+#[derive(Clone, Copy)]
+pub struct MeshInfo {
+    pub vertices_cnt: usize,
+    pub faces_cnt: usize,
+    pub size: Vector3<f32>,
+    // every edge is shared by exactly two faces
+    pub is_watertight: bool,
+}
+
 #[derive(Default, Clone)]
 pub struct IndexedMesh {
+    pub name: String,
+
     pub positions: Vec<Vector3<f32>>,
     pub normals: Vec<Vector3<f32>>,
     pub indices: Vec<u32>,
+    // baked ambient occlusion per vertex, empty when not baked
+    pub occlusion: Vec<f32>,
+    // color per face from ply, used only while it matches faces count
+    pub face_colors: Vec<Vector3<f32>>,
+    // built on first spatial query, shared by clones until edited
+    pub aabb_tree: Option<Arc<AabbTree>>,
+    // counts for ui, computed on first request and dropped on edit like the tree
+    pub info: Option<MeshInfo>,
+
+    pub normals_dirty: bool,
 }
 
 impl IndexedMesh {
+    // triangles as index triples, incomplete tail of index buffer is ignored
+    pub fn faces(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.indices.chunks_exact(3).map(|face_idxs| [face_idxs[0], face_idxs[1], face_idxs[2]])
+    }
+
+    pub fn faces_mut(&mut self) -> impl Iterator<Item = &mut [u32; 3]> + '_ {
+        self.indices.chunks_exact_mut(3).map(|face_idxs| <&mut [u32; 3]>::try_from(face_idxs).unwrap())
+    }
+
+    // operations that rebuild faces don't carry colors, so they are left mismatched and ignored
+    pub fn has_face_colors(&self) -> bool {
+        !self.face_colors.is_empty() && self.face_colors.len() == self.indices.len() / 3
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.positions.is_empty() || self.normals.is_empty() || self.indices.is_empty()
+        self.positions.is_empty() || self.indices.is_empty()
     }
     pub fn clear(&mut self) {
         self.positions.clear();
         self.normals.clear();
         self.indices.clear();
+        self.occlusion.clear();
+        self.face_colors.clear();
+        self.aabb_tree = None;
+        self.info = None;
+        self.normals_dirty = false;
+    }
+
+    // normals are recomputed lazily, only before upload/export
+    pub fn ensure_normals(&mut self) {
+        // geometry changed, so baked occlusion and spatial tree are stale too
+        if self.normals_dirty {
+            self.occlusion.clear();
+            self.aabb_tree = None;
+            self.info = None;
+        }
+        if self.normals_dirty || self.normals.len() != self.positions.len() {
+            self.recalculate_normals();
+        }
     }
 
+    #[cfg(not(all(feature = "rayon", not(target_arch = "wasm32"))))]
     pub fn recalculate_normals(&mut self) {
-        self.normals.resize(self.positions.len(), Vector3::new(0.0, 0.0, 0.0));
+        let mut normals = vec![Vector3::new(0.0f32, 0.0, 0.0); self.positions.len()];
 
-        for face_idxs in self.indices.windows(3).step_by(3) {
+        for face_idxs in self.faces() {
             let v0 = self.positions[face_idxs[0] as usize];
             let v1 = self.positions[face_idxs[1] as usize];
             let v2 = self.positions[face_idxs[2] as usize];
 
             let face_normal = (v1 - v0).cross(v2 - v0);
-            self.normals[face_idxs[0] as usize] += face_normal;
-            self.normals[face_idxs[1] as usize] += face_normal;
-            self.normals[face_idxs[2] as usize] += face_normal;
+            normals[face_idxs[0] as usize] += face_normal;
+            normals[face_idxs[1] as usize] += face_normal;
+            normals[face_idxs[2] as usize] += face_normal;
         }
-        for normal in self.normals.iter_mut() {
+        for normal in normals.iter_mut() {
             *normal = normal.normalize();
         }
+        self.normals = normals;
+        self.normals_dirty = false;
+    }
+
+    // each chunk of faces accumulates into own buffer, shared vertices are summed on reduce
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    pub fn recalculate_normals(&mut self) {
+        use rayon::prelude::*;
+
+        let positions = &self.positions;
+        let vertices_cnt = positions.len();
+        let min_faces_per_chunk = self.indices.len() / 3 / rayon::current_num_threads() + 1;
+
+        let mut normals = self.indices
+            .par_chunks_exact(3)
+            .with_min_len(min_faces_per_chunk)
+            .fold(
+                || vec![Vector3::new(0.0f32, 0.0, 0.0); vertices_cnt],
+                |mut normals, face_idxs| {
+                    let v0 = positions[face_idxs[0] as usize];
+                    let v1 = positions[face_idxs[1] as usize];
+                    let v2 = positions[face_idxs[2] as usize];
+
+                    let face_normal = (v1 - v0).cross(v2 - v0);
+                    normals[face_idxs[0] as usize] += face_normal;
+                    normals[face_idxs[1] as usize] += face_normal;
+                    normals[face_idxs[2] as usize] += face_normal;
+
+                    normals
+                }
+            )
+            .reduce(
+                || vec![Vector3::new(0.0f32, 0.0, 0.0); vertices_cnt],
+                |mut normals, other| {
+                    for (normal, other) in normals.iter_mut().zip(other) {
+                        *normal += other;
+                    }
+                    normals
+                }
+            );
+
+        normals.par_iter_mut().for_each(|normal| *normal = normal.normalize());
+
+        self.normals = normals;
+        self.normals_dirty = false;
+    }
+
+    // moves positions and imported normals, mirroring matrix also flips winding to keep faces outward
+    pub fn transform(&mut self, matrix: Matrix4<f32>) {
+        for p in self.positions.iter_mut() {
+            *p = (matrix * p.extend(1.0)).truncate();
+        }
+
+        let linear = Matrix3::from_cols(matrix.x.truncate(), matrix.y.truncate(), matrix.z.truncate());
+        if let Some(normal_matrix) = linear.invert() {
+            let normal_matrix = normal_matrix.transpose();
+            for n in self.normals.iter_mut() {
+                *n = (normal_matrix * *n).normalize();
+            }
+        }
+        if linear.determinant() < 0.0 {
+            for face_idxs in self.faces_mut() {
+                face_idxs.swap(1, 2);
+            }
+        }
+
+        self.aabb_tree = None;
+        self.info = None;
+    }
+
+    pub fn calculate_area(&self) -> f32 {
+        self.faces()
+            .map(|face_idxs| {
+                let v0 = self.positions[face_idxs[0] as usize];
+                let v1 = self.positions[face_idxs[1] as usize];
+                let v2 = self.positions[face_idxs[2] as usize];
+                (v1 - v0).cross(v2 - v0).magnitude() / 2.0
+            })
+            .sum()
     }
 
     pub fn calculate_center_point(&self) -> Vector3<f32> {
@@ -62,11 +200,704 @@ impl IndexedMesh {
         (min, max)
     }
 
+    // per vertex mean curvature from cotangent laplacian, positive for convex regions
+    pub fn mean_curvature(&self) -> Vec<f32> {
+        let vertices_cnt = self.positions.len();
+        let mut laplacian = vec![Vector3::new(0.0f32, 0.0, 0.0); vertices_cnt];
+        let mut normals = vec![Vector3::new(0.0f32, 0.0, 0.0); vertices_cnt];
+        let mut areas = vec![0.0f32; vertices_cnt];
+
+        for face_idxs in self.faces() {
+            let face_normal = (self.positions[face_idxs[1] as usize] - self.positions[face_idxs[0] as usize])
+                .cross(self.positions[face_idxs[2] as usize] - self.positions[face_idxs[0] as usize]);
+            let area = face_normal.magnitude() / 2.0;
+            if area <= std::f32::EPSILON { continue; }
+
+            for k in 0..3 {
+                let (i, a, b) = (face_idxs[k] as usize, face_idxs[(k + 1) % 3] as usize, face_idxs[(k + 2) % 3] as usize);
+                let (ea, eb) = (self.positions[a] - self.positions[i], self.positions[b] - self.positions[i]);
+
+                // cotangent of angle at i weights the opposite edge
+                let cot = ea.dot(eb) / ea.cross(eb).magnitude();
+                laplacian[a] += (self.positions[b] - self.positions[a]) * cot;
+                laplacian[b] += (self.positions[a] - self.positions[b]) * cot;
+
+                normals[i] += face_normal;
+                areas[i] += area / 3.0;
+            }
+        }
+
+        (0..vertices_cnt)
+            .map(|i| {
+                if areas[i] <= std::f32::EPSILON { return 0.0; }
+                -laplacian[i].dot(normals[i].normalize()) / (4.0 * areas[i])
+            })
+            .collect()
+    }
+
+    // per vertex gaussian curvature from angle deficit, boundary vertices use pi instead of 2pi
+    pub fn gaussian_curvature(&self) -> Vec<f32> {
+        use std::f32::consts::PI;
+
+        let vertices_cnt = self.positions.len();
+        let mut angles = vec![0.0f32; vertices_cnt];
+        let mut areas = vec![0.0f32; vertices_cnt];
+
+        // edges used by single face lie on the boundary
+        let mut edges_faces = std::collections::HashMap::new();
+
+        for face_idxs in self.faces() {
+            for k in 0..3 {
+                let (a, b) = (face_idxs[k], face_idxs[(k + 1) % 3]);
+                *edges_faces.entry((a.min(b), a.max(b))).or_insert(0u32) += 1;
+            }
+
+            let area = (self.positions[face_idxs[1] as usize] - self.positions[face_idxs[0] as usize])
+                .cross(self.positions[face_idxs[2] as usize] - self.positions[face_idxs[0] as usize])
+                .magnitude() / 2.0;
+            if area <= std::f32::EPSILON { continue; }
+
+            for k in 0..3 {
+                let (i, a, b) = (face_idxs[k] as usize, face_idxs[(k + 1) % 3] as usize, face_idxs[(k + 2) % 3] as usize);
+                let (ea, eb) = (self.positions[a] - self.positions[i], self.positions[b] - self.positions[i]);
+
+                angles[i] += ea.cross(eb).magnitude().atan2(ea.dot(eb));
+                areas[i] += area / 3.0;
+            }
+        }
+
+        let mut is_boundary = vec![false; vertices_cnt];
+        for (&(a, b), &faces_cnt) in edges_faces.iter() {
+            if faces_cnt == 1 {
+                is_boundary[a as usize] = true;
+                is_boundary[b as usize] = true;
+            }
+        }
+
+        (0..vertices_cnt)
+            .map(|i| {
+                if areas[i] <= std::f32::EPSILON { return 0.0; }
+                let full_angle = if is_boundary[i] { PI } else { 2.0 * PI };
+                (full_angle - angles[i]) / areas[i]
+            })
+            .collect()
+    }
+
+    // ritter's approximate bounding sphere, returns center and radius
+    pub fn bounding_sphere(&self) -> (Vector3<f32>, f32) {
+        if self.positions.is_empty() {
+            return (Vector3::new(0.0, 0.0, 0.0), 0.0);
+        }
+
+        let farthest_from = |p: Vector3<f32>| {
+            self.positions
+                .iter()
+                .copied()
+                .max_by(|a, b| a.distance2(p).partial_cmp(&b.distance2(p)).unwrap_or(std::cmp::Ordering::Equal))
+                .unwrap()
+        };
+
+        let a = farthest_from(self.positions[0]);
+        let b = farthest_from(a);
+
+        let mut center = (a + b) / 2.0;
+        let mut radius = a.distance(b) / 2.0;
+
+        // grow sphere to include points left outside
+        for &p in self.positions.iter() {
+            let dist = p.distance(center);
+            if dist > radius {
+                let new_radius = (radius + dist) / 2.0;
+                center += (p - center) * ((new_radius - radius) / dist);
+                radius = new_radius;
+            }
+        }
+
+        (center, radius)
+    }
+
+    // unique edges as pairs of indices, suitable for drawing as lines
+    pub fn calculate_edges(&self) -> Vec<u32> {
+        let mut edges = std::collections::HashSet::with_capacity(self.indices.len());
+        for face_idxs in self.faces() {
+            for k in 0..3 {
+                let (a, b) = (face_idxs[k], face_idxs[(k + 1) % 3]);
+                edges.insert((a.min(b), a.max(b)));
+            }
+        }
+
+        edges.into_iter().flat_map(|(a, b)| [a, b]).collect()
+    }
+
+    // drop faces with repeated vertices or zero area, returns number of removed faces
+    pub fn remove_degenerate_faces(&mut self) -> usize {
+        let faces_cnt = self.indices.len() / 3;
+
+        let is_face_colors = self.has_face_colors();
+        let mut indices = Vec::with_capacity(self.indices.len());
+        let mut face_colors = vec![];
+        for (f, face_idxs) in self.faces().enumerate() {
+            if self.is_degenerate_face(face_idxs) { continue; }
+
+            indices.extend_from_slice(&face_idxs);
+            if is_face_colors {
+                face_colors.push(self.face_colors[f]);
+            }
+        }
+
+        let removed = faces_cnt - indices.len() / 3;
+        if removed > 0 {
+            self.indices = indices;
+            if is_face_colors {
+                self.face_colors = face_colors;
+            }
+            self.normals_dirty = true;
+        }
+
+        removed
+    }
+
+    pub fn is_degenerate_face(&self, face_idxs: [u32; 3]) -> bool {
+        if face_idxs[0] == face_idxs[1] || face_idxs[1] == face_idxs[2] || face_idxs[2] == face_idxs[0] {
+            return true;
+        }
+
+        let v0 = self.positions[face_idxs[0] as usize];
+        let v1 = self.positions[face_idxs[1] as usize];
+        let v2 = self.positions[face_idxs[2] as usize];
+        (v1 - v0).cross(v2 - v0).magnitude2() == 0.0
+    }
+
+    // drop repeats of a face over the same vertices, whatever their order, returns number of removed faces
+    pub fn remove_duplicate_faces(&mut self) -> usize {
+        use std::collections::HashSet;
+
+        let faces_cnt = self.indices.len() / 3;
+
+        let is_face_colors = self.has_face_colors();
+        let mut seen = HashSet::with_capacity(faces_cnt);
+        let mut indices = Vec::with_capacity(self.indices.len());
+        let mut face_colors = vec![];
+        for (f, face_idxs) in self.faces().enumerate() {
+            let mut key = face_idxs;
+            key.sort_unstable();
+            if !seen.insert(key) { continue; }
+
+            indices.extend_from_slice(&face_idxs);
+            if is_face_colors {
+                face_colors.push(self.face_colors[f]);
+            }
+        }
+
+        let removed = faces_cnt - indices.len() / 3;
+        if removed > 0 {
+            self.indices = indices;
+            if is_face_colors {
+                self.face_colors = face_colors;
+            }
+            self.normals_dirty = true;
+        }
+
+        removed
+    }
+
+    // drop faces referencing missing vertices and incomplete tail, returns number of dropped faces
+    pub fn validate(&mut self) -> usize {
+        let vertices_cnt = self.positions.len() as u32;
+        let faces_cnt = self.indices.len() / 3;
+
+        let is_face_colors = self.has_face_colors();
+        let mut indices = Vec::with_capacity(self.indices.len());
+        let mut face_colors = vec![];
+        for (f, face_idxs) in self.faces().enumerate() {
+            if face_idxs.iter().all(|&idx| idx < vertices_cnt) {
+                indices.extend_from_slice(&face_idxs);
+                if is_face_colors {
+                    face_colors.push(self.face_colors[f]);
+                }
+            }
+        }
+
+        let dropped = faces_cnt - indices.len() / 3;
+        if indices.len() != self.indices.len() {
+            self.indices = indices;
+            if is_face_colors {
+                self.face_colors = face_colors;
+            }
+            self.normals_dirty = true;
+        }
+
+        dropped
+    }
+
+    // merge vertices closer than eps, returns number of removed vertices
+    pub fn weld_vertices(&mut self, eps: f32) -> usize {
+        use std::collections::HashMap;
+
+        let cell_size = eps.max(1e-12);
+        let cell_of = |p: &Vector3<f32>| (
+            (p.x / cell_size).floor() as i64,
+            (p.y / cell_size).floor() as i64,
+            (p.z / cell_size).floor() as i64,
+        );
+
+        let mut grid: HashMap<(i64, i64, i64), Vec<u32>> = HashMap::new();
+        let mut positions: Vec<Vector3<f32>> = Vec::with_capacity(self.positions.len());
+        let mut remap = Vec::with_capacity(self.positions.len());
+
+        for p in self.positions.iter() {
+            let cell = cell_of(p);
+
+            // close vertex can lie in a neighbour cell
+            let mut found = None;
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        if let Some(candidates) = grid.get(&(cell.0 + dx, cell.1 + dy, cell.2 + dz)) {
+                            for &candidate in candidates {
+                                if (positions[candidate as usize] - p).magnitude2() <= eps * eps {
+                                    found = Some(candidate);
+                                    break 'search;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            let idx = match found {
+                Some(idx) => idx,
+                None => {
+                    let idx = positions.len() as u32;
+                    positions.push(*p);
+                    grid.entry(cell).or_default().push(idx);
+                    idx
+                }
+            };
+            remap.push(idx);
+        }
+
+        for idx in self.indices.iter_mut() {
+            *idx = remap[*idx as usize];
+        }
+
+        let removed = self.positions.len() - positions.len();
+        if removed > 0 {
+            self.positions = positions;
+            self.normals_dirty = true;
+        }
+
+        removed
+    }
+
+    // drop vertices no face refers to, per vertex normals and occlusion are kept in step,
+    // returns number of removed vertices
+    pub fn remove_unused_vertices(&mut self) -> usize {
+        let mut remap = vec![std::u32::MAX; self.positions.len()];
+        for &idx in self.indices.iter() {
+            remap[idx as usize] = 0;
+        }
+
+        let mut kept_cnt = 0;
+        for new_idx in remap.iter_mut() {
+            if *new_idx != std::u32::MAX {
+                *new_idx = kept_cnt;
+                kept_cnt += 1;
+            }
+        }
+        let removed = self.positions.len() - kept_cnt as usize;
+        if removed == 0 { return 0; }
+
+        fn compact<T>(values: &mut Vec<T>, remap: &[u32]) {
+            let mut k = 0;
+            values.retain(|_| {
+                k += 1;
+                remap[k - 1] != std::u32::MAX
+            });
+        }
+        if self.normals.len() == self.positions.len() {
+            compact(&mut self.normals, &remap);
+        }
+        if self.occlusion.len() == self.positions.len() {
+            compact(&mut self.occlusion, &remap);
+        }
+        compact(&mut self.positions, &remap);
+
+        for idx in self.indices.iter_mut() {
+            *idx = remap[*idx as usize];
+        }
+        self.aabb_tree = None;
+        self.info = None;
+
+        removed
+    }
+
+    // every face gets own vertices with face normal, so flat look survives export
+    pub fn to_faceted(&self) -> IndexedMesh {
+        let mut faceted = IndexedMesh {
+            name: self.name.clone(),
+            ..Default::default()
+        };
+        let is_occlusion = self.occlusion.len() == self.positions.len();
+        if self.has_face_colors() {
+            faceted.face_colors = self.face_colors.clone();
+        }
+
+        for face_idxs in self.faces() {
+            let v0 = self.positions[face_idxs[0] as usize];
+            let v1 = self.positions[face_idxs[1] as usize];
+            let v2 = self.positions[face_idxs[2] as usize];
+            let face_normal = (v1 - v0).cross(v2 - v0).normalize();
+
+            for idx in face_idxs {
+                faceted.indices.push(faceted.positions.len() as u32);
+                faceted.positions.push(self.positions[idx as usize]);
+                faceted.normals.push(face_normal);
+                if is_occlusion {
+                    faceted.occlusion.push(self.occlusion[idx as usize]);
+                }
+            }
+        }
+
+        faceted
+    }
+
+    // concatenation of all meshes, indices of each next mesh are shifted by vertices before it
+    pub fn merge(meshes: &[&IndexedMesh]) -> IndexedMesh {
+        let mut merged = IndexedMesh::default();
+        // normals carry over when every part has them up to date, then baked occlusion stays valid too
+        let is_normals = !meshes.is_empty() && meshes.iter().all(|mesh| !mesh.normals_dirty && mesh.normals.len() == mesh.positions.len());
+        let is_occlusion = is_normals && meshes.iter().all(|mesh| mesh.occlusion.len() == mesh.positions.len());
+        let is_face_colors = !meshes.is_empty() && meshes.iter().all(|mesh| mesh.has_face_colors());
+
+        for mesh in meshes.iter() {
+            let offset = merged.positions.len() as u32;
+            merged.indices.extend(mesh.indices.iter().map(|idx| idx + offset));
+            merged.positions.extend_from_slice(&mesh.positions);
+            if is_normals {
+                merged.normals.extend_from_slice(&mesh.normals);
+            }
+            if is_occlusion {
+                merged.occlusion.extend_from_slice(&mesh.occlusion);
+            }
+            if is_face_colors {
+                merged.face_colors.extend_from_slice(&mesh.face_colors);
+            }
+        }
+        merged.normals_dirty = !is_normals;
+
+        merged
+    }
+
+    // polylines where plane dot(normal, p) = offset cuts the surface, closed loops end with their first point
+    pub fn section_contours(&self, normal: Vector3<f32>, offset: f32) -> Vec<Vec<Vector3<f32>>> {
+        let dist: Vec<f32> = self.positions.iter().map(|p| normal.dot(*p) - offset).collect();
+
+        // segment ends are identified by cut mesh edge, so neighbour faces share them exactly
+        let half_edge_mesh = HalfEdgeMesh::from(self);
+        let mut segments: Vec<[u32; 2]> = vec![];
+        let mut edge_ends: Vec<[u32; 2]> = vec![[0, 0]; half_edge_mesh.edges_cnt()];
+        for f in 0..half_edge_mesh.faces_cnt() as u32 {
+            let mut ends = vec![];
+            for h in half_edge_mesh.face_edges(f) {
+                let (a, b) = (half_edge_mesh.origin(h), half_edge_mesh.target(h));
+                if (dist[a as usize] >= 0.0) != (dist[b as usize] >= 0.0) {
+                    let edge = half_edge_mesh.edge(h);
+                    edge_ends[edge as usize] = [a, b];
+                    ends.push(edge);
+                }
+            }
+            if ends.len() == 2 {
+                segments.push([ends[0], ends[1]]);
+            }
+        }
+
+        IndexedMesh::chain_segments(&segments)
+            .iter()
+            .map(|edges| {
+                edges
+                    .iter()
+                    .map(|&edge| {
+                        let [a, b] = edge_ends[edge as usize];
+                        let (pa, pb) = (self.positions[a as usize], self.positions[b as usize]);
+                        let (da, db) = (dist[a as usize], dist[b as usize]);
+                        pa + (pb - pa) * (da / (da - db))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // outline of the mesh seen along normal, edges between faces turned toward and away from it
+    // and border edges, closed loops end with their first point, points stay in 3d
+    pub fn silhouette_contours(&self, normal: Vector3<f32>) -> Vec<Vec<Vector3<f32>>> {
+        let faces: Vec<[u32; 3]> = self.faces().collect();
+        let is_toward = |face_idxs: [u32; 3]| {
+            let [p0, p1, p2] = face_idxs.map(|idx| self.positions[idx as usize]);
+            (p1 - p0).cross(p2 - p0).dot(normal) >= 0.0
+        };
+
+        // non manifold edges count when their faces don't all agree
+        let half_edge_mesh = HalfEdgeMesh::from_faces(self.positions.len(), &faces);
+        let mut segments: Vec<[u32; 2]> = half_edge_mesh.edge_half_edges()
+            .into_iter()
+            .filter_map(|half_edges| {
+                let sides: Vec<bool> = half_edges
+                    .iter()
+                    .map(|&h| faces[half_edge_mesh.face(h) as usize])
+                    .filter(|&face_idxs| !self.is_degenerate_face(face_idxs))
+                    .map(is_toward)
+                    .collect();
+                let is_outline = sides.len() == 1 || sides.iter().any(|&side| side != sides[0]);
+
+                let (a, b) = (half_edge_mesh.origin(half_edges[0]), half_edge_mesh.target(half_edges[0]));
+                if is_outline { Some([a.min(b), a.max(b)]) } else { None }
+            })
+            .collect();
+        // hash map order would give different file on every export
+        segments.sort_unstable();
+
+        IndexedMesh::chain_segments(&segments)
+            .iter()
+            .map(|chain| chain.iter().map(|&idx| self.positions[idx as usize]).collect())
+            .collect()
+    }
+
+    // joins segments sharing ends into polylines, closed loops end with their first end
+    fn chain_segments<K: Copy + Eq + std::hash::Hash>(segments: &[[K; 2]]) -> Vec<Vec<K>> {
+        use std::collections::HashMap;
+
+        let mut end_segments: HashMap<K, Vec<usize>> = HashMap::new();
+        for (i, segment) in segments.iter().enumerate() {
+            end_segments.entry(segment[0]).or_default().push(i);
+            end_segments.entry(segment[1]).or_default().push(i);
+        }
+
+        let mut is_used = vec![false; segments.len()];
+        let next_end = |end: K, is_used: &mut Vec<bool>| {
+            let i = *end_segments.get(&end)?.iter().find(|&&i| !is_used[i])?;
+            is_used[i] = true;
+            Some(if segments[i][0] == end { segments[i][1] } else { segments[i][0] })
+        };
+
+        let mut chains = vec![];
+        for i in 0..segments.len() {
+            if is_used[i] { continue; }
+            is_used[i] = true;
+
+            let mut ends = vec![segments[i][0], segments[i][1]];
+            while let Some(end) = next_end(*ends.last().unwrap(), &mut is_used) {
+                ends.push(end);
+                if end == ends[0] { break; }
+            }
+            // open chain, grow it from the other end too
+            if ends.last() != ends.first() {
+                let mut ends_back = vec![];
+                while let Some(end) = next_end(*ends_back.last().unwrap_or(&ends[0]), &mut is_used) {
+                    ends_back.push(end);
+                }
+                ends_back.reverse();
+                ends_back.extend(ends);
+                ends = ends_back;
+            }
+
+            chains.push(ends);
+        }
+
+        chains
+    }
+
+    pub fn ensure_aabb_tree(&mut self) -> Arc<AabbTree> {
+        // drops stale tree of edited mesh
+        self.ensure_normals();
+
+        if self.aabb_tree.is_none() {
+            self.aabb_tree = Some(Arc::new(AabbTree::from(self)));
+        }
+
+        self.aabb_tree.clone().unwrap()
+    }
+
+    pub fn ensure_info(&mut self) -> MeshInfo {
+        // drops stale info of edited mesh
+        self.ensure_normals();
+
+        if self.info.is_none() {
+            self.info = Some(self.calculate_info());
+        }
+
+        self.info.unwrap()
+    }
+
+    pub fn calculate_info(&self) -> MeshInfo {
+        let edge_half_edges = HalfEdgeMesh::from(self).edge_half_edges();
+
+        let (min, max) = self.calculate_aabb();
+        MeshInfo {
+            vertices_cnt: self.positions.len(),
+            faces_cnt: self.indices.len() / 3,
+            size: if self.positions.is_empty() { Vector3::new(0.0, 0.0, 0.0) } else { max - min },
+            is_watertight: !edge_half_edges.is_empty() && edge_half_edges.iter().all(|half_edges| half_edges.len() == 2),
+        }
+    }
+
+    // fraction of hemisphere around vertex normal not blocked by nearby geometry, 1 is fully open
+    pub fn ambient_occlusion(&mut self, rays_cnt: usize) -> Vec<f32> {
+        const GOLDEN_ANGLE: f32 = 2.399_963;
+
+        let aabb_tree = self.ensure_aabb_tree();
+        let (min, max) = self.calculate_aabb();
+        let diagonal = (max - min).magnitude();
+
+        // only close geometry darkens, otherwise inside of a closed mesh blocks everything
+        let max_dist = diagonal * 0.25;
+        let offset = diagonal * 0.0001;
+        let rays_cnt = rays_cnt.max(1);
+
+        self.positions
+            .iter()
+            .zip(self.normals.iter())
+            .enumerate()
+            .map(|(i, (p, n))| {
+                if !(n.x.is_finite() && n.y.is_finite() && n.z.is_finite()) { return 1.0; }
+
+                let helper = if n.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+                let tangent = n.cross(helper).normalize();
+                let bitangent = n.cross(tangent);
+                let origin = p + n * offset;
+
+                // cosine weighted spiral, rotated per vertex to break up banding
+                let mut open_cnt = 0;
+                for k in 0..rays_cnt {
+                    let u = (k as f32 + 0.5) / rays_cnt as f32;
+                    let r = u.sqrt();
+                    let phi = k as f32 * GOLDEN_ANGLE + i as f32;
+                    let dir = tangent * (r * phi.cos()) + bitangent * (r * phi.sin()) + n * (1.0 - u).sqrt();
+
+                    if !aabb_tree.is_hit(origin, dir, max_dist) {
+                        open_cnt += 1;
+                    }
+                }
+
+                open_cnt as f32 / rays_cnt as f32
+            })
+            .collect()
+    }
+
+    // glTF TRIANGLE_STRIP to triangle list, every odd triangle flips winding,
+    // zero area triangles used to stitch strips together are dropped
+    pub fn triangles_from_strip(strip: &[u32]) -> Vec<u32> {
+        let mut indices = Vec::with_capacity(strip.len().saturating_sub(2) * 3);
+
+        for (i, face_idxs) in strip.windows(3).enumerate() {
+            let (a, b, c) = (face_idxs[0], face_idxs[1], face_idxs[2]);
+            if a == b || b == c || a == c { continue; }
+
+            if i % 2 == 0 {
+                indices.extend_from_slice(&[a, b, c]);
+            } else {
+                indices.extend_from_slice(&[b, a, c]);
+            }
+        }
+
+        indices
+    }
+
+    // glTF TRIANGLE_FAN to triangle list, all triangles share the first vertex
+    pub fn triangles_from_fan(fan: &[u32]) -> Vec<u32> {
+        let mut indices = Vec::with_capacity(fan.len().saturating_sub(2) * 3);
+
+        if let Some((&center, rest)) = fan.split_first() {
+            for edge in rest.windows(2) {
+                indices.extend_from_slice(&[center, edge[0], edge[1]]);
+            }
+        }
+
+        indices
+    }
+
+    // ear clipping in the best fit plane, concave polygons that fan would break come out right,
+    // returns corner triples into points keeping polygon winding
+    pub fn ear_clip(points: &[Vector3<f32>]) -> Vec<usize> {
+        let corners_cnt = points.len();
+        if corners_cnt < 3 { return vec![]; }
+
+        let fan = || (2..corners_cnt).flat_map(|k| [0, k - 1, k]).collect::<Vec<_>>();
+        if corners_cnt == 3 { return fan(); }
+
+        // newell normal points along polygon winding even for concave ones
+        let mut normal = Vector3::new(0.0f32, 0.0, 0.0);
+        for (k, p) in points.iter().enumerate() {
+            let q = points[(k + 1) % corners_cnt];
+            normal.x += (p.y - q.y) * (p.z + q.z);
+            normal.y += (p.z - q.z) * (p.x + q.x);
+            normal.z += (p.x - q.x) * (p.y + q.y);
+        }
+        if normal.magnitude2() <= std::f32::EPSILON * std::f32::EPSILON { return fan(); }
+        let normal = normal.normalize();
+
+        let helper = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+        let u = (helper - normal * normal.dot(helper)).normalize();
+        let v = normal.cross(u);
+        let points_2d: Vec<Vector2<f32>> = points.iter().map(|p| Vector2::new(u.dot(*p), v.dot(*p))).collect();
+
+        // polygon is counter clockwise in this basis, so ears turn left
+        let cross = |a: usize, b: usize, c: usize| {
+            (points_2d[b] - points_2d[a]).perp_dot(points_2d[c] - points_2d[a])
+        };
+
+        // convex polygons fan just fine and keep the order other importers produce
+        if (0..corners_cnt).all(|k| cross(k, (k + 1) % corners_cnt, (k + 2) % corners_cnt) > 0.0) {
+            return fan();
+        }
+
+        let mut remaining: Vec<usize> = (0..corners_cnt).collect();
+        let mut triangles = Vec::with_capacity((corners_cnt - 2) * 3);
+        while remaining.len() > 3 {
+            let remaining_cnt = remaining.len();
+            let ear = (0..remaining_cnt).find(|&k| {
+                let prev = remaining[(k + remaining_cnt - 1) % remaining_cnt];
+                let curr = remaining[k];
+                let next = remaining[(k + 1) % remaining_cnt];
+                if cross(prev, curr, next) <= 0.0 { return false; }
+
+                // no other corner may lie inside or on the ear
+                !remaining.iter().any(|&other| {
+                    other != prev && other != curr && other != next
+                        && cross(prev, curr, other) >= 0.0
+                        && cross(curr, next, other) >= 0.0
+                        && cross(next, prev, other) >= 0.0
+                })
+            });
+
+            match ear {
+                Some(k) => {
+                    let prev = remaining[(k + remaining_cnt - 1) % remaining_cnt];
+                    let next = remaining[(k + 1) % remaining_cnt];
+                    triangles.extend_from_slice(&[prev, remaining[k], next]);
+                    remaining.remove(k);
+                }
+                // self intersecting or degenerate rest, fan keeps at least all faces
+                None => break,
+            }
+        }
+        for k in 2..remaining.len() {
+            triangles.extend_from_slice(&[remaining[0], remaining[k - 1], remaining[k]]);
+        }
+
+        triangles
+    }
+
     pub fn box3d(len: Vector3<f32>) -> IndexedMesh {
+        IndexedMesh::box3d_at(len, Vector3::new(0.0f32, 0.0, 0.0))
+    }
 
-        let mut box3d = IndexedMesh::default();
+    pub fn box3d_at(len: Vector3<f32>, center: Vector3<f32>) -> IndexedMesh {
+        let mut box3d = IndexedMesh {
+            name: "Box".to_string(),
+            ..Default::default()
+        };
         let half_len = len / 2.0;
-        let center = Vector3::new(0.0f32, 0.0, 0.0);
 
         box3d.positions.push(center + Vector3::new(-half_len.x, -half_len.y, -half_len.z));
         box3d.positions.push(center + Vector3::new(half_len.x, -half_len.y, -half_len.z));
@@ -96,8 +927,148 @@ impl IndexedMesh {
         box3d.indices.extend_from_slice(&[1, 4, 0]);
         box3d.indices.extend_from_slice(&[4, 1, 5]);
 
-        box3d.recalculate_normals();
+        box3d.normals_dirty = true;
 
         box3d
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_keeps_normals_and_occlusion() {
+        let mut a = IndexedMesh::box3d(Vector3::new(1.0, 1.0, 1.0));
+        let mut b = IndexedMesh::box3d_at(Vector3::new(1.0, 1.0, 1.0), Vector3::new(3.0, 0.0, 0.0));
+        for mesh in [&mut a, &mut b] {
+            mesh.ensure_normals();
+            mesh.occlusion = vec![0.5; mesh.positions.len()];
+        }
+
+        let mut merged = IndexedMesh::merge(&[&a, &b]);
+        assert_eq!(merged.positions.len(), 16);
+        assert_eq!(merged.indices.len() / 3, 24);
+        assert_eq!(merged.indices[36..], b.indices.iter().map(|idx| idx + 8).collect::<Vec<_>>()[..]);
+
+        merged.ensure_normals();
+        assert_eq!(merged.normals[8..], b.normals[..]);
+        assert_eq!(merged.occlusion.len(), 16);
+
+        // stale part makes normals of the whole merge stale
+        b.normals_dirty = true;
+        let merged = IndexedMesh::merge(&[&a, &b]);
+        assert!(merged.normals_dirty && merged.occlusion.is_empty());
+    }
+
+    #[test]
+    fn remove_unused_vertices_compacts_attributes() {
+        let mut mesh = IndexedMesh {
+            positions: (0..6).map(|k| Vector3::new(k as f32, (k * k) as f32, 1.0)).collect(),
+            normals: (0..6).map(|k| Vector3::new(0.0, 0.0, k as f32)).collect(),
+            occlusion: (0..6).map(|k| k as f32 / 10.0).collect(),
+            // vertices 0 and 3 are orphans
+            indices: vec![1, 2, 4, 4, 2, 5],
+            ..Default::default()
+        };
+
+        assert_eq!(mesh.remove_unused_vertices(), 2);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 2, 1, 3]);
+        let kept = [1, 2, 4, 5];
+        assert_eq!(mesh.positions, kept.map(|k| Vector3::new(k as f32, (k * k) as f32, 1.0)));
+        assert_eq!(mesh.normals, kept.map(|k| Vector3::new(0.0, 0.0, k as f32)));
+        assert_eq!(mesh.occlusion, kept.map(|k| k as f32 / 10.0));
+
+        assert_eq!(mesh.remove_unused_vertices(), 0);
+    }
+
+    #[test]
+    fn faceted_cube_has_face_normals() {
+        let cube = IndexedMesh::box3d(Vector3::new(1.0, 1.0, 1.0));
+        let faceted = cube.to_faceted();
+        assert_eq!(faceted.positions.len(), 36);
+        assert_eq!(faceted.normals.len(), 36);
+        assert_eq!(faceted.indices, (0..36).collect::<Vec<_>>());
+
+        for face_idxs in faceted.faces() {
+            let [v0, v1, v2] = face_idxs.map(|idx| faceted.positions[idx as usize]);
+            let face_normal = (v1 - v0).cross(v2 - v0).normalize();
+            // box faces are axis aligned and point away from center
+            assert!((face_normal.x.abs().max(face_normal.y.abs()).max(face_normal.z.abs()) - 1.0).abs() < 1e-6);
+            assert!(face_normal.dot(v0) > 0.0);
+            for idx in face_idxs {
+                assert!((faceted.normals[idx as usize] - face_normal).magnitude() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn validate_drops_out_of_range_faces() {
+        let mut mesh = IndexedMesh {
+            positions: vec![Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0)],
+            indices: vec![0, 1, 2, 0, 1, 3, u32::MAX, 2, 1, 2, 1, 0],
+            face_colors: (0..4).map(|f| Vector3::new(f as f32, 0.0, 0.0)).collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(mesh.validate(), 2);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 2, 1, 0]);
+        // colors stay with the faces they belonged to
+        assert_eq!(mesh.face_colors, vec![Vector3::new(0.0, 0.0, 0.0), Vector3::new(3.0, 0.0, 0.0)]);
+        assert!(mesh.normals_dirty);
+        mesh.ensure_normals();
+
+        assert_eq!(mesh.validate(), 0);
+    }
+
+    #[test]
+    fn bounding_sphere_contains_all_vertices() {
+        // elongated random cloud, where aabb diagonal frames too loose
+        let mut seed = 12345u32;
+        let mut random = || {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+        let mesh = IndexedMesh {
+            positions: (0..1000).map(|_| Vector3::new(random() * 20.0, random(), random() * 3.0)).collect(),
+            ..Default::default()
+        };
+
+        let (center, radius) = mesh.bounding_sphere();
+        for p in mesh.positions.iter() {
+            assert!(p.distance(center) <= radius * (1.0 + 1e-5));
+        }
+        let (min, max) = mesh.calculate_aabb();
+        assert!(radius < (max - min).magnitude() / 2.0);
+
+        assert_eq!(IndexedMesh::default().bounding_sphere().1, 0.0);
+    }
+
+    #[test]
+    fn faces_skip_incomplete_tail() {
+        let mut mesh = IndexedMesh { indices: vec![0, 1, 2, 3, 4, 5, 6, 7], ..Default::default() };
+        assert_eq!(mesh.faces().collect::<Vec<_>>(), vec![[0, 1, 2], [3, 4, 5]]);
+
+        for face_idxs in mesh.faces_mut() {
+            face_idxs.swap(1, 2);
+        }
+        assert_eq!(mesh.indices, vec![0, 2, 1, 3, 5, 4, 6, 7]);
+
+        mesh.indices.truncate(2);
+        assert_eq!(mesh.faces().count(), 0);
+    }
+
+    #[test]
+    fn strip_alternates_winding() {
+        assert_eq!(IndexedMesh::triangles_from_strip(&[0, 1, 2, 3, 4]), vec![0, 1, 2, 2, 1, 3, 2, 3, 4]);
+        // degenerate stitch triangles are dropped without breaking parity of the rest
+        assert_eq!(IndexedMesh::triangles_from_strip(&[0, 1, 2, 2, 3, 4]), vec![0, 1, 2, 3, 2, 4]);
+        assert!(IndexedMesh::triangles_from_strip(&[0, 1]).is_empty());
+    }
+
+    #[test]
+    fn fan_shares_first_vertex() {
+        assert_eq!(IndexedMesh::triangles_from_fan(&[0, 1, 2, 3]), vec![0, 1, 2, 0, 2, 3]);
+        assert!(IndexedMesh::triangles_from_fan(&[]).is_empty());
+    }
+}
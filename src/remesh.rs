@@ -1,29 +1,481 @@
+use cgmath::*;
+
 use crate::mesh::IndexedMesh;
+use crate::spatial::AabbTree;
+use crate::halfedge::HalfEdgeMesh;
+use crate::simplification::WELD_EPS_RELATIVE;
 
 // just split triangles
 pub struct Remesher {}
 impl Remesher {
-    pub fn split_faces(mesh: &mut IndexedMesh, iteration: usize) {
+    // every iteration splits each face into three
+    pub fn projected_faces_count(faces_cnt: usize, iteration: usize) -> usize {
+        faces_cnt.saturating_mul(3usize.saturating_pow(iteration as u32))
+    }
+
+    // stops before an iteration that would give more than max_faces, returns number of iterations done
+    pub fn split_faces(mesh: &mut IndexedMesh, iteration: usize, max_faces: usize) -> usize {
         let mut new_indices = Vec::with_capacity(mesh.indices.len());
-        for _ in 0..iteration {
-            for face_idxs in mesh.indices.windows(3).step_by(3) {
+        let mut centroids = Vec::with_capacity(mesh.indices.len() / 3);
+        let mut done = 0;
+        while done < iteration && Remesher::projected_faces_count(mesh.indices.len() / 3, 1) <= max_faces {
+            let vertices_cnt = mesh.positions.len() as u32;
+            for face_idxs in mesh.faces() {
                 let v0 = mesh.positions[face_idxs[0] as usize];
                 let v1 = mesh.positions[face_idxs[1] as usize];
                 let v2 = mesh.positions[face_idxs[2] as usize];
 
-                let centroid = (v0 + v1 + v2) / 3.0;
-                let new_idx = mesh.positions.len() as u32;
-                mesh.positions.push(centroid);
+                let new_idx = vertices_cnt + centroids.len() as u32;
+                centroids.push((v0 + v1 + v2) / 3.0);
 
                 new_indices.extend([face_idxs[0], face_idxs[1], new_idx]);
                 new_indices.extend([face_idxs[1], face_idxs[2], new_idx]);
                 new_indices.extend([face_idxs[2], face_idxs[0], new_idx]);
             }
 
+            mesh.positions.append(&mut centroids);
             std::mem::swap(&mut mesh.indices, &mut new_indices);
             new_indices.clear();
+            done += 1;
+        }
+
+        mesh.normals_dirty = true;
+        done
+    }
+
+    // loop subdivision, every face splits into four and vertices move toward a smooth limit surface,
+    // border and non manifold edges are creases with 1/2-1/2 midpoints, their corners stay fixed,
+    // stops before an iteration that would give more than max_faces, returns number of iterations done
+    pub fn loop_subdivide(mesh: &mut IndexedMesh, iterations: usize, max_faces: usize) -> usize {
+        let mut done = 0;
+        while done < iterations && (mesh.indices.len() / 3).saturating_mul(4) <= max_faces {
+            // every edge of unwelded mesh (e.g. from stl) is a border and would stay a flat crease
+            if done == 0 {
+                let (min, max) = mesh.calculate_aabb();
+                mesh.weld_vertices((max - min).magnitude() * WELD_EPS_RELATIVE);
+            }
+            let vertices_cnt = mesh.positions.len();
+
+            // new vertex of every edge goes after the old ones in order of edges
+            let faces: Vec<[u32; 3]> = mesh.faces().collect();
+            let half_edge_mesh = HalfEdgeMesh::from_faces(vertices_cnt, &faces);
+            let edge_half_edges = half_edge_mesh.edge_half_edges();
+
+            let mut neighbours = vec![vec![]; vertices_cnt];
+            let mut crease_neighbours = vec![vec![]; vertices_cnt];
+            for half_edges in edge_half_edges.iter() {
+                let (a, b) = (half_edge_mesh.origin(half_edges[0]) as usize, half_edge_mesh.target(half_edges[0]) as usize);
+                neighbours[a].push(b);
+                neighbours[b].push(a);
+                if half_edges.len() != 2 {
+                    crease_neighbours[a].push(b);
+                    crease_neighbours[b].push(a);
+                }
+            }
+
+            let positions = &mesh.positions;
+            let mut new_positions: Vec<Vector3<f32>> = (0..vertices_cnt)
+                .map(|i| {
+                    let p = positions[i];
+                    match crease_neighbours[i].len() {
+                        0 if !neighbours[i].is_empty() => {
+                            let n = neighbours[i].len() as f32;
+                            let cos = (2.0 * std::f32::consts::PI / n).cos();
+                            let beta = (5.0 / 8.0 - (3.0 / 8.0 + cos / 4.0).powi(2)) / n;
+                            let sum = neighbours[i].iter().fold(Vector3::zero(), |sum, &j| sum + positions[j]);
+                            p * (1.0 - n * beta) + sum * beta
+                        }
+                        2 => {
+                            let (j0, j1) = (crease_neighbours[i][0], crease_neighbours[i][1]);
+                            p * 0.75 + (positions[j0] + positions[j1]) * 0.125
+                        }
+                        _ => p,
+                    }
+                })
+                .collect();
+
+            // vertices opposite to the edge weight interior edge points
+            let opposite = |h: u32| positions[half_edge_mesh.origin(half_edge_mesh.prev(h)) as usize];
+            new_positions.extend(edge_half_edges.iter().map(|half_edges| {
+                let (pa, pb) = (positions[half_edge_mesh.origin(half_edges[0]) as usize], positions[half_edge_mesh.target(half_edges[0]) as usize]);
+                match half_edges[..] {
+                    [h, g] => (pa + pb) * 0.375 + (opposite(h) + opposite(g)) * 0.125,
+                    _ => (pa + pb) * 0.5,
+                }
+            }));
+
+            let mut new_indices = Vec::with_capacity(mesh.indices.len() * 4);
+            let edge_point = |h: u32| vertices_cnt as u32 + half_edge_mesh.edge(h);
+            for (f, &[a, b, c]) in faces.iter().enumerate() {
+                let [ab, bc, ca] = half_edge_mesh.face_edges(f as u32).map(edge_point);
+                new_indices.extend([a, ab, ca, ab, b, bc, ca, bc, c, ab, bc, ca]);
+            }
+
+            if mesh.has_face_colors() {
+                mesh.face_colors = mesh.face_colors.iter().flat_map(|&color| [color; 4]).collect();
+            }
+            mesh.positions = new_positions;
+            mesh.indices = new_indices;
+            done += 1;
+        }
+
+        mesh.normals_dirty = true;
+        done
+    }
+
+    // laplacian smoothing without creases, every vertex moves toward the average of its neighbours,
+    // vertices on open borders stay fixed so the mesh doesn't shrink there
+    pub fn smooth_laplacian(mesh: &mut IndexedMesh, iterations: usize, lambda: f32) {
+        Remesher::smooth_laplacian_preserving(mesh, iterations, lambda, None);
+    }
+
+    // laplacian smoothing, every vertex moves toward the average of its neighbours and open borders stay fixed,
+    // edges with dihedral angle above feature_angle (degrees) are creases,
+    // vertex on a crease line is smoothed only along it and crease corners are fixed
+    pub fn smooth_laplacian_preserving(
+        mesh: &mut IndexedMesh,
+        iterations: usize,
+        lambda: f32,
+        feature_angle: Option<f32>
+    ) {
+        // every edge of unwelded mesh (e.g. from stl) is a border and would keep all vertices fixed,
+        // so smoothing runs on a welded copy and every corner takes the position of its welded vertex
+        let mut welded = mesh.clone();
+        let (min, max) = welded.calculate_aabb();
+        welded.weld_vertices((max - min).magnitude() * WELD_EPS_RELATIVE);
+        Remesher::smooth_welded(&mut welded, iterations, lambda, feature_angle);
+
+        // welding keeps the order of indices
+        for (&idx, &welded_idx) in mesh.indices.iter().zip(welded.indices.iter()) {
+            mesh.positions[idx as usize] = welded.positions[welded_idx as usize];
+        }
+        mesh.normals_dirty = true;
+    }
+
+    fn smooth_welded(mesh: &mut IndexedMesh, iterations: usize, lambda: f32, feature_angle: Option<f32>) {
+        let face_normals: Vec<Vector3<f32>> = mesh.faces()
+            .map(|face_idxs| {
+                let v0 = mesh.positions[face_idxs[0] as usize];
+                let v1 = mesh.positions[face_idxs[1] as usize];
+                let v2 = mesh.positions[face_idxs[2] as usize];
+                (v1 - v0).cross(v2 - v0).normalize()
+            })
+            .collect();
+
+        let half_edge_mesh = HalfEdgeMesh::from(mesh);
+        let mut is_feature_edge = vec![false; half_edge_mesh.edges_cnt()];
+        if let Some(feature_angle) = feature_angle {
+            for h in half_edge_mesh.feature_edges(&face_normals, feature_angle) {
+                is_feature_edge[half_edge_mesh.edge(h) as usize] = true;
+            }
+        }
+
+        let vertices_cnt = mesh.positions.len();
+        let mut neighbours = vec![vec![]; vertices_cnt];
+        let mut feature_neighbours = vec![vec![]; vertices_cnt];
+        let mut is_fixed = vec![false; vertices_cnt];
+        for half_edges in half_edge_mesh.edge_half_edges() {
+            let (a, b) = (half_edge_mesh.origin(half_edges[0]) as usize, half_edge_mesh.target(half_edges[0]) as usize);
+            neighbours[a].push(b);
+            neighbours[b].push(a);
+
+            // border and non manifold edges
+            if half_edges.len() != 2 {
+                is_fixed[a] = true;
+                is_fixed[b] = true;
+                continue;
+            }
+
+            if is_feature_edge[half_edge_mesh.edge(half_edges[0]) as usize] {
+                feature_neighbours[a].push(b);
+                feature_neighbours[b].push(a);
+            }
+        }
+
+        let smoothing_neighbours: Vec<Vec<usize>> = neighbours
+            .into_iter()
+            .zip(feature_neighbours)
+            .zip(is_fixed)
+            .map(|((neighbours, feature_neighbours), is_fixed)| {
+                let mut adjacent = match feature_neighbours.len() {
+                    _ if is_fixed => vec![],
+                    0 => neighbours,
+                    2 => feature_neighbours,
+                    _ => vec![],
+                };
+                // hash map order would make result differ between runs in last bits
+                adjacent.sort_unstable();
+                adjacent
+            })
+            .collect();
+
+        for _ in 0..iterations {
+            let positions = mesh.positions.clone();
+            for (i, adjacent) in smoothing_neighbours.iter().enumerate() {
+                if adjacent.is_empty() { continue; }
+
+                let sum = adjacent.iter().fold(Vector3::new(0.0f32, 0.0, 0.0), |sum, &j| sum + positions[j]);
+                let average = sum / adjacent.len() as f32;
+                mesh.positions[i] = positions[i] + (average - positions[i]) * lambda;
+            }
+        }
+    }
+
+    // rebuilds the surface from a distance grid, resolution is cells along the longest side,
+    // holes narrower than about two cells get closed and whatever is not reachable from outside is filled,
+    // cells are split into six tetrahedra for extraction, so the result is closed and manifold
+    pub fn voxel_remesh(mesh: &IndexedMesh, resolution: usize) -> IndexedMesh {
+        use std::collections::{HashMap, VecDeque};
+
+        // padding keeps the grid border out of the distance band
+        const PADDING: usize = 3;
+        // corners of a cell are x + 2y + 4z, tetrahedra go along the 0-7 diagonal
+        const TETRAHEDRA: [[usize; 4]; 6] = [
+            [0, 1, 3, 7], [0, 1, 5, 7], [0, 2, 3, 7], [0, 2, 6, 7], [0, 4, 5, 7], [0, 4, 6, 7]
+        ];
+
+        let (min, max) = mesh.calculate_aabb();
+        let extent = max - min;
+        let h = extent.x.max(extent.y).max(extent.z) / resolution.max(1) as f32;
+        if mesh.is_empty() || h <= 0.0 {
+            return IndexedMesh::default();
+        }
+
+        let origin = min - Vector3::new(h, h, h) * PADDING as f32;
+        let dims = [
+            (extent.x / h).ceil() as usize + 2 * PADDING + 1,
+            (extent.y / h).ceil() as usize + 2 * PADDING + 1,
+            (extent.z / h).ceil() as usize + 2 * PADDING + 1,
+        ];
+        let vertices_cnt = dims[0] * dims[1] * dims[2];
+        let grid_idx = |x: usize, y: usize, z: usize| (z * dims[1] + y) * dims[0] + x;
+        let grid_xyz = |i: usize| [i % dims[0], i / dims[0] % dims[1], i / (dims[0] * dims[1])];
+        let grid_pos = |i: usize| {
+            let [x, y, z] = grid_xyz(i);
+            origin + Vector3::new(x as f32, y as f32, z as f32) * h
+        };
+        let neighbors = |i: usize| {
+            let [x, y, z] = grid_xyz(i);
+            let (sx, sy) = (1, dims[0]);
+            let sz = dims[0] * dims[1];
+            [
+                (x > 0).then(|| i - sx), (x + 1 < dims[0]).then(|| i + sx),
+                (y > 0).then(|| i - sy), (y + 1 < dims[1]).then(|| i + sy),
+                (z > 0).then(|| i - sz), (z + 1 < dims[2]).then(|| i + sz),
+            ]
+        };
+
+        // exact distance only near faces, farther vertices are clamped to the band,
+        // edges with sign change always have both ends inside it
+        let band = 2.0 * h;
+        let mut is_near = vec![false; vertices_cnt];
+        for face_idxs in mesh.faces() {
+            let corners = face_idxs.map(|idx| mesh.positions[idx as usize]);
+            let lo: Vec<usize> = (0..3)
+                .map(|axis| {
+                    let p = corners.iter().map(|p| p[axis]).fold(std::f32::MAX, f32::min);
+                    ((p - band - origin[axis]) / h).floor().max(0.0) as usize
+                })
+                .collect();
+            let hi: Vec<usize> = (0..3)
+                .map(|axis| {
+                    let p = corners.iter().map(|p| p[axis]).fold(std::f32::MIN, f32::max);
+                    (((p + band - origin[axis]) / h).ceil() as usize).min(dims[axis] - 1)
+                })
+                .collect();
+            for z in lo[2]..=hi[2] {
+                for y in lo[1]..=hi[1] {
+                    for x in lo[0]..=hi[0] {
+                        is_near[grid_idx(x, y, z)] = true;
+                    }
+                }
+            }
+        }
+
+        // front side by normal of the nearest face, trusted only inside the band
+        let tree = AabbTree::from(mesh);
+        let mut dist = vec![band; vertices_cnt];
+        let mut is_front = vec![false; vertices_cnt];
+        for i in (0..vertices_cnt).filter(|&i| is_near[i]) {
+            let p = grid_pos(i);
+            if let Some((nearest, face)) = tree.nearest_point_face(p) {
+                let face = face as usize;
+                let v0 = mesh.positions[mesh.indices[3 * face] as usize];
+                let v1 = mesh.positions[mesh.indices[3 * face + 1] as usize];
+                let v2 = mesh.positions[mesh.indices[3 * face + 2] as usize];
+                dist[i] = nearest.distance(p).min(band);
+                is_front[i] = (p - nearest).dot((v1 - v0).cross(v2 - v0)) >= 0.0;
+            }
+        }
+        drop(is_near);
+
+        // outside is flooded from the grid border and can't pass closer than a cell to the surface,
+        // then it grows into the band only through vertices in front of faces
+        let closing = h;
+        let mut is_outside = vec![false; vertices_cnt];
+        let mut queue = VecDeque::new();
+        for i in 0..vertices_cnt {
+            let [x, y, z] = grid_xyz(i);
+            let is_border = x == 0 || y == 0 || z == 0 || x + 1 == dims[0] || y + 1 == dims[1] || z + 1 == dims[2];
+            if is_border && dist[i] > closing {
+                is_outside[i] = true;
+                queue.push_back(i);
+            }
+        }
+        while let Some(i) = queue.pop_front() {
+            for j in neighbors(i).into_iter().flatten() {
+                if is_outside[j] || dist[j] <= closing { continue; }
+
+                is_outside[j] = true;
+                queue.push_back(j);
+            }
+        }
+        queue.extend((0..vertices_cnt).filter(|&i| is_outside[i]));
+        while let Some(i) = queue.pop_front() {
+            for j in neighbors(i).into_iter().flatten() {
+                if is_outside[j] || dist[j] > closing || !is_front[j] { continue; }
+
+                is_outside[j] = true;
+                queue.push_back(j);
+            }
+        }
+
+        // signed by outside flag, kept off zero so every edge with sign change interpolates
+        let eps = h * 1e-4;
+        let value = |i: usize| if is_outside[i] { dist[i].max(eps) } else { -dist[i].max(eps) };
+
+        let mut result = IndexedMesh {
+            name: mesh.name.clone(),
+            normals_dirty: true,
+            ..Default::default()
+        };
+        let mut edge_vertices: HashMap<(usize, usize), u32> = HashMap::new();
+        let mut edge_vertex = |result: &mut IndexedMesh, a: usize, b: usize| {
+            *edge_vertices.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let (fa, fb) = (value(a), value(b));
+                let (pa, pb) = (grid_pos(a), grid_pos(b));
+                result.positions.push(pa + (pb - pa) * (fa / (fa - fb)));
+                result.positions.len() as u32 - 1
+            })
+        };
+
+        for z in 0..dims[2] - 1 {
+            for y in 0..dims[1] - 1 {
+                for x in 0..dims[0] - 1 {
+                    let corners: Vec<usize> = (0..8).map(|k| grid_idx(x + (k & 1), y + (k >> 1 & 1), z + (k >> 2 & 1))).collect();
+                    let outside_cnt = corners.iter().filter(|&&i| is_outside[i]).count();
+                    if outside_cnt == 0 || outside_cnt == 8 { continue; }
+
+                    for tetrahedron in TETRAHEDRA.iter() {
+                        let vertices = tetrahedron.map(|k| corners[k]);
+                        let (outs, ins): (Vec<usize>, Vec<usize>) = vertices.iter().partition(|&&i| is_outside[i]);
+
+                        let polygon = match (ins.len(), outs.len()) {
+                            (1, 3) => outs.iter().map(|&o| edge_vertex(&mut result, ins[0], o)).collect(),
+                            (3, 1) => ins.iter().map(|&i| edge_vertex(&mut result, i, outs[0])).collect(),
+                            (2, 2) => vec![
+                                edge_vertex(&mut result, ins[0], outs[0]),
+                                edge_vertex(&mut result, ins[0], outs[1]),
+                                edge_vertex(&mut result, ins[1], outs[1]),
+                                edge_vertex(&mut result, ins[1], outs[0]),
+                            ],
+                            _ => vec![],
+                        };
+                        if polygon.is_empty() { continue; }
+
+                        // faces look from inside to outside
+                        let center = |group: &[usize]| group.iter().map(|&i| grid_pos(i)).sum::<Vector3<f32>>() / group.len() as f32;
+                        let outward = center(&outs) - center(&ins);
+                        for k in 1..polygon.len() - 1 {
+                            let (a, b, c) = (polygon[0], polygon[k], polygon[k + 1]);
+                            let (pa, pb, pc) = (result.positions[a as usize], result.positions[b as usize], result.positions[c as usize]);
+                            if (pb - pa).cross(pc - pa).dot(outward) >= 0.0 {
+                                result.indices.extend([a, b, c]);
+                            } else {
+                                result.indices.extend([a, c, b]);
+                            }
+                        }
+                    }
+                }
+            }
         }
 
-        mesh.recalculate_normals();
+        // vertices interpolated right at grid vertices leave slivers
+        result.weld_vertices(h * 1e-4);
+        result.remove_degenerate_faces();
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every face with own corners, like stl gives it
+    fn unwelded_icosahedron() -> IndexedMesh {
+        let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+        let corners: Vec<Vector3<f32>> = [
+            [-1.0, t, 0.0], [1.0, t, 0.0], [-1.0, -t, 0.0], [1.0, -t, 0.0],
+            [0.0, -1.0, t], [0.0, 1.0, t], [0.0, -1.0, -t], [0.0, 1.0, -t],
+            [t, 0.0, -1.0], [t, 0.0, 1.0], [-t, 0.0, -1.0], [-t, 0.0, 1.0],
+        ].iter().map(|&[x, y, z]| Vector3::new(x, y, z).normalize()).collect();
+        let faces = [
+            [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+            [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+            [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+            [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+        ];
+
+        let mut mesh = IndexedMesh::default();
+        for face in faces {
+            for idx in face {
+                mesh.indices.push(mesh.positions.len() as u32);
+                mesh.positions.push(corners[idx]);
+            }
+        }
+        mesh
+    }
+
+    #[test]
+    fn loop_subdivide_rounds_unwelded_icosahedron() {
+        let radius_range = |mesh: &IndexedMesh| mesh.positions
+            .iter()
+            .map(|p| p.magnitude())
+            .fold((f32::MAX, 0.0f32), |(min, max), r| (min.min(r), max.max(r)));
+
+        let mut mesh = unwelded_icosahedron();
+        assert_eq!(Remesher::loop_subdivide(&mut mesh, 3, usize::MAX), 3);
+
+        // welded first, so it stays closed: 12 corners, 30 edges, 20 faces grown three times
+        assert_eq!(mesh.positions.len(), 642);
+        assert_eq!(mesh.indices.len() / 3, 1280);
+
+        // face centers of the flat icosahedron are at 0.79 of the corner radius, smooth one is nearly a sphere
+        let (min, max) = radius_range(&mesh);
+        assert!(min / max > 0.95);
+    }
+
+    #[test]
+    fn smooth_moves_unwelded_icosahedron() {
+        let mut mesh = unwelded_icosahedron();
+        let original = mesh.clone();
+        Remesher::smooth_laplacian(&mut mesh, 1, 0.5);
+
+        // layout of unwelded mesh is kept, but every corner moved toward the center
+        assert_eq!(mesh.positions.len(), original.positions.len());
+        assert_eq!(mesh.indices, original.indices);
+        for p in mesh.positions.iter() {
+            assert!(p.magnitude() < 0.99);
+        }
+
+        // copies of one corner stay together, so the surface stays closed
+        for (p, q) in mesh.positions.iter().zip(original.positions.iter()) {
+            for (p2, q2) in mesh.positions.iter().zip(original.positions.iter()) {
+                if (q - q2).magnitude() < 1e-6 {
+                    assert!((p - p2).magnitude() < 1e-6);
+                }
+            }
+        }
     }
 }
@@ -1,10 +1,15 @@
+use std::sync::atomic::AtomicBool;
+
 use cgmath::*;
 use egui_glow::glow;
 
-use crate::app::Settings;
+use crate::app::{LightingPreset, MeshState, Settings, ShadingMode};
 use crate::camera::OrbitalCamera;
 use crate::mesh::IndexedMesh;
 
+// draw_elements takes i32 count and browsers fail on huge single draws, so big meshes are drawn in parts
+const MAX_INDICES_PER_DRAW: usize = 1 << 24;
+
 enum RenderBuffersUsage {
     Static,
     Dynamic,
@@ -13,19 +18,34 @@ enum RenderBuffersUsage {
 struct IndexedMeshRenderBuffers {
     vertices_cnt: u32,
     triangles_cnt: u32,
+    edges_cnt: u32,
+
+    // scale of mean and gaussian curvature used to normalize colormap
+    curvature_range: [f32; 2],
+    // translucent meshes are sorted back to front by it
+    center: Vector3<f32>,
+    has_face_colors: bool,
 
     positions_vbo: glow::Buffer,
     normals_vbo: glow::Buffer,
+    curvature_vbo: glow::Buffer,
+    occlusion_vbo: glow::Buffer,
+    colors_vbo: glow::Buffer,
     indices_ebo: glow::Buffer,
+    // both ends of every edge, lines are drawn as instanced quads over them
+    lines_vbo: glow::Buffer,
 
     vao: glow::VertexArray,
+    lines_vao: glow::VertexArray,
 }
 
 impl IndexedMeshRenderBuffers {
     fn from_mesh(
         gl: &glow::Context,
         mesh: &IndexedMesh,
-        usage: RenderBuffersUsage
+        usage: RenderBuffersUsage,
+        shading_mode: ShadingMode,
+        has_lines: bool
     ) -> Result<IndexedMeshRenderBuffers, String> {
         use glow::HasContext as _;
 
@@ -34,6 +54,66 @@ impl IndexedMeshRenderBuffers {
             RenderBuffersUsage::Dynamic => glow::DYNAMIC_DRAW,
         };
 
+        // mean and gaussian curvature interleaved per vertex, slow on big meshes so only while it's shown
+        let has_curvature = RenderScene::is_curvature_shading(shading_mode);
+        let mut curvature: Vec<f32> = vec![];
+        let mut curvature_range = [1.0, 1.0];
+        if has_curvature {
+            let (mean_curvature, gaussian_curvature) = (mesh.mean_curvature(), mesh.gaussian_curvature());
+            curvature = mean_curvature
+                .iter()
+                .zip(gaussian_curvature.iter())
+                .flat_map(|(&mean, &gaussian)| [mean, gaussian])
+                .collect();
+            curvature_range = [
+                IndexedMeshRenderBuffers::calculate_colormap_range(mean_curvature),
+                IndexedMeshRenderBuffers::calculate_colormap_range(gaussian_curvature),
+            ];
+        }
+
+        // not baked means fully open
+        let mut occlusion = if mesh.occlusion.len() == mesh.positions.len() {
+            mesh.occlusion.clone()
+        } else {
+            vec![1.0f32; mesh.positions.len()]
+        };
+
+        // finding edges is slow on big meshes, so they are left empty until wireframe is shown
+        let segments: Vec<Vector3<f32>> = if has_lines {
+            mesh.calculate_edges()
+                .iter()
+                .map(|&idx| mesh.positions[idx as usize])
+                .collect()
+        } else {
+            vec![]
+        };
+
+        // face colors need own vertices for every face, so such meshes are uploaded with separate corners,
+        // only while colors are shown, other modes draw the shared ones
+        let has_face_colors = shading_mode == ShadingMode::FaceColor
+            && mesh.has_face_colors()
+            && mesh.normals.len() == mesh.positions.len();
+        let mut colors: Vec<Vector3<f32>> = vec![];
+        let expanded_positions: Vec<Vector3<f32>>;
+        let expanded_normals: Vec<Vector3<f32>>;
+        let expanded_indices: Vec<u32>;
+        let (positions, normals, indices) = if has_face_colors {
+            let corners = &mesh.indices[..mesh.face_colors.len() * 3];
+
+            expanded_positions = corners.iter().map(|&idx| mesh.positions[idx as usize]).collect();
+            expanded_normals = corners.iter().map(|&idx| mesh.normals[idx as usize]).collect();
+            expanded_indices = (0..corners.len() as u32).collect();
+            if has_curvature {
+                curvature = corners.iter().flat_map(|&idx| [curvature[2 * idx as usize], curvature[2 * idx as usize + 1]]).collect();
+            }
+            occlusion = corners.iter().map(|&idx| occlusion[idx as usize]).collect();
+            colors = (0..corners.len()).map(|corner| mesh.face_colors[corner / 3]).collect();
+
+            (&expanded_positions[..], &expanded_normals[..], &expanded_indices[..])
+        } else {
+            (&mesh.positions[..], &mesh.normals[..], &mesh.indices[..])
+        };
+
         unsafe {
             let vao = gl.create_vertex_array()?;
             gl.bind_vertex_array(Some(vao));
@@ -42,8 +122,8 @@ impl IndexedMeshRenderBuffers {
 
             gl.bind_buffer(glow::ARRAY_BUFFER, Some(positions_vbo));
             let positions_u8: &[u8] = core::slice::from_raw_parts(
-                mesh.positions.as_ptr() as *const u8,
-                mesh.positions.len() * 3 * core::mem::size_of::<f32>(),
+                positions.as_ptr() as *const u8,
+                positions.len() * 3 * core::mem::size_of::<f32>(),
             );
             gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, positions_u8, usage_gl);
             gl.enable_vertex_attrib_array(0);
@@ -53,82 +133,545 @@ impl IndexedMeshRenderBuffers {
 
             gl.bind_buffer(glow::ARRAY_BUFFER, Some(normals_vbo));
             let normals_u8: &[u8] = core::slice::from_raw_parts(
-                mesh.normals.as_ptr() as *const u8,
-                mesh.normals.len() * 3 * core::mem::size_of::<f32>(),
+                normals.as_ptr() as *const u8,
+                normals.len() * 3 * core::mem::size_of::<f32>(),
             );
             gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, normals_u8, usage_gl);
             gl.enable_vertex_attrib_array(1);
             gl.vertex_attrib_pointer_f32(1, 3, glow::FLOAT, false, 3 * core::mem::size_of::<f32>() as i32, 0);
 
+            // like colors, attribute stays disabled and unread without curvature
+            let curvature_vbo = gl.create_buffer()?;
+            if has_curvature {
+                gl.bind_buffer(glow::ARRAY_BUFFER, Some(curvature_vbo));
+                let curvature_u8: &[u8] = core::slice::from_raw_parts(
+                    curvature.as_ptr() as *const u8,
+                    curvature.len() * core::mem::size_of::<f32>(),
+                );
+                gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, curvature_u8, usage_gl);
+                gl.enable_vertex_attrib_array(2);
+                gl.vertex_attrib_pointer_f32(2, 2, glow::FLOAT, false, 2 * core::mem::size_of::<f32>() as i32, 0);
+            }
+
+            let occlusion_vbo = gl.create_buffer()?;
+
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(occlusion_vbo));
+            let occlusion_u8: &[u8] = core::slice::from_raw_parts(
+                occlusion.as_ptr() as *const u8,
+                occlusion.len() * core::mem::size_of::<f32>(),
+            );
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, occlusion_u8, usage_gl);
+            gl.enable_vertex_attrib_array(3);
+            gl.vertex_attrib_pointer_f32(3, 1, glow::FLOAT, false, core::mem::size_of::<f32>() as i32, 0);
+
+            // attribute stays disabled without colors, shader doesn't read it then
+            let colors_vbo = gl.create_buffer()?;
+            if has_face_colors {
+                gl.bind_buffer(glow::ARRAY_BUFFER, Some(colors_vbo));
+                let colors_u8: &[u8] = core::slice::from_raw_parts(
+                    colors.as_ptr() as *const u8,
+                    colors.len() * 3 * core::mem::size_of::<f32>(),
+                );
+                gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, colors_u8, usage_gl);
+                gl.enable_vertex_attrib_array(4);
+                gl.vertex_attrib_pointer_f32(4, 3, glow::FLOAT, false, 3 * core::mem::size_of::<f32>() as i32, 0);
+            }
+
             let indices_ebo = gl.create_buffer()?;
             gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(indices_ebo));
             let indices_u8: &[u8] = core::slice::from_raw_parts(
-                mesh.indices.as_ptr() as *const u8,
-                mesh.indices.len() * core::mem::size_of::<u32>(),
+                indices.as_ptr() as *const u8,
+                indices.len() * core::mem::size_of::<u32>(),
             );
             gl.buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, indices_u8, usage_gl);
 
+            // start and end of a segment advance once per instance, quad corners come from gl_VertexID
+            let lines_vao = gl.create_vertex_array()?;
+            gl.bind_vertex_array(Some(lines_vao));
+
+            let lines_vbo = gl.create_buffer()?;
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(lines_vbo));
+            let segments_u8: &[u8] = core::slice::from_raw_parts(
+                segments.as_ptr() as *const u8,
+                segments.len() * 3 * core::mem::size_of::<f32>(),
+            );
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, segments_u8, usage_gl);
+            for (location, offset) in [(0, 0), (1, 3 * core::mem::size_of::<f32>() as i32)] {
+                gl.enable_vertex_attrib_array(location);
+                gl.vertex_attrib_pointer_f32(location, 3, glow::FLOAT, false, 6 * core::mem::size_of::<f32>() as i32, offset);
+                gl.vertex_attrib_divisor(location, 1);
+            }
+
             gl.bind_vertex_array(None);
 
             Ok(IndexedMeshRenderBuffers {
-                vertices_cnt: mesh.positions.len() as u32,
-                triangles_cnt: (mesh.indices.len() / 3) as u32,
+                vertices_cnt: positions.len() as u32,
+                triangles_cnt: (indices.len() / 3) as u32,
+                edges_cnt: (segments.len() / 2) as u32,
+
+                curvature_range,
+                center: mesh.calculate_center_point(),
+                has_face_colors,
 
                 positions_vbo,
                 normals_vbo,
+                curvature_vbo,
+                occlusion_vbo,
+                colors_vbo,
                 indices_ebo,
+                lines_vbo,
                 vao,
+                lines_vao,
             })
         }
     }
 
+    // high percentile of magnitudes, so few extreme values don't wash out the colormap
+    fn calculate_colormap_range(mut values: Vec<f32>) -> f32 {
+        values.retain(|value| value.is_finite());
+        if values.is_empty() { return 1.0; }
+
+        for value in values.iter_mut() {
+            *value = value.abs();
+        }
+
+        let nth = (values.len() - 1) * 95 / 100;
+        let (_, range, _) = values.select_nth_unstable_by(nth, |a, b| a.partial_cmp(b).unwrap());
+
+        if *range > std::f32::EPSILON { *range } else { 1.0 }
+    }
+
+    // (byte offset, count) of every draw call, parts end on whole primitives
+    fn draw_ranges(indices_cnt: usize, primitive_size: usize) -> impl Iterator<Item = (i32, i32)> {
+        let per_draw = MAX_INDICES_PER_DRAW - MAX_INDICES_PER_DRAW % primitive_size;
+        debug_assert_eq!(indices_cnt % primitive_size, 0);
+        debug_assert!(indices_cnt * core::mem::size_of::<u32>() <= i32::MAX as usize, "index buffer offset overflows i32");
+
+        (0..indices_cnt).step_by(per_draw).map(move |start| {
+            let count = per_draw.min(indices_cnt - start);
+            ((start * core::mem::size_of::<u32>()) as i32, count as i32)
+        })
+    }
+
+    unsafe fn draw_triangles(&self, gl: &glow::Context) {
+        use glow::HasContext as _;
+
+        gl.bind_vertex_array(Some(self.vao));
+        for (offset, count) in IndexedMeshRenderBuffers::draw_ranges(self.triangles_cnt as usize * 3, 3) {
+            gl.draw_elements(glow::TRIANGLES, count, glow::UNSIGNED_INT, offset);
+        }
+    }
+
+    // two triangles per edge, expanded by the line program
+    unsafe fn draw_lines(&self, gl: &glow::Context) {
+        use glow::HasContext as _;
+
+        gl.bind_vertex_array(Some(self.lines_vao));
+        gl.draw_arrays_instanced(glow::TRIANGLES, 0, 6, self.edges_cnt as i32);
+    }
+
     pub fn destroy(&self, gl: &glow::Context) {
         use glow::HasContext as _;
         unsafe {
             gl.delete_vertex_array(self.vao);
+            gl.delete_vertex_array(self.lines_vao);
             gl.delete_buffer(self.positions_vbo);
             gl.delete_buffer(self.normals_vbo);
+            gl.delete_buffer(self.curvature_vbo);
+            gl.delete_buffer(self.occlusion_vbo);
+            gl.delete_buffer(self.colors_vbo);
             gl.delete_buffer(self.indices_ebo);
+            gl.delete_buffer(self.lines_vbo);
         }
     }
 }
 
+// offscreen normals and depth for outline detection, recreated when viewport size changes
+struct OutlineTarget {
+    width: i32,
+    height: i32,
+
+    fbo: glow::Framebuffer,
+    normals_texture: glow::Texture,
+    depth_texture: glow::Texture,
+}
+
+// screen sized texture sampled texel by texel
+unsafe fn create_target_texture(
+    gl: &glow::Context,
+    width: i32,
+    height: i32,
+    internal_format: u32,
+    format: u32,
+    ty: u32
+) -> Result<glow::Texture, String> {
+    use glow::HasContext as _;
+
+    let texture = gl.create_texture()?;
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    gl.tex_image_2d(glow::TEXTURE_2D, 0, internal_format as i32, width, height, 0, format, ty, None);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE as i32);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE as i32);
+    Ok(texture)
+}
+
+impl OutlineTarget {
+    fn new(gl: &glow::Context, width: i32, height: i32) -> Result<OutlineTarget, String> {
+        use glow::HasContext as _;
+
+        unsafe {
+            let create_texture = |internal_format: u32, format: u32, ty: u32| {
+                create_target_texture(gl, width, height, internal_format, format, ty)
+            };
+
+            let normals_texture = create_texture(glow::RGBA8, glow::RGBA, glow::UNSIGNED_BYTE)?;
+            let depth_texture = create_texture(glow::DEPTH_COMPONENT24, glow::DEPTH_COMPONENT, glow::UNSIGNED_INT)?;
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            let fbo = gl.create_framebuffer()?;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+            gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::TEXTURE_2D, Some(normals_texture), 0);
+            gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::DEPTH_ATTACHMENT, glow::TEXTURE_2D, Some(depth_texture), 0);
+            let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+
+            let outline_target = OutlineTarget { width, height, fbo, normals_texture, depth_texture };
+            if status != glow::FRAMEBUFFER_COMPLETE {
+                outline_target.destroy(gl);
+                return Err(format!("Outline framebuffer is incomplete: {:#x}", status));
+            }
+
+            Ok(outline_target)
+        }
+    }
+
+    fn destroy(&self, gl: &glow::Context) {
+        use glow::HasContext as _;
+        unsafe {
+            gl.delete_framebuffer(self.fbo);
+            gl.delete_texture(self.normals_texture);
+            gl.delete_texture(self.depth_texture);
+        }
+    }
+}
+
+// where egui wants the scene, fbo is None for the default framebuffer
+pub struct RenderTarget {
+    pub fbo: Option<glow::Framebuffer>,
+    // x, y from bottom, width, height in pixels
+    pub viewport: [i32; 4],
+}
+
+// weighted sums of translucent layers, float targets need EXT_color_buffer_float
+struct OitTarget {
+    width: i32,
+    height: i32,
+
+    fbo: glow::Framebuffer,
+    // premultiplied color times weight, alpha is product of transmittances
+    accum_texture: glow::Texture,
+    // sum of alpha times weight
+    accum_alpha_texture: glow::Texture,
+    depth_texture: glow::Texture,
+}
+
+impl OitTarget {
+    fn new(gl: &glow::Context, width: i32, height: i32) -> Result<OitTarget, String> {
+        use glow::HasContext as _;
+
+        unsafe {
+            let accum_texture = create_target_texture(gl, width, height, glow::RGBA16F, glow::RGBA, glow::HALF_FLOAT)?;
+            let accum_alpha_texture = create_target_texture(gl, width, height, glow::R16F, glow::RED, glow::HALF_FLOAT)?;
+            let depth_texture = create_target_texture(
+                gl, width, height, glow::DEPTH_COMPONENT24, glow::DEPTH_COMPONENT, glow::UNSIGNED_INT
+            )?;
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            let fbo = gl.create_framebuffer()?;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+            gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::TEXTURE_2D, Some(accum_texture), 0);
+            gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT1, glow::TEXTURE_2D, Some(accum_alpha_texture), 0);
+            gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::DEPTH_ATTACHMENT, glow::TEXTURE_2D, Some(depth_texture), 0);
+            gl.draw_buffers(&[glow::COLOR_ATTACHMENT0, glow::COLOR_ATTACHMENT1]);
+            let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+
+            let oit_target = OitTarget { width, height, fbo, accum_texture, accum_alpha_texture, depth_texture };
+            if status != glow::FRAMEBUFFER_COMPLETE {
+                oit_target.destroy(gl);
+                return Err(format!("Transparency framebuffer is incomplete: {:#x}", status));
+            }
+
+            Ok(oit_target)
+        }
+    }
+
+    fn destroy(&self, gl: &glow::Context) {
+        use glow::HasContext as _;
+        unsafe {
+            gl.delete_framebuffer(self.fbo);
+            gl.delete_texture(self.accum_texture);
+            gl.delete_texture(self.accum_alpha_texture);
+            gl.delete_texture(self.depth_texture);
+        }
+    }
+}
+
+// lights are placed a few scene radii from the scene center, so they are outside of the model at any scale
+#[derive(Clone, Copy)]
+pub struct LightRig {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl Default for LightRig {
+    fn default() -> Self {
+        LightRig {
+            center: Vector3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+        }
+    }
+}
+
+impl LightRig {
+    const LIGHTS_CNT: usize = 3;
+    const DIST_IN_RADII: f32 = 4.0;
+
+    pub fn from_bounds(center: Vector3<f32>, radius: f32) -> Self {
+        LightRig {
+            center,
+            radius: if radius > 0.0 { radius } else { 1.0 },
+        }
+    }
+
+    // view space positions and intensities, unused lights have zero intensity
+    pub fn view_lights(&self, settings: &Settings, view: &Matrix4<f32>) -> [(Vector3<f32>, f32); LightRig::LIGHTS_CNT] {
+        let dist = self.radius * LightRig::DIST_IN_RADII;
+        let center = (view * self.center.extend(1.0)).truncate();
+        // camera relative directions, x right, y up, z towards the viewer
+        let from_camera = |dir: Vector3<f32>| center + dir.normalize() * dist;
+        let from_world = |dir: Vector3<f32>| (view * (self.center + dir.normalize() * dist).extend(1.0)).truncate();
+        let off = (center, 0.0);
+
+        match settings.lighting_preset {
+            LightingPreset::Studio => [
+                // key from upper left front, fill from lower right front, rim from behind
+                (from_camera(Vector3::new(-1.0, 1.0, 1.0)), 0.9),
+                (from_camera(Vector3::new(1.0, -0.3, 1.0)), 0.35),
+                (from_camera(Vector3::new(0.3, 0.8, -1.0)), 0.6),
+            ],
+            LightingPreset::Top => [
+                (from_world(Vector3::unit_y()), 0.9),
+                (from_camera(Vector3::unit_z()), 0.3),
+                off,
+            ],
+            LightingPreset::Headlight => [
+                (Vector3::new(0.0, 0.0, 0.0), 1.0),
+                off,
+                off,
+            ],
+            LightingPreset::Manual => [
+                ((view * Vector3::from(settings.light_pos).extend(1.0)).truncate(), 1.0),
+                off,
+                off,
+            ],
+        }
+    }
+}
+
+// set by webgl canvas event listeners, app picks them up on the next frame
+pub static IS_CONTEXT_LOST: AtomicBool = AtomicBool::new(false);
+pub static IS_CONTEXT_RESTORED: AtomicBool = AtomicBool::new(false);
+
 pub struct RenderScene {
-    program_default_indexed_mesh: glow::Program,
+    // None when shaders failed to build, nothing is drawn until retry or fallback
+    program_default_indexed_mesh: Option<glow::Program>,
     indexed_render_buffers: Vec<IndexedMeshRenderBuffers>,
+    // coarse copies of heavy static meshes, drawn instead of them while camera moves
+    indexed_render_buffers_lod: Vec<Option<IndexedMeshRenderBuffers>>,
     indexed_render_buffers_temp: Vec<IndexedMeshRenderBuffers>,
+    // meshes from before the last apply, kept for comparison until discarded
+    indexed_render_buffers_original: Vec<IndexedMeshRenderBuffers>,
+    // scale reference from settings with the center and size it was built for
+    reference_render_buffers: Option<(Vector3<f32>, f32, IndexedMeshRenderBuffers)>,
+
+    // wide lines, webgl clamps line width of plain lines to a single pixel
+    program_line: Option<glow::Program>,
+
+    program_outline: Option<glow::Program>,
+    outline_target: Option<OutlineTarget>,
+
+    program_oit_composite: Option<glow::Program>,
+    oit_target: Option<OitTarget>,
+    // set when float targets can't be created, translucent meshes are sorted instead
+    is_oit_unsupported: bool,
+
+    // compile and link logs of the last failed shader build
+    shader_error: Option<String>,
+    // what mesh buffers were uploaded for, curvature and face colors are only uploaded while they are shown
+    shading_mode: ShadingMode,
+    // lines are uploaded the first time wireframe is shown and kept after
+    has_lines: bool,
+
+    // gl objects of lost context are gone, nothing can be drawn or uploaded until restore
+    is_context_lost: bool,
 }
 
 // for glow
 #[allow(unsafe_code)]
 impl RenderScene {
     pub fn new(gl: &glow::Context) -> Self {
-        use glow::HasContext as _;
-
-        let shader_version = if cfg!(target_arch = "wasm32") {
-            "#version 300 es"
-        } else {
-            "#version 410"
+        let mut render_scene = Self {
+            program_default_indexed_mesh: None,
+            indexed_render_buffers: vec![],
+            indexed_render_buffers_lod: vec![],
+            indexed_render_buffers_temp: vec![],
+            indexed_render_buffers_original: vec![],
+            reference_render_buffers: None,
+            program_line: None,
+            program_outline: None,
+            outline_target: None,
+            program_oit_composite: None,
+            oit_target: None,
+            is_oit_unsupported: false,
+            shader_error: None,
+            shading_mode: ShadingMode::Color,
+            has_lines: false,
+            is_context_lost: false,
         };
+        render_scene.retry_shaders(gl);
+
+        render_scene
+    }
+
+    pub fn shader_error(&self) -> Option<&str> {
+        self.shader_error.as_deref()
+    }
+
+    // builds all programs again, failed ones stay None and their logs go to shader_error
+    pub fn retry_shaders(&mut self, gl: &glow::Context) {
+        self.delete_programs(gl);
+
+        let mut errors = vec![];
+        match RenderScene::create_program(gl) {
+            Ok(program) => self.program_default_indexed_mesh = Some(program),
+            Err(err) => errors.push(format!("mesh shader:\n{}", err)),
+        }
+        match RenderScene::create_line_program(gl) {
+            Ok(program) => self.program_line = Some(program),
+            Err(err) => errors.push(format!("line shader:\n{}", err)),
+        }
+        match RenderScene::create_outline_program(gl) {
+            Ok(program) => self.program_outline = Some(program),
+            Err(err) => errors.push(format!("outline shader:\n{}", err)),
+        }
+        match RenderScene::create_oit_composite_program(gl) {
+            Ok(program) => self.program_oit_composite = Some(program),
+            Err(err) => errors.push(format!("transparency shader:\n{}", err)),
+        }
+
+        self.shader_error = if errors.is_empty() { None } else { Some(errors.join("\n")) };
+    }
 
+    // flat unlit shader, keeps meshes visible when the full one doesn't build
+    pub fn use_basic_shader(&mut self, gl: &glow::Context) {
+        self.delete_programs(gl);
+
+        match RenderScene::create_basic_program(gl) {
+            Ok(program) => {
+                self.program_default_indexed_mesh = Some(program);
+                self.shader_error = None;
+            }
+            Err(err) => self.shader_error = Some(format!("basic shader:\n{}", err)),
+        }
+        // wireframe is simply left out when even this one fails
+        self.program_line = RenderScene::create_line_program(gl).ok();
+    }
+
+    fn delete_programs(&mut self, gl: &glow::Context) {
+        use glow::HasContext as _;
         unsafe {
-            let program = gl.create_program().expect("Cannot create program");
+            if let Some(program) = self.program_default_indexed_mesh.take() {
+                gl.delete_program(program);
+            }
+            if let Some(program) = self.program_line.take() {
+                gl.delete_program(program);
+            }
+            if let Some(program) = self.program_outline.take() {
+                gl.delete_program(program);
+            }
+            if let Some(program) = self.program_oit_composite.take() {
+                gl.delete_program(program);
+            }
+        }
+    }
+
+    fn create_basic_program(gl: &glow::Context) -> Result<glow::Program, String> {
+        unsafe {
+            let (vertex_shader_source, fragment_shader_source) = (
+                r#"
+                    layout (location = 0) in vec3 in_position;
+
+                    uniform mat4 u_model;
+                    uniform mat4 u_view;
+                    uniform mat4 u_proj;
+
+                    uniform float u_point_size;
+
+                    void main() {
+                        gl_PointSize = u_point_size;
+                        gl_Position = u_proj * u_view * u_model * vec4(in_position.xyz, 1.0);
+                    }
+                "#,
+                r#"
+                    precision mediump float;
+
+                    out vec4 out_color;
+
+                    uniform vec4 u_color;
+
+                    void main() {
+                        out_color = u_color;
+                    }
+                "#,
+            );
 
+            RenderScene::compile_program(gl, vertex_shader_source, fragment_shader_source)
+        }
+    }
+
+    fn create_program(gl: &glow::Context) -> Result<glow::Program, String> {
+        unsafe {
             let (vertex_shader_source, fragment_shader_source) = (
                 r#"
                     layout (location = 0) in vec3 in_position;
                     layout (location = 1) in vec3 in_normal;
+                    layout (location = 2) in vec2 in_curvature;
+                    layout (location = 3) in float in_occlusion;
+                    layout (location = 4) in vec3 in_color;
 
                     out vec3 vs_out_pos;
                     out vec3 vs_out_unproject_pos;
                     out vec3 vs_out_normal;
+                    out vec2 vs_out_curvature;
+                    out float vs_out_occlusion;
+                    out vec3 vs_out_color;
 
                     uniform mat4 u_model;
                     uniform mat4 u_view;
                     uniform mat4 u_proj;
 
+                    uniform float u_point_size;
+
                     void main() {
+                        gl_PointSize = u_point_size;
+                        vs_out_curvature = in_curvature;
+                        vs_out_occlusion = in_occlusion;
+                        vs_out_color = in_color;
                         vs_out_pos = vec3(u_view * u_model * vec4(in_position.xyz, 1.0));
+                        vs_out_unproject_pos = vec3(u_model * vec4(in_position.xyz, 1.0));
                         vs_out_normal = mat3(transpose(inverse(u_view * u_model))) * in_normal;
                         gl_Position = u_proj * u_view * u_model * vec4(in_position.xyz, 1.0);
                     }
@@ -137,17 +680,65 @@ impl RenderScene {
                     precision mediump float;
 
                     in vec3 vs_out_pos;
+                    in vec3 vs_out_unproject_pos;
                     in vec3 vs_out_normal;
+                    in vec2 vs_out_curvature;
+                    in float vs_out_occlusion;
+                    in vec3 vs_out_color;
 
-                    out vec4 out_color;
+                    layout (location = 0) out vec4 out_color;
+                    // second target of transparency pass, ignored by single target framebuffers
+                    layout (location = 1) out vec4 out_accum_alpha;
 
-                    uniform vec3 u_light_pos;
-                    uniform vec3 u_camera_pos;
+                    uniform vec3 u_light_pos[3];
+                    uniform float u_light_intensity[3];
                     uniform vec4 u_color;
 
+                    uniform float u_rim_strength;
+                    uniform float u_rim_power;
+                    uniform vec3 u_rim_color;
+
                     uniform int u_is_flat_shading;
+                    uniform int u_is_orthographic;
+                    uniform int u_is_unlit;
+                    // offscreen pass for outlines writes packed normals instead of color
+                    uniform int u_is_normal_pass;
+                    // offscreen pass for order independent transparency writes weighted sums
+                    uniform int u_is_oit_pass;
+
+                    // world space plane as normal and offset, everything in front of it is cut away
+                    uniform int u_is_clipping;
+                    uniform vec4 u_clip_plane;
+
+                    // 0 plain color, 1 mean curvature, 2 gaussian curvature, 3 face color
+                    uniform int u_shading_mode;
+                    uniform vec2 u_curvature_range;
+                    uniform int u_has_face_colors;
+
+                    // back sides of faces get own color, so inverted patches stand out
+                    uniform int u_is_backface_highlight;
+                    uniform vec3 u_backface_color;
+
+                    // diverging blue-white-red map of t in [-1, 1]
+                    vec3 colormap(float t) {
+                        vec3 white = vec3(0.9, 0.9, 0.9);
+                        if (t < 0.0) {
+                            return mix(white, vec3(0.1, 0.3, 0.9), -t);
+                        }
+                        return mix(white, vec3(0.9, 0.2, 0.1), t);
+                    }
 
                     void main() {
+                        // webgl2 has no gl_ClipDistance, so clip per fragment
+                        if (u_is_clipping == 1 && dot(u_clip_plane.xyz, vs_out_unproject_pos) > u_clip_plane.w) {
+                            discard;
+                        }
+
+                        if (u_is_unlit == 1) {
+                            out_color = u_color;
+                            return;
+                        }
+
                         vec3 normal;
                         if (u_is_flat_shading == 0) {
                             normal = normalize(vs_out_normal);
@@ -155,23 +746,61 @@ impl RenderScene {
                             normal = normalize(cross(dFdx(vs_out_pos), dFdy(vs_out_pos)));
                         }
 
-                        vec3 light_dir = normalize(u_light_pos - vs_out_pos);
+                        if (u_is_normal_pass == 1) {
+                            out_color = vec4(normal * 0.5 + 0.5, 1.0);
+                            return;
+                        }
+
                         vec3 light_color = vec3(1.0, 1.0, 1.0);
 
-                        vec3 view_dir = normalize(u_camera_pos - vs_out_pos);
-                        vec3 reflect_dir = reflect(-light_dir, normal);
+                        // positions are in view space so eye is at origin, parallel rays all go along z
+                        vec3 view_dir = u_is_orthographic == 1 ? vec3(0.0, 0.0, 1.0) : normalize(-vs_out_pos);
 
                         float ambient_strength = 0.1;
                         vec3 ambient = ambient_strength * light_color;
-                        
-                        float diff = max(dot(normal, light_dir), 0.0);
-                        vec3 diffuse = diff * light_color;
 
                         float specular_strength = 0.5;
-                        float spec = pow(max(dot(view_dir, reflect_dir), 0.0), 32.0);
-                        vec3 specular = specular_strength * spec * light_color;
+                        vec3 diffuse = vec3(0.0);
+                        vec3 specular = vec3(0.0);
+                        for (int i = 0; i < 3; i++) {
+                            vec3 light_dir = normalize(u_light_pos[i] - vs_out_pos);
+                            vec3 reflect_dir = reflect(-light_dir, normal);
 
-                        vec3 color = (ambient + diffuse + specular) * u_color.rgb;
+                            float diff = max(dot(normal, light_dir), 0.0);
+                            diffuse += u_light_intensity[i] * diff * light_color;
+
+                            float spec = pow(max(dot(view_dir, reflect_dir), 0.0), 32.0);
+                            specular += u_light_intensity[i] * specular_strength * spec * light_color;
+                        }
+
+                        // fresnel like rim
+                        float rim = u_rim_strength * pow(1.0 - max(dot(view_dir, normal), 0.0), u_rim_power);
+
+                        vec3 base_color = u_color.rgb;
+                        if (u_shading_mode == 1) {
+                            base_color = colormap(clamp(vs_out_curvature.x / u_curvature_range.x, -1.0, 1.0));
+                        } else if (u_shading_mode == 2) {
+                            base_color = colormap(clamp(vs_out_curvature.y / u_curvature_range.y, -1.0, 1.0));
+                        } else if (u_shading_mode == 3 && u_has_face_colors == 1) {
+                            base_color = vs_out_color;
+                        }
+                        if (u_is_backface_highlight == 1 && !gl_FrontFacing) {
+                            base_color = u_backface_color;
+                        }
+
+                        vec3 color = ((ambient + diffuse) * vs_out_occlusion + specular) * base_color + rim * u_rim_color;
+
+                        // weight falls with depth, so nearer layers dominate (McGuire and Bavoil 2013)
+                        if (u_is_oit_pass == 1) {
+                            float alpha = u_color.a;
+                            highp float weight = clamp(
+                                pow(min(1.0, alpha * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - gl_FragCoord.z * 0.9, 3.0),
+                                1e-2, 3e3
+                            );
+                            out_color = vec4(color * alpha * weight, alpha);
+                            out_accum_alpha = vec4(alpha * weight);
+                            return;
+                        }
 
                         out_color = vec4(color, u_color.a);
                     }
@@ -179,55 +808,431 @@ impl RenderScene {
 
             );
 
-            let shader_sources = [
-                (glow::VERTEX_SHADER, vertex_shader_source),
-                (glow::FRAGMENT_SHADER, fragment_shader_source),
-            ];
+            RenderScene::compile_program(gl, vertex_shader_source, fragment_shader_source)
+        }
+    }
 
-            let shaders: Vec<_> = shader_sources
-                .iter()
-                .map(|(shader_type, shader_source)| {
-                    let shader = gl
-                        .create_shader(*shader_type)
-                        .expect("Cannot create shader");
-                    gl.shader_source(shader, &format!("{}\n{}", shader_version, shader_source));
-                    gl.compile_shader(shader);
-                    if !gl.get_shader_compile_status(shader) {
-                        panic!("{}", gl.get_shader_info_log(shader));
+    // every segment becomes a screen space quad of constant width in pixels,
+    // fragments fade out over the last pixel at its sides for antialiasing
+    fn create_line_program(gl: &glow::Context) -> Result<glow::Program, String> {
+        unsafe {
+            let (vertex_shader_source, fragment_shader_source) = (
+                r#"
+                    layout (location = 0) in vec3 in_start;
+                    layout (location = 1) in vec3 in_end;
+
+                    out vec3 vs_out_unproject_pos;
+                    // distance from the center line in pixels
+                    out float vs_out_offset;
+
+                    uniform mat4 u_model;
+                    uniform mat4 u_view;
+                    uniform mat4 u_proj;
+
+                    uniform vec2 u_viewport_size;
+                    uniform float u_line_width;
+
+                    // quad corners as end of segment and side of the line
+                    const vec2 corners[6] = vec2[6](
+                        vec2(0.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+                        vec2(0.0, -1.0), vec2(1.0, 1.0), vec2(0.0, 1.0)
+                    );
+
+                    void main() {
+                        vec2 corner = corners[gl_VertexID];
+                        vec4 start = u_proj * u_view * u_model * vec4(in_start, 1.0);
+                        vec4 end = u_proj * u_view * u_model * vec4(in_end, 1.0);
+
+                        // end behind the eye would flip the direction on screen, so segment is cut at near plane
+                        float start_near = start.z + start.w;
+                        float end_near = end.z + end.w;
+                        if (start_near < 0.0 && end_near < 0.0) {
+                            gl_Position = vec4(0.0, 0.0, 2.0, 1.0);
+                            return;
+                        }
+                        if (start_near < 0.0) {
+                            start = mix(start, end, start_near / (start_near - end_near));
+                        } else if (end_near < 0.0) {
+                            end = mix(end, start, end_near / (end_near - start_near));
+                        }
+
+                        vec2 half_viewport = 0.5 * u_viewport_size;
+                        vec2 start_screen = start.xy / start.w * half_viewport;
+                        vec2 end_screen = end.xy / end.w * half_viewport;
+                        vec2 screen_dir = end_screen - start_screen;
+                        vec2 dir = length(screen_dir) > 1e-6 ? normalize(screen_dir) : vec2(1.0, 0.0);
+                        vec2 normal = vec2(-dir.y, dir.x);
+
+                        // extra pixel for the fade and square caps, so joined edges have no gaps
+                        float half_width = 0.5 * u_line_width + 1.0;
+                        vec2 offset = normal * corner.y * half_width + dir * (corner.x * 2.0 - 1.0) * 0.5 * u_line_width;
+
+                        vec4 pos = mix(start, end, corner.x);
+                        vs_out_unproject_pos = vec3(u_model * vec4(mix(in_start, in_end, corner.x), 1.0));
+                        vs_out_offset = corner.y * half_width;
+                        gl_Position = vec4(pos.xy + offset / half_viewport * pos.w, pos.zw);
                     }
-                    gl.attach_shader(program, shader);
-                    shader
-                })
-                .collect();
+                "#,
+                r#"
+                    precision mediump float;
+
+                    in vec3 vs_out_unproject_pos;
+                    in float vs_out_offset;
 
+                    out vec4 out_color;
+
+                    uniform vec4 u_color;
+                    uniform float u_line_width;
+
+                    uniform int u_is_clipping;
+                    uniform vec4 u_clip_plane;
+
+                    void main() {
+                        if (u_is_clipping == 1 && dot(u_clip_plane.xyz, vs_out_unproject_pos) > u_clip_plane.w) {
+                            discard;
+                        }
+
+                        float coverage = clamp(0.5 * u_line_width + 0.5 - abs(vs_out_offset), 0.0, 1.0);
+                        if (coverage <= 0.0) {
+                            discard;
+                        }
+                        out_color = vec4(u_color.rgb, u_color.a * coverage);
+                    }
+                "#,
+            );
+
+            RenderScene::compile_program(gl, vertex_shader_source, fragment_shader_source)
+        }
+    }
+
+    // edges from jumps of normal and depth of the offscreen pass, drawn over the scene
+    fn create_outline_program(gl: &glow::Context) -> Result<glow::Program, String> {
+        unsafe {
+            let (vertex_shader_source, fragment_shader_source) = (
+                r#"
+                    out vec2 vs_out_uv;
+
+                    void main() {
+                        // single triangle covering the viewport
+                        vec2 pos = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2));
+                        vs_out_uv = pos;
+                        gl_Position = vec4(pos * 2.0 - 1.0, 0.0, 1.0);
+                    }
+                "#,
+                r#"
+                    precision highp float;
+
+                    in vec2 vs_out_uv;
+
+                    out vec4 out_color;
+
+                    // view space normal packed to [0, 1], alpha marks covered pixels
+                    uniform sampler2D u_normals;
+                    uniform sampler2D u_depth;
+
+                    uniform vec2 u_texel_size;
+                    uniform float u_near;
+                    uniform float u_far;
+                    uniform int u_is_orthographic;
+                    uniform vec3 u_color;
+
+                    float linear_depth(vec2 uv) {
+                        if (texture(u_normals, uv).a == 0.0) {
+                            return u_far;
+                        }
+                        float z = texture(u_depth, uv).r * 2.0 - 1.0;
+                        if (u_is_orthographic == 1) {
+                            return u_near + (z * 0.5 + 0.5) * (u_far - u_near);
+                        }
+                        return 2.0 * u_near * u_far / (u_far + u_near - z * (u_far - u_near));
+                    }
+
+                    void main() {
+                        vec3 normal_gx = vec3(0.0);
+                        vec3 normal_gy = vec3(0.0);
+                        float depth_gx = 0.0;
+                        float depth_gy = 0.0;
+                        float depth_min = u_far;
+                        float coverage = 0.0;
+
+                        // sobel over 3x3 neighbourhood
+                        for (int i = -1; i <= 1; i++) {
+                            for (int j = -1; j <= 1; j++) {
+                                vec2 uv = vs_out_uv + vec2(float(i), float(j)) * u_texel_size;
+                                vec4 normal = texture(u_normals, uv);
+                                float depth = linear_depth(uv);
+
+                                float wx = float(i) * (j == 0 ? 2.0 : 1.0);
+                                float wy = float(j) * (i == 0 ? 2.0 : 1.0);
+                                normal_gx += wx * normal.rgb;
+                                normal_gy += wy * normal.rgb;
+                                depth_gx += wx * depth;
+                                depth_gy += wy * depth;
+
+                                depth_min = min(depth_min, depth);
+                                coverage += normal.a;
+                            }
+                        }
+
+                        if (coverage == 0.0) {
+                            discard;
+                        }
+
+                        // depth jump relative to distance, so lines don't depend on zoom
+                        float normal_edge = length(normal_gx) + length(normal_gy);
+                        float depth_edge = (abs(depth_gx) + abs(depth_gy)) / depth_min;
+                        float edge = max(smoothstep(0.8, 1.6, normal_edge), smoothstep(0.2, 0.5, depth_edge));
+
+                        out_color = vec4(u_color, edge);
+                    }
+                "#,
+            );
+
+            RenderScene::compile_program(gl, vertex_shader_source, fragment_shader_source)
+        }
+    }
+
+    fn create_oit_composite_program(gl: &glow::Context) -> Result<glow::Program, String> {
+        unsafe {
+            let (vertex_shader_source, fragment_shader_source) = (
+                r#"
+                    out vec2 vs_out_uv;
+
+                    void main() {
+                        // single triangle covering the viewport
+                        vec2 pos = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2));
+                        vs_out_uv = pos;
+                        gl_Position = vec4(pos * 2.0 - 1.0, 0.0, 1.0);
+                    }
+                "#,
+                r#"
+                    precision highp float;
+
+                    in vec2 vs_out_uv;
+
+                    out vec4 out_color;
+
+                    uniform sampler2D u_accum;
+                    uniform sampler2D u_accum_alpha;
+
+                    void main() {
+                        vec4 accum = texture(u_accum, vs_out_uv);
+                        float coverage = 1.0 - accum.a;
+                        if (coverage <= 0.0) {
+                            discard;
+                        }
+
+                        // weighted average color, premultiplied by how much the layers cover
+                        float weight_sum = max(texture(u_accum_alpha, vs_out_uv).r, 1e-5);
+                        out_color = vec4(accum.rgb / weight_sum * coverage, coverage);
+                    }
+                "#,
+            );
+
+            RenderScene::compile_program(gl, vertex_shader_source, fragment_shader_source)
+        }
+    }
+
+    unsafe fn compile_program(
+        gl: &glow::Context,
+        vertex_shader_source: &str,
+        fragment_shader_source: &str
+    ) -> Result<glow::Program, String> {
+        use glow::HasContext as _;
+
+        let shader_version = if cfg!(target_arch = "wasm32") {
+            "#version 300 es"
+        } else {
+            "#version 410"
+        };
+
+        let program = gl.create_program()?;
+
+        let shader_sources = [
+            (glow::VERTEX_SHADER, vertex_shader_source),
+            (glow::FRAGMENT_SHADER, fragment_shader_source),
+        ];
+
+        // on failure everything created so far is deleted before returning the log
+        let mut shaders = vec![];
+        let mut error = None;
+        for (shader_type, shader_source) in shader_sources.iter() {
+            let shader = match gl.create_shader(*shader_type) {
+                Ok(shader) => shader,
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                }
+            };
+            gl.shader_source(shader, &format!("{}\n{}", shader_version, shader_source));
+            gl.compile_shader(shader);
+            gl.attach_shader(program, shader);
+            shaders.push(shader);
+            if !gl.get_shader_compile_status(shader) {
+                let kind = if *shader_type == glow::VERTEX_SHADER { "vertex" } else { "fragment" };
+                error = Some(format!("{} shader compile failed: {}", kind, gl.get_shader_info_log(shader)));
+                break;
+            }
+        }
+
+        if error.is_none() {
             gl.link_program(program);
             if !gl.get_program_link_status(program) {
-                panic!("{}", gl.get_program_info_log(program));
+                error = Some(format!("program link failed: {}", gl.get_program_info_log(program)));
             }
+        }
 
-            for shader in shaders {
-                gl.detach_shader(program, shader);
-                gl.delete_shader(shader);
-            }
+        for shader in shaders {
+            gl.detach_shader(program, shader);
+            gl.delete_shader(shader);
+        }
 
-            Self {
-                program_default_indexed_mesh: program,
-                indexed_render_buffers: vec![],
-                indexed_render_buffers_temp: vec![],
+        match error {
+            Some(error) => {
+                gl.delete_program(program);
+                Err(error)
             }
+            None => Ok(program),
         }
     }
 
+    pub fn is_curvature_shading(shading_mode: ShadingMode) -> bool {
+        matches!(shading_mode, ShadingMode::MeanCurvature | ShadingMode::GaussianCurvature)
+    }
+
+    // uploads meshes again when the new mode needs data the buffers were built without
+    pub fn set_shading_mode(
+        &mut self,
+        gl: &glow::Context,
+        shading_mode: ShadingMode,
+        meshes: &[IndexedMesh],
+        meshes_lod: &[Option<IndexedMesh>],
+        meshes_temp: &[IndexedMesh],
+        meshes_original: &[IndexedMesh]
+    ) {
+        let is_same_buffers = RenderScene::is_curvature_shading(self.shading_mode) == RenderScene::is_curvature_shading(shading_mode)
+            && (self.shading_mode == ShadingMode::FaceColor) == (shading_mode == ShadingMode::FaceColor);
+        self.shading_mode = shading_mode;
+        if is_same_buffers { return; }
+
+        self.reset_static_and_create_static_meshes(gl, meshes, meshes_lod);
+        self.reset_temp_and_create_temp_meshes(gl, meshes_temp);
+        self.reset_original_and_create_original_meshes(gl, meshes_original);
+    }
+
+    // uploads meshes again with lines when wireframe is turned on for the first time
+    pub fn set_render_wireframe(
+        &mut self,
+        gl: &glow::Context,
+        is_render_wireframe: bool,
+        meshes: &[IndexedMesh],
+        meshes_lod: &[Option<IndexedMesh>],
+        meshes_temp: &[IndexedMesh],
+        meshes_original: &[IndexedMesh]
+    ) {
+        if self.has_lines || !is_render_wireframe { return; }
+        self.has_lines = true;
+
+        if let Some((_, _, buffer)) = self.reference_render_buffers.take() {
+            buffer.destroy(gl);
+        }
+        self.reset_static_and_create_static_meshes(gl, meshes, meshes_lod);
+        self.reset_temp_and_create_temp_meshes(gl, meshes_temp);
+        self.reset_original_and_create_original_meshes(gl, meshes_original);
+    }
+
+    pub fn invalidate(&mut self) {
+        self.is_context_lost = true;
+    }
+
+    // handles from lost context are dead, so they are dropped without deleting
+    pub fn recreate(
+        &mut self,
+        gl: &glow::Context,
+        meshes: &[IndexedMesh],
+        meshes_lod: &[Option<IndexedMesh>],
+        meshes_temp: &[IndexedMesh],
+        meshes_original: &[IndexedMesh]
+    ) {
+        // old programs died with the context, so they are forgotten before the rebuild
+        self.program_default_indexed_mesh = None;
+        self.program_line = None;
+        self.program_outline = None;
+        self.program_oit_composite = None;
+        self.retry_shaders(gl);
+        self.outline_target = None;
+        self.oit_target = None;
+        self.is_oit_unsupported = false;
+        self.indexed_render_buffers.clear();
+        self.indexed_render_buffers_lod.clear();
+        self.indexed_render_buffers_temp.clear();
+        self.indexed_render_buffers_original.clear();
+        self.reference_render_buffers = None;
+        self.is_context_lost = false;
+
+        for (mesh, mesh_lod) in meshes.iter().zip(meshes_lod.iter()) {
+            self.push_static_mesh(gl, mesh, mesh_lod.as_ref());
+        }
+        self.reset_temp_and_create_temp_meshes(gl, meshes_temp);
+        self.reset_original_and_create_original_meshes(gl, meshes_original);
+    }
+
     pub fn destroy(&self, gl: &glow::Context) {
         use glow::HasContext as _;
         unsafe {
-            gl.delete_program(self.program_default_indexed_mesh);
+            if let Some(program) = self.program_default_indexed_mesh {
+                gl.delete_program(program);
+            }
+            if let Some(program) = self.program_line {
+                gl.delete_program(program);
+            }
+            if let Some(program) = self.program_outline {
+                gl.delete_program(program);
+            }
+            if let Some(outline_target) = self.outline_target.as_ref() {
+                outline_target.destroy(gl);
+            }
+            if let Some(program) = self.program_oit_composite {
+                gl.delete_program(program);
+            }
+            if let Some(oit_target) = self.oit_target.as_ref() {
+                oit_target.destroy(gl);
+            }
             for buffer in self.indexed_render_buffers.iter() {
                 buffer.destroy(gl);
             }
+            for buffer in self.indexed_render_buffers_lod.iter().flatten() {
+                buffer.destroy(gl);
+            }
             for buffer in self.indexed_render_buffers_temp.iter() {
                 buffer.destroy(gl);
             }
+            for buffer in self.indexed_render_buffers_original.iter() {
+                buffer.destroy(gl);
+            }
+            if let Some((_, _, buffer)) = self.reference_render_buffers.as_ref() {
+                buffer.destroy(gl);
+            }
+        }
+    }
+
+    // rebuilt only when the cube moves or changes size
+    fn update_reference_buffers(&mut self, gl: &glow::Context, reference_cube: Option<(Vector3<f32>, f32)>) {
+        let is_current = match (self.reference_render_buffers.as_ref(), reference_cube) {
+            (Some((center, size, _)), Some(reference_cube)) => (*center, *size) == reference_cube,
+            (None, None) => true,
+            _ => false,
+        };
+        if is_current { return; }
+
+        if let Some((_, _, buffer)) = self.reference_render_buffers.take() {
+            buffer.destroy(gl);
+        }
+        if let Some((center, size)) = reference_cube {
+            // faceted for flat sides, shared corners would round the lighting
+            let cube = IndexedMesh::box3d_at(Vector3::new(size, size, size), center).to_faceted();
+            let buffer = IndexedMeshRenderBuffers::from_mesh(gl, &cube, RenderBuffersUsage::Static, self.shading_mode, self.has_lines).unwrap();
+            self.reference_render_buffers = Some((center, size, buffer));
         }
     }
 
@@ -236,29 +1241,55 @@ impl RenderScene {
             buffer.destroy(gl);
         }
         self.indexed_render_buffers.clear();
+        for buffer in self.indexed_render_buffers_lod.iter().flatten() {
+            buffer.destroy(gl);
+        }
+        self.indexed_render_buffers_lod.clear();
 
         self.reset_temp_buffers(gl);
     } 
 
-    pub fn push_static_mesh(&mut self, gl: &glow::Context, mesh: &IndexedMesh) {
+    pub fn push_static_mesh(&mut self, gl: &glow::Context, mesh: &IndexedMesh, mesh_lod: Option<&IndexedMesh>) {
+        if self.is_context_lost { return; }
+
         self.indexed_render_buffers
-            .push(IndexedMeshRenderBuffers::from_mesh(gl, &mesh, RenderBuffersUsage::Static).unwrap());
+            .push(IndexedMeshRenderBuffers::from_mesh(gl, &mesh, RenderBuffersUsage::Static, self.shading_mode, self.has_lines).unwrap());
+        self.indexed_render_buffers_lod.push(mesh_lod.map(|mesh_lod|
+            IndexedMeshRenderBuffers::from_mesh(gl, mesh_lod, RenderBuffersUsage::Static, self.shading_mode, self.has_lines).unwrap()
+        ));
     } 
 
-    pub fn reset_static_and_create_static_meshes(&mut self, gl: &glow::Context, meshes: &[IndexedMesh]) {
+    // for meshes swapped often, like animation frames, lod copy of the old one is dropped
+    pub fn replace_static_mesh(&mut self, gl: &glow::Context, idx: usize, mesh: &IndexedMesh) {
+        if self.is_context_lost { return; }
+
+        let buffer = IndexedMeshRenderBuffers::from_mesh(gl, mesh, RenderBuffersUsage::Dynamic, self.shading_mode, self.has_lines).unwrap();
+        std::mem::replace(&mut self.indexed_render_buffers[idx], buffer).destroy(gl);
+        if let Some(buffer_lod) = self.indexed_render_buffers_lod[idx].take() {
+            buffer_lod.destroy(gl);
+        }
+    }
+
+    pub fn reset_static_and_create_static_meshes(
+        &mut self,
+        gl: &glow::Context,
+        meshes: &[IndexedMesh],
+        meshes_lod: &[Option<IndexedMesh>]
+    ) {
         self.reset_buffers(gl);
 
-        for mesh in meshes.iter() {
-            self.push_static_mesh(gl, mesh);
+        for (mesh, mesh_lod) in meshes.iter().zip(meshes_lod.iter()) {
+            self.push_static_mesh(gl, mesh, mesh_lod.as_ref());
         }
     }
 
     pub fn reset_temp_and_create_temp_meshes(&mut self, gl: &glow::Context, meshes: &[IndexedMesh]) {
         self.reset_temp_buffers(gl);
+        if self.is_context_lost { return; }
 
         for mesh in meshes.iter() {
             self.indexed_render_buffers_temp
-                .push(IndexedMeshRenderBuffers::from_mesh(gl, &mesh, RenderBuffersUsage::Dynamic).unwrap());
+                .push(IndexedMeshRenderBuffers::from_mesh(gl, &mesh, RenderBuffersUsage::Dynamic, self.shading_mode, self.has_lines).unwrap());
         }
     }
 
@@ -271,91 +1302,624 @@ impl RenderScene {
         self.indexed_render_buffers_temp.clear();
     }
 
-    pub fn render(&self, gl: &glow::Context, settings: &Settings, camera: &OrbitalCamera) {
+    // static buffers are moved instead of uploading the same meshes again
+    pub fn keep_static_as_original(&mut self, gl: &glow::Context) {
+        self.reset_original_buffers(gl);
+        self.indexed_render_buffers_original = std::mem::take(&mut self.indexed_render_buffers);
+        for buffer_lod in self.indexed_render_buffers_lod.drain(..).flatten() {
+            buffer_lod.destroy(gl);
+        }
+    }
+
+    pub fn reset_original_and_create_original_meshes(&mut self, gl: &glow::Context, meshes: &[IndexedMesh]) {
+        self.reset_original_buffers(gl);
+        if self.is_context_lost { return; }
+
+        for mesh in meshes.iter() {
+            self.indexed_render_buffers_original
+                .push(IndexedMeshRenderBuffers::from_mesh(gl, mesh, RenderBuffersUsage::Static, self.shading_mode, self.has_lines).unwrap());
+        }
+    }
+
+    pub fn reset_original_buffers(&mut self, gl: &glow::Context) {
+        for buffer in self.indexed_render_buffers_original.iter() {
+            buffer.destroy(gl);
+        }
+        self.indexed_render_buffers_original.clear();
+    }
+
+    // mesh states hold visibility and alpha per mesh, temp meshes mirror static ones
+    pub fn render(
+        &mut self,
+        gl: &glow::Context,
+        settings: &Settings,
+        camera: &OrbitalCamera,
+        mesh_states: &[MeshState],
+        is_lod: bool,
+        target: &RenderTarget
+    ) {
         use glow::HasContext as _;
 
-        let proj = camera.calculate_perspective_matrix();
+        if self.is_context_lost { return; }
+        let program = match self.program_default_indexed_mesh {
+            Some(program) => program,
+            None => return,
+        };
+
+        let proj = camera.calculate_projection_matrix();
         let view = camera.calculate_view_matrix();
         let model = Matrix4::identity();
 
         unsafe {
-            gl.use_program(Some(self.program_default_indexed_mesh));
+            gl.use_program(Some(program));
 
             gl.uniform_matrix_4_f32_slice(
-                gl.get_uniform_location(self.program_default_indexed_mesh, "u_model").as_ref(),
+                gl.get_uniform_location(program, "u_model").as_ref(),
                 false,
                 std::slice::from_raw_parts(model.as_ptr(), 16)
             );
             gl.uniform_matrix_4_f32_slice(
-                gl.get_uniform_location(self.program_default_indexed_mesh, "u_view").as_ref(),
+                gl.get_uniform_location(program, "u_view").as_ref(),
                 false,
                 std::slice::from_raw_parts(view.as_ptr(), 16)
             );
             gl.uniform_matrix_4_f32_slice(
-                gl.get_uniform_location(self.program_default_indexed_mesh, "u_proj").as_ref(),
+                gl.get_uniform_location(program, "u_proj").as_ref(),
                 false,
                 std::slice::from_raw_parts(proj.as_ptr(), 16)
             );
+
+            // lighting is done in view space, headlight sits at the eye
+            let lights = settings.light_rig.view_lights(settings, &view);
+            let light_pos: Vec<f32> = lights.iter().flat_map(|(pos, _)| [pos.x, pos.y, pos.z]).collect();
+            let light_intensity: Vec<f32> = lights.iter().map(|&(_, intensity)| intensity).collect();
             gl.uniform_3_f32_slice(
-                gl.get_uniform_location(self.program_default_indexed_mesh, "u_light_pos").as_ref(),
-                &settings.light_pos
+                gl.get_uniform_location(program, "u_light_pos").as_ref(),
+                &light_pos
+            );
+            gl.uniform_1_f32_slice(
+                gl.get_uniform_location(program, "u_light_intensity").as_ref(),
+                &light_intensity
             );
 
-            let camera_pos = camera.calculate_pos();
-            gl.uniform_3_f32(
-                gl.get_uniform_location(self.program_default_indexed_mesh, "u_camera_pos").as_ref(),
-                camera_pos.x, camera_pos.y, camera_pos.z
+            gl.uniform_1_f32(
+                gl.get_uniform_location(program, "u_rim_strength").as_ref(),
+                settings.rim_strength
+            );
+            gl.uniform_1_f32(
+                gl.get_uniform_location(program, "u_rim_power").as_ref(),
+                settings.rim_power
+            );
+            gl.uniform_3_f32_slice(
+                gl.get_uniform_location(program, "u_rim_color").as_ref(),
+                &settings.rim_color
             );
 
             let is_flat_shading_i32 = if settings.is_flat_shading { 1 } else { 0 };
             gl.uniform_1_i32(
-                gl.get_uniform_location(self.program_default_indexed_mesh, "u_is_flat_shading").as_ref(),
+                gl.get_uniform_location(program, "u_is_flat_shading").as_ref(),
                 is_flat_shading_i32
             );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(program, "u_is_orthographic").as_ref(),
+                camera.is_orthographic as i32
+            );
+
+            gl.uniform_1_f32(
+                gl.get_uniform_location(program, "u_point_size").as_ref(),
+                settings.point_size
+            );
+
+            let is_clipping_i32 = if settings.is_clipping { 1 } else { 0 };
+            gl.uniform_1_i32(
+                gl.get_uniform_location(program, "u_is_clipping").as_ref(),
+                is_clipping_i32
+            );
+            gl.uniform_4_f32(
+                gl.get_uniform_location(program, "u_clip_plane").as_ref(),
+                settings.clip_normal[0], settings.clip_normal[1], settings.clip_normal[2], settings.clip_offset
+            );
+
+            let shading_mode_i32 = match settings.shading_mode {
+                ShadingMode::Color => 0,
+                ShadingMode::MeanCurvature => 1,
+                ShadingMode::GaussianCurvature => 2,
+                ShadingMode::FaceColor => 3,
+            };
+            gl.uniform_1_i32(
+                gl.get_uniform_location(program, "u_shading_mode").as_ref(),
+                shading_mode_i32
+            );
+
+            let is_backface_highlight_i32 = if settings.is_backface_highlight { 1 } else { 0 };
+            gl.uniform_1_i32(
+                gl.get_uniform_location(program, "u_is_backface_highlight").as_ref(),
+                is_backface_highlight_i32
+            );
+            gl.uniform_3_f32_slice(
+                gl.get_uniform_location(program, "u_backface_color").as_ref(),
+                &settings.backface_color
+            );
+
+            if let Some(program_line) = self.program_line {
+                RenderScene::set_line_uniforms(gl, program_line, settings, &model, &view, &proj, target);
+                gl.use_program(Some(program));
+            }
 
             gl.enable(glow::DEPTH_TEST);
             gl.clear(glow::DEPTH_BUFFER_BIT);
 
-            if settings.is_cull_face {
+            // webgl always takes point size from the shader
+            if !cfg!(target_arch = "wasm32") {
+                gl.enable(glow::PROGRAM_POINT_SIZE);
+            }
+
+            // culled back sides couldn't be highlighted
+            if settings.is_cull_face && !settings.is_backface_highlight {
                 gl.enable(glow::CULL_FACE);
                 gl.cull_face(glow::BACK);
             }
 
+            // push faces back a bit so lines and points drawn over them don't z-fight
+            gl.enable(glow::POLYGON_OFFSET_FILL);
+            gl.polygon_offset(1.0, 1.0);
+
+            if settings.is_oit {
+                self.ensure_oit_target(gl, target);
+            }
+
             if settings.is_render_static {
-                const MESH_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+                self.render_buffers(gl, &self.static_buffers(is_lod), mesh_states, settings, camera, settings.fill_color);
+            }
 
-                for buffer in self.indexed_render_buffers.iter() {
-                    gl.uniform_4_f32_slice(
-                        gl.get_uniform_location(self.program_default_indexed_mesh, "u_color").as_ref(),
-                        &MESH_COLOR
-                    );
+            if settings.original_state.is_visible && !self.indexed_render_buffers_original.is_empty() {
+                let buffers: Vec<_> = self.indexed_render_buffers_original.iter().collect();
+                let mesh_states = vec![settings.original_state; buffers.len()];
+                self.render_buffers(gl, &buffers, &mesh_states, settings, camera, settings.original_color);
+            }
 
-                    gl.bind_vertex_array(Some(buffer.vao));
-                    gl.draw_elements(glow::TRIANGLES, buffer.triangles_cnt as i32 * 3, glow::UNSIGNED_INT, 0);
-                }
+            self.update_reference_buffers(gl, settings.reference_cube);
+            if let Some((_, _, buffer)) = self.reference_render_buffers.as_ref() {
+                self.render_buffers(gl, &[buffer], &[MeshState::default()], settings, camera, settings.reference_color);
+            }
 
-                if !self.indexed_render_buffers.is_empty() {
-                    gl.bind_vertex_array(None);
+            if settings.is_render_temp {
+                // drop static depth so result is never hidden, but still occludes itself
+                if settings.is_render_temp_on_top {
+                    gl.clear(glow::DEPTH_BUFFER_BIT);
                 }
+
+                let fill_color = settings.fill_color.map(|c| c * 0.5);
+                let buffers: Vec<_> = self.indexed_render_buffers_temp.iter().collect();
+                self.render_buffers(gl, &buffers, mesh_states, settings, camera, fill_color);
             }
 
-            if settings.is_render_temp {
-                const MESH_COLOR: [f32; 4] = [0.4, 0.4, 0.4, 1.0];
+            if self.is_oit_ready(settings) {
+                self.render_translucent_oit(gl, settings, mesh_states, is_lod, target);
+            }
 
-                for buffer in self.indexed_render_buffers_temp.iter() {
-                    gl.uniform_4_f32_slice(
-                        gl.get_uniform_location(self.program_default_indexed_mesh, "u_color").as_ref(),
-                        &MESH_COLOR
-                    );
+            gl.disable(glow::POLYGON_OFFSET_FILL);
 
-                    gl.bind_vertex_array(Some(buffer.vao));
-                    gl.draw_elements(glow::TRIANGLES, buffer.triangles_cnt as i32 * 3, glow::UNSIGNED_INT, 0);
+            if settings.is_outline {
+                self.render_outline(gl, settings, camera, mesh_states, is_lod, target);
+            }
+        }
+    }
+
+    // frame wide state of the line program, only color changes between draws
+    unsafe fn set_line_uniforms(
+        gl: &glow::Context,
+        program: glow::Program,
+        settings: &Settings,
+        model: &Matrix4<f32>,
+        view: &Matrix4<f32>,
+        proj: &Matrix4<f32>,
+        target: &RenderTarget
+    ) {
+        use glow::HasContext as _;
+
+        gl.use_program(Some(program));
+        for (name, matrix) in [("u_model", model), ("u_view", view), ("u_proj", proj)] {
+            gl.uniform_matrix_4_f32_slice(
+                gl.get_uniform_location(program, name).as_ref(),
+                false,
+                std::slice::from_raw_parts(matrix.as_ptr(), 16)
+            );
+        }
+        gl.uniform_2_f32(
+            gl.get_uniform_location(program, "u_viewport_size").as_ref(),
+            target.viewport[2] as f32, target.viewport[3] as f32
+        );
+        gl.uniform_1_f32(
+            gl.get_uniform_location(program, "u_line_width").as_ref(),
+            settings.line_width
+        );
+
+        let is_clipping_i32 = if settings.is_clipping { 1 } else { 0 };
+        gl.uniform_1_i32(
+            gl.get_uniform_location(program, "u_is_clipping").as_ref(),
+            is_clipping_i32
+        );
+        gl.uniform_4_f32(
+            gl.get_uniform_location(program, "u_clip_plane").as_ref(),
+            settings.clip_normal[0], settings.clip_normal[1], settings.clip_normal[2], settings.clip_offset
+        );
+    }
+
+    fn static_buffers(&self, is_lod: bool) -> Vec<&IndexedMeshRenderBuffers> {
+        self.indexed_render_buffers
+            .iter()
+            .zip(self.indexed_render_buffers_lod.iter())
+            .map(|(buffer, buffer_lod)| match buffer_lod {
+                Some(buffer_lod) if is_lod => buffer_lod,
+                _ => buffer,
+            })
+            .collect()
+    }
+
+    fn is_oit_ready(&self, settings: &Settings) -> bool {
+        settings.is_oit && self.oit_target.is_some() && self.program_oit_composite.is_some()
+    }
+
+    // on failure it isn't tried again until the context is recreated
+    unsafe fn ensure_oit_target(&mut self, gl: &glow::Context, target: &RenderTarget) {
+        use glow::HasContext as _;
+
+        let [_, _, width, height] = target.viewport;
+        if width <= 0 || height <= 0 || self.is_oit_unsupported { return; }
+
+        if self.oit_target.as_ref().map_or(true, |t| t.width != width || t.height != height) {
+            if let Some(oit_target) = self.oit_target.take() {
+                oit_target.destroy(gl);
+            }
+            let oit_target = OitTarget::new(gl, width, height);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, target.fbo);
+            match oit_target {
+                Ok(oit_target) => self.oit_target = Some(oit_target),
+                Err(err) => {
+                    tracing::warn!("{}, translucent meshes are sorted instead", err);
+                    self.is_oit_unsupported = true;
                 }
+            }
+        }
+    }
+
+    // weighted blended transparency, order of translucent meshes doesn't matter, opaque depth is drawn again
+    // offscreen so layers behind opaque surfaces are dropped, then the average of layers goes over the scene
+    unsafe fn render_translucent_oit(
+        &self,
+        gl: &glow::Context,
+        settings: &Settings,
+        mesh_states: &[MeshState],
+        is_lod: bool,
+        target: &RenderTarget
+    ) {
+        use glow::HasContext as _;
+
+        let (program, program_composite, oit_target) =
+            match (self.program_default_indexed_mesh, self.program_oit_composite, self.oit_target.as_ref()) {
+                (Some(program), Some(program_composite), Some(oit_target)) => (program, program_composite, oit_target),
+                _ => return,
+            };
+        if !settings.is_render_fill { return; }
+
+        // temp meshes drawn on top aren't told apart here, their depth hides static layers behind them
+        let reference_state = MeshState::default();
+        let mut buffers = vec![];
+        if settings.is_render_static {
+            buffers.extend(self.static_buffers(is_lod).into_iter().zip(mesh_states.iter()).map(|(buffer, mesh_state)| {
+                (buffer, mesh_state, settings.fill_color)
+            }));
+        }
+        if let Some((_, _, buffer)) = self.reference_render_buffers.as_ref() {
+            buffers.push((buffer, &reference_state, settings.reference_color));
+        }
+        if settings.is_render_temp {
+            let fill_color = settings.fill_color.map(|c| c * 0.5);
+            buffers.extend(self.indexed_render_buffers_temp.iter().zip(mesh_states.iter()).map(|(buffer, mesh_state)| {
+                (buffer, mesh_state, fill_color)
+            }));
+        }
+        buffers.retain(|(_, mesh_state, _)| mesh_state.is_visible);
+        if buffers.iter().all(|(_, mesh_state, _)| mesh_state.alpha >= 1.0) { return; }
+
+        let [x, y, width, height] = target.viewport;
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(oit_target.fbo));
+        gl.viewport(0, 0, width, height);
+        gl.disable(glow::SCISSOR_TEST);
+        gl.clear_buffer_f32_slice(glow::COLOR, 0, &[0.0, 0.0, 0.0, 1.0]);
+        gl.clear_buffer_f32_slice(glow::COLOR, 1, &[0.0, 0.0, 0.0, 0.0]);
+        gl.clear(glow::DEPTH_BUFFER_BIT);
+
+        gl.use_program(Some(program));
+        let color_location = gl.get_uniform_location(program, "u_color");
+        let curvature_range_location = gl.get_uniform_location(program, "u_curvature_range");
+        let has_face_colors_location = gl.get_uniform_location(program, "u_has_face_colors");
+        let is_oit_pass_location = gl.get_uniform_location(program, "u_is_oit_pass");
+        gl.uniform_1_i32(gl.get_uniform_location(program, "u_is_unlit").as_ref(), 0);
+
+        gl.color_mask(false, false, false, false);
+        for (buffer, _, _) in buffers.iter().filter(|(_, mesh_state, _)| mesh_state.alpha >= 1.0) {
+            buffer.draw_triangles(gl);
+        }
+        gl.color_mask(true, true, true, true);
+
+        // color and weights add up, alpha channel multiplies transmittances
+        gl.enable(glow::BLEND);
+        gl.blend_func_separate(glow::ONE, glow::ONE, glow::ZERO, glow::ONE_MINUS_SRC_ALPHA);
+        gl.depth_mask(false);
+        gl.uniform_1_i32(is_oit_pass_location.as_ref(), 1);
+        for (buffer, mesh_state, color) in buffers.iter().filter(|(_, mesh_state, _)| mesh_state.alpha < 1.0) {
+            gl.uniform_4_f32(color_location.as_ref(), color[0], color[1], color[2], mesh_state.alpha);
+            gl.uniform_2_f32_slice(curvature_range_location.as_ref(), &buffer.curvature_range);
+            gl.uniform_1_i32(has_face_colors_location.as_ref(), buffer.has_face_colors as i32);
+            buffer.draw_triangles(gl);
+        }
+        gl.bind_vertex_array(None);
+        gl.uniform_1_i32(is_oit_pass_location.as_ref(), 0);
+        gl.depth_mask(true);
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, target.fbo);
+        gl.viewport(x, y, width, height);
+        gl.enable(glow::SCISSOR_TEST);
+        gl.disable(glow::DEPTH_TEST);
+        gl.blend_func(glow::ONE, glow::ONE_MINUS_SRC_ALPHA);
+
+        gl.use_program(Some(program_composite));
+        gl.active_texture(glow::TEXTURE0);
+        gl.bind_texture(glow::TEXTURE_2D, Some(oit_target.accum_texture));
+        gl.active_texture(glow::TEXTURE1);
+        gl.bind_texture(glow::TEXTURE_2D, Some(oit_target.accum_alpha_texture));
+        gl.uniform_1_i32(gl.get_uniform_location(program_composite, "u_accum").as_ref(), 0);
+        gl.uniform_1_i32(gl.get_uniform_location(program_composite, "u_accum_alpha").as_ref(), 1);
+
+        gl.draw_arrays(glow::TRIANGLES, 0, 3);
+
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        gl.active_texture(glow::TEXTURE0);
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        gl.disable(glow::BLEND);
+        gl.enable(glow::DEPTH_TEST);
+        gl.use_program(Some(program));
+    }
+
+    // normals and depth go to offscreen target, then edges found there are blended over the scene
+    unsafe fn render_outline(
+        &mut self,
+        gl: &glow::Context,
+        settings: &Settings,
+        camera: &OrbitalCamera,
+        mesh_states: &[MeshState],
+        is_lod: bool,
+        target: &RenderTarget
+    ) {
+        use glow::HasContext as _;
 
-                if !self.indexed_render_buffers_temp.is_empty() {
-                    gl.bind_vertex_array(None);
+        let [x, y, width, height] = target.viewport;
+        if width <= 0 || height <= 0 { return; }
+        let (program, program_outline) = match (self.program_default_indexed_mesh, self.program_outline) {
+            (Some(program), Some(program_outline)) => (program, program_outline),
+            _ => return,
+        };
+
+        if self.outline_target.as_ref().map_or(true, |t| t.width != width || t.height != height) {
+            if let Some(outline_target) = self.outline_target.take() {
+                outline_target.destroy(gl);
+            }
+            match OutlineTarget::new(gl, width, height) {
+                Ok(outline_target) => self.outline_target = Some(outline_target),
+                Err(err) => {
+                    tracing::warn!("{}", err);
+                    gl.bind_framebuffer(glow::FRAMEBUFFER, target.fbo);
+                    return;
                 }
             }
         }
+        let outline_target = self.outline_target.as_ref().unwrap();
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(outline_target.fbo));
+        gl.viewport(0, 0, width, height);
+        gl.disable(glow::SCISSOR_TEST);
+        gl.clear_color(0.0, 0.0, 0.0, 0.0);
+        gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+
+        let is_normal_pass_location = gl.get_uniform_location(program, "u_is_normal_pass");
+        gl.use_program(Some(program));
+        gl.uniform_1_i32(gl.get_uniform_location(program, "u_is_unlit").as_ref(), 0);
+        gl.uniform_1_i32(is_normal_pass_location.as_ref(), 1);
+
+        let mut buffers = vec![];
+        if settings.is_render_static {
+            buffers.extend(self.static_buffers(is_lod).into_iter().zip(mesh_states.iter()));
+        }
+        if settings.is_render_temp {
+            buffers.extend(self.indexed_render_buffers_temp.iter().zip(mesh_states.iter()));
+        }
+        for (buffer, mesh_state) in buffers {
+            if !mesh_state.is_visible { continue; }
+
+            buffer.draw_triangles(gl);
+        }
+        gl.bind_vertex_array(None);
+        gl.uniform_1_i32(is_normal_pass_location.as_ref(), 0);
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, target.fbo);
+        gl.viewport(x, y, width, height);
+        gl.enable(glow::SCISSOR_TEST);
+        gl.disable(glow::DEPTH_TEST);
+        let is_cull_face = gl.is_enabled(glow::CULL_FACE);
+        gl.disable(glow::CULL_FACE);
+        gl.enable(glow::BLEND);
+        gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+
+        gl.use_program(Some(program_outline));
+        gl.active_texture(glow::TEXTURE0);
+        gl.bind_texture(glow::TEXTURE_2D, Some(outline_target.normals_texture));
+        gl.active_texture(glow::TEXTURE1);
+        gl.bind_texture(glow::TEXTURE_2D, Some(outline_target.depth_texture));
+
+        gl.uniform_1_i32(gl.get_uniform_location(program_outline, "u_normals").as_ref(), 0);
+        gl.uniform_1_i32(gl.get_uniform_location(program_outline, "u_depth").as_ref(), 1);
+        gl.uniform_2_f32(
+            gl.get_uniform_location(program_outline, "u_texel_size").as_ref(),
+            1.0 / width as f32, 1.0 / height as f32
+        );
+        gl.uniform_1_f32(gl.get_uniform_location(program_outline, "u_near").as_ref(), camera.near);
+        gl.uniform_1_f32(gl.get_uniform_location(program_outline, "u_far").as_ref(), camera.far);
+        gl.uniform_1_i32(gl.get_uniform_location(program_outline, "u_is_orthographic").as_ref(), camera.is_orthographic as i32);
+        gl.uniform_3_f32_slice(gl.get_uniform_location(program_outline, "u_color").as_ref(), &settings.outline_color);
+
+        gl.draw_arrays(glow::TRIANGLES, 0, 3);
+
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        gl.active_texture(glow::TEXTURE0);
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        gl.disable(glow::BLEND);
+        gl.enable(glow::DEPTH_TEST);
+        if is_cull_face {
+            gl.enable(glow::CULL_FACE);
+        }
+        gl.use_program(Some(program));
+    }
+
+    unsafe fn render_buffers(
+        &self,
+        gl: &glow::Context,
+        buffers: &[&IndexedMeshRenderBuffers],
+        mesh_states: &[MeshState],
+        settings: &Settings,
+        camera: &OrbitalCamera,
+        fill_color: [f32; 3],
+    ) {
+        use glow::HasContext as _;
+
+        let buffers: Vec<_> = buffers
+            .iter()
+            .zip(mesh_states.iter())
+            .filter(|(_, mesh_state)| mesh_state.is_visible)
+            .map(|(&buffer, mesh_state)| (buffer, mesh_state.alpha))
+            .collect();
+
+        if buffers.is_empty() { return; }
+        let program = match self.program_default_indexed_mesh {
+            Some(program) => program,
+            None => return,
+        };
+
+        let color_location = gl.get_uniform_location(program, "u_color");
+        let is_unlit_location = gl.get_uniform_location(program, "u_is_unlit");
+        let curvature_range_location = gl.get_uniform_location(program, "u_curvature_range");
+        let has_face_colors_location = gl.get_uniform_location(program, "u_has_face_colors");
+
+        if settings.is_render_fill {
+            gl.uniform_1_i32(is_unlit_location.as_ref(), 0);
+            gl.uniform_4_f32(color_location.as_ref(), fill_color[0], fill_color[1], fill_color[2], 1.0);
+
+            for (buffer, _) in buffers.iter().filter(|(_, alpha)| *alpha >= 1.0) {
+                gl.uniform_2_f32_slice(curvature_range_location.as_ref(), &buffer.curvature_range);
+                gl.uniform_1_i32(has_face_colors_location.as_ref(), buffer.has_face_colors as i32);
+
+                buffer.draw_triangles(gl);
+            }
+        }
+
+        if let (true, Some(program_line)) = (settings.is_render_wireframe, self.program_line) {
+            let color = settings.wireframe_color;
+            gl.use_program(Some(program_line));
+            gl.uniform_4_f32(
+                gl.get_uniform_location(program_line, "u_color").as_ref(),
+                color[0], color[1], color[2], 1.0
+            );
+
+            // quads are faces too, they mustn't be pushed back with the fill
+            let is_polygon_offset = gl.is_enabled(glow::POLYGON_OFFSET_FILL);
+            gl.disable(glow::POLYGON_OFFSET_FILL);
+            gl.disable(glow::CULL_FACE);
+            gl.enable(glow::BLEND);
+            gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+
+            for (buffer, _) in buffers.iter() {
+                buffer.draw_lines(gl);
+            }
+
+            gl.disable(glow::BLEND);
+            if is_polygon_offset {
+                gl.enable(glow::POLYGON_OFFSET_FILL);
+            }
+            if settings.is_cull_face && !settings.is_backface_highlight {
+                gl.enable(glow::CULL_FACE);
+            }
+            gl.use_program(Some(program));
+        }
+
+        if settings.is_render_points {
+            let color = settings.points_color;
+            gl.uniform_1_i32(is_unlit_location.as_ref(), 1);
+            gl.uniform_4_f32(color_location.as_ref(), color[0], color[1], color[2], 1.0);
+
+            for (buffer, _) in buffers.iter() {
+                gl.bind_vertex_array(Some(buffer.vao));
+                gl.draw_arrays(glow::POINTS, 0, buffer.vertices_cnt as i32);
+            }
+        }
+
+        // translucent fill goes last, farthest first, and without depth writes so meshes behind stay visible
+        let mut translucent: Vec<_> = buffers.iter().filter(|(_, alpha)| *alpha < 1.0).collect();
+        // with transparency pass the fill is left for it
+        if settings.is_render_fill && !self.is_oit_ready(settings) && !translucent.is_empty() {
+            let eye = camera.calculate_pos();
+            translucent.sort_by(|(a, _), (b, _)| {
+                b.center.distance2(eye).partial_cmp(&a.center.distance2(eye)).unwrap_or(std::cmp::Ordering::Equal)
+            });
+
+            gl.enable(glow::BLEND);
+            gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+            gl.depth_mask(false);
+            gl.uniform_1_i32(is_unlit_location.as_ref(), 0);
+
+            for (buffer, alpha) in translucent {
+                gl.uniform_4_f32(color_location.as_ref(), fill_color[0], fill_color[1], fill_color[2], *alpha);
+                gl.uniform_2_f32_slice(curvature_range_location.as_ref(), &buffer.curvature_range);
+                gl.uniform_1_i32(has_face_colors_location.as_ref(), buffer.has_face_colors as i32);
+
+                buffer.draw_triangles(gl);
+            }
+
+            gl.depth_mask(true);
+            gl.disable(glow::BLEND);
+        }
+
+        gl.bind_vertex_array(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_ranges_split_on_whole_primitives() {
+        let per_draw = MAX_INDICES_PER_DRAW - MAX_INDICES_PER_DRAW % 3;
+        for (indices_cnt, draws_cnt) in [
+            (0, 0),
+            (3, 1),
+            (per_draw - 3, 1),
+            (per_draw, 1),
+            (per_draw + 3, 2),
+            (per_draw * 2, 2),
+            (per_draw * 2 + 3, 3),
+        ] {
+            let ranges: Vec<_> = IndexedMeshRenderBuffers::draw_ranges(indices_cnt, 3).collect();
+            assert_eq!(ranges.len(), draws_cnt, "{} indices", indices_cnt);
+
+            // parts follow each other without gaps and cover whole buffer
+            let mut next_offset = 0;
+            for &(offset, count) in ranges.iter() {
+                assert_eq!(offset as usize, next_offset);
+                assert!(count > 0 && count as usize <= MAX_INDICES_PER_DRAW && count % 3 == 0);
+                next_offset += count as usize * core::mem::size_of::<u32>();
+            }
+            assert_eq!(next_offset, indices_cnt * core::mem::size_of::<u32>());
+        }
     }
 }
@@ -0,0 +1,313 @@
+
+use cgmath::*;
+
+use crate::halfedge::HalfEdgeMesh;
+use crate::mesh::IndexedMesh;
+use crate::simplification;
+
+#[derive(Clone, Copy, Default)]
+pub struct ManifoldStats {
+    pub faces_cnt: usize,
+    // edges of a single face, every hole is rimmed by them
+    pub border_edges_cnt: usize,
+    // edges shared by more than two faces
+    pub non_manifold_edges_cnt: usize,
+    // edges whose both faces go along them in the same direction
+    pub flipped_edges_cnt: usize,
+}
+
+impl ManifoldStats {
+    pub fn is_watertight(&self) -> bool {
+        self.faces_cnt > 0 && self.border_edges_cnt == 0 && self.non_manifold_edges_cnt == 0
+    }
+    pub fn is_manifold(&self) -> bool {
+        self.non_manifold_edges_cnt == 0 && self.flipped_edges_cnt == 0
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct PrintableReport {
+    pub before: ManifoldStats,
+    pub after: ManifoldStats,
+    pub welded_cnt: usize,
+    pub degenerate_cnt: usize,
+    pub unused_cnt: usize,
+    pub flipped_faces_cnt: usize,
+    pub filled_holes_cnt: usize,
+}
+
+// problems found by inspect, each is paired with a fix
+#[derive(Clone, Copy, Default)]
+pub struct InspectReport {
+    pub stats: ManifoldStats,
+    pub degenerate_faces_cnt: usize,
+    pub duplicate_faces_cnt: usize,
+    pub boundary_loops_cnt: usize,
+    // vertices closer than weld tolerance, usually unwelded seams of stl files
+    pub duplicate_vertices_cnt: usize,
+    pub isolated_vertices_cnt: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fix {
+    RemoveDegenerate,
+    RemoveDuplicateFaces,
+    Weld,
+    UnifyWinding,
+    FillHoles,
+    RemoveUnusedVertices,
+}
+
+// cleanup for 3d printing, slicers want closed consistently wound surface
+pub struct Repair {}
+impl Repair {
+    pub fn manifold_stats(mesh: &IndexedMesh) -> ManifoldStats {
+        let half_edge_mesh = HalfEdgeMesh::from(mesh);
+
+        let mut stats = ManifoldStats {
+            faces_cnt: mesh.indices.len() / 3,
+            ..Default::default()
+        };
+        for half_edges in half_edge_mesh.edge_half_edges() {
+            match half_edges[..] {
+                [_] => stats.border_edges_cnt += 1,
+                // faces agree when they go along the shared edge in opposite directions
+                [h, g] => if half_edge_mesh.twin(h) != Some(g) { stats.flipped_edges_cnt += 1 },
+                _ => stats.non_manifold_edges_cnt += 1,
+            }
+        }
+
+        stats
+    }
+
+    // counts only, mesh is left as it is
+    pub fn inspect(mesh: &IndexedMesh) -> InspectReport {
+        use std::collections::HashSet;
+
+        let mut report = InspectReport {
+            stats: Repair::manifold_stats(mesh),
+            ..Default::default()
+        };
+
+        let mut seen = HashSet::with_capacity(mesh.indices.len() / 3);
+        for face_idxs in mesh.faces() {
+            if mesh.is_degenerate_face(face_idxs) {
+                report.degenerate_faces_cnt += 1;
+            }
+            let mut key = face_idxs;
+            key.sort_unstable();
+            if !seen.insert(key) {
+                report.duplicate_faces_cnt += 1;
+            }
+        }
+
+        if report.stats.border_edges_cnt > 0 {
+            report.boundary_loops_cnt = HalfEdgeMesh::from(mesh).boundary_loops().len();
+        }
+
+        let mut welded = IndexedMesh {
+            positions: mesh.positions.clone(),
+            ..Default::default()
+        };
+        report.duplicate_vertices_cnt = welded.weld_vertices(Repair::weld_eps(mesh));
+
+        let mut is_used = vec![false; mesh.positions.len()];
+        for &idx in mesh.indices.iter() {
+            is_used[idx as usize] = true;
+        }
+        report.isolated_vertices_cnt = is_used.iter().filter(|&&is_used| !is_used).count();
+
+        report
+    }
+
+    // returns number of changed elements, faces or vertices depending on fix
+    pub fn apply_fix(mesh: &mut IndexedMesh, fix: Fix) -> usize {
+        match fix {
+            Fix::RemoveDegenerate => mesh.remove_degenerate_faces(),
+            Fix::RemoveDuplicateFaces => mesh.remove_duplicate_faces(),
+            Fix::Weld => mesh.weld_vertices(Repair::weld_eps(mesh)),
+            Fix::UnifyWinding => Repair::unify_winding(mesh),
+            Fix::FillHoles => Repair::fill_holes(mesh),
+            Fix::RemoveUnusedVertices => mesh.remove_unused_vertices(),
+        }
+    }
+
+    fn weld_eps(mesh: &IndexedMesh) -> f32 {
+        let (min, max) = mesh.calculate_aabb();
+        (max - min).magnitude() * simplification::WELD_EPS_RELATIVE
+    }
+
+    // weld, drop degenerate faces, unify winding and cap holes, then check the result
+    pub fn make_printable(mesh: &mut IndexedMesh) -> PrintableReport {
+        let before = Repair::manifold_stats(mesh);
+
+        mesh.validate();
+        let welded_cnt = mesh.weld_vertices(Repair::weld_eps(mesh));
+        let degenerate_cnt = mesh.remove_degenerate_faces();
+        // vertices of dropped faces and merged duplicates would still count into aabb and center
+        let unused_cnt = mesh.remove_unused_vertices();
+
+        // caps follow winding of faces around the hole, so it has to be consistent first,
+        // capped parts are closed only afterwards and may need turning outward again
+        let mut flipped_faces_cnt = Repair::unify_winding(mesh);
+        let filled_holes_cnt = Repair::fill_holes(mesh);
+        if filled_holes_cnt > 0 {
+            flipped_faces_cnt += Repair::unify_winding(mesh);
+        }
+
+        PrintableReport {
+            before,
+            after: Repair::manifold_stats(mesh),
+            welded_cnt,
+            degenerate_cnt,
+            unused_cnt,
+            flipped_faces_cnt,
+            filled_holes_cnt,
+        }
+    }
+
+    // faces are flipped to agree with neighbours across manifold edges, then every connected part
+    // is turned outward by sign of its volume, returns number of flipped faces
+    pub fn unify_winding(mesh: &mut IndexedMesh) -> usize {
+        let faces: Vec<[u32; 3]> = mesh.faces().collect();
+
+        // faces across manifold edges and whether they go along the shared edge the same way
+        let half_edge_mesh = HalfEdgeMesh::from_faces(mesh.positions.len(), &faces);
+        let mut neighbors: Vec<Vec<(usize, bool)>> = vec![vec![]; faces.len()];
+        for half_edges in half_edge_mesh.edge_half_edges() {
+            if let [h, g] = half_edges[..] {
+                let is_same_way = half_edge_mesh.origin(h) == half_edge_mesh.origin(g);
+                let (f, g) = (half_edge_mesh.face(h) as usize, half_edge_mesh.face(g) as usize);
+                neighbors[f].push((g, is_same_way));
+                neighbors[g].push((f, is_same_way));
+            }
+        }
+
+        let mut is_flipped = vec![false; faces.len()];
+        let mut part = vec![usize::MAX; faces.len()];
+        let mut parts_cnt = 0;
+        for seed in 0..faces.len() {
+            if part[seed] != usize::MAX { continue; }
+
+            part[seed] = parts_cnt;
+            let mut stack = vec![seed];
+            while let Some(f) = stack.pop() {
+                for &(g, is_same_way) in neighbors[f].iter() {
+                    // conflicts of non orientable parts are left as they are
+                    if part[g] != usize::MAX { continue; }
+
+                    // neighbour agrees when it goes along the shared edge the other way
+                    part[g] = parts_cnt;
+                    is_flipped[g] = is_flipped[f] ^ is_same_way;
+                    stack.push(g);
+                }
+            }
+            parts_cnt += 1;
+        }
+
+        // relative to center, so far away meshes don't lose the volume to rounding
+        let center = mesh.calculate_center_point();
+        let mut volumes = vec![0.0f32; parts_cnt];
+        for (f, face_idxs) in faces.iter().enumerate() {
+            let v0 = mesh.positions[face_idxs[0] as usize] - center;
+            let v1 = mesh.positions[face_idxs[1] as usize] - center;
+            let v2 = mesh.positions[face_idxs[2] as usize] - center;
+            let volume = v0.dot(v1.cross(v2));
+            volumes[part[f]] += if is_flipped[f] { -volume } else { volume };
+        }
+
+        let mut flipped_cnt = 0;
+        for (f, face_idxs) in mesh.faces_mut().enumerate() {
+            if is_flipped[f] != (volumes[part[f]] < 0.0) {
+                face_idxs.swap(1, 2);
+                flipped_cnt += 1;
+            }
+        }
+        if flipped_cnt > 0 {
+            mesh.normals_dirty = true;
+        }
+
+        flipped_cnt
+    }
+
+    // manual fix for patches unify_winding got wrong, flips the seed face and faces connected to it
+    // with centroid within radius of center, returns number of flipped faces
+    pub fn flip_faces(mesh: &mut IndexedMesh, seed: u32, center: Vector3<f32>, radius: f32) -> usize {
+        let faces: Vec<[u32; 3]> = mesh.faces().collect();
+        if seed as usize >= faces.len() { return 0; }
+
+        let mut is_flipped = vec![false; faces.len()];
+        is_flipped[seed as usize] = true;
+        if radius > 0.0 {
+            let half_edge_mesh = HalfEdgeMesh::from_faces(mesh.positions.len(), &faces);
+            let edge_half_edges = half_edge_mesh.edge_half_edges();
+            let centroid = |f: usize| {
+                faces[f].iter().map(|&idx| mesh.positions[idx as usize]).sum::<Vector3<f32>>() / 3.0
+            };
+
+            // grown over edges, so the other side of a thin wall stays as it is
+            let mut stack = vec![seed as usize];
+            while let Some(f) = stack.pop() {
+                for h in half_edge_mesh.face_edges(f as u32) {
+                    for &g in edge_half_edges[half_edge_mesh.edge(h) as usize].iter() {
+                        let g = half_edge_mesh.face(g) as usize;
+                        if is_flipped[g] || centroid(g).distance2(center) > radius * radius { continue; }
+
+                        is_flipped[g] = true;
+                        stack.push(g);
+                    }
+                }
+            }
+        }
+
+        let mut flipped_cnt = 0;
+        for (f, face_idxs) in mesh.faces_mut().enumerate() {
+            if is_flipped[f] {
+                face_idxs.swap(1, 2);
+                flipped_cnt += 1;
+            }
+        }
+        mesh.normals_dirty = true;
+
+        flipped_cnt
+    }
+
+    // every closed border loop gets capped by ear clipping, returns number of filled holes
+    pub fn fill_holes(mesh: &mut IndexedMesh) -> usize {
+        let half_edge_mesh = HalfEdgeMesh::from(mesh);
+        let is_face_colors = mesh.has_face_colors();
+
+        let mut filled_cnt = 0;
+        for boundary_loop in half_edge_mesh.boundary_loops() {
+            if boundary_loop.len() < 3 { continue; }
+
+            // loop stopped at non manifold vertex doesn't come back to the start
+            let (first, last) = (boundary_loop[0], boundary_loop[boundary_loop.len() - 1]);
+            let closing = half_edge_mesh.outgoing(last)
+                .iter()
+                .copied()
+                .find(|&h| half_edge_mesh.is_boundary(h) && half_edge_mesh.target(h) == first);
+            let closing = match closing {
+                Some(closing) => closing,
+                None => continue,
+            };
+
+            // faces around go along the border, so the cap goes against it
+            let cap: Vec<u32> = boundary_loop.iter().rev().copied().collect();
+            let points: Vec<Vector3<f32>> = cap.iter().map(|&idx| mesh.positions[idx as usize]).collect();
+            let triangles = IndexedMesh::ear_clip(&points);
+            mesh.indices.extend(triangles.iter().map(|&k| cap[k]));
+            if is_face_colors {
+                let color = mesh.face_colors[half_edge_mesh.face(closing) as usize];
+                let colors_cnt = mesh.face_colors.len() + triangles.len() / 3;
+                mesh.face_colors.resize(colors_cnt, color);
+            }
+            filled_cnt += 1;
+        }
+        if filled_cnt > 0 {
+            mesh.normals_dirty = true;
+        }
+
+        filled_cnt
+    }
+}
@@ -0,0 +1,326 @@
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+use cgmath::*;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+
+use crate::app::MeshState;
+use crate::mesh::IndexedMesh;
+
+const DB_NAME: &str = "web_editor";
+const STORE_NAME: &str = "sessions";
+const SESSION_KEY: &str = "last";
+
+// blobs of other version are ignored, so layout changes only need a bump
+const SESSION_MAGIC: &[u8; 4] = b"WESS";
+const SESSION_VERSION: u32 = 1;
+const SESSION_ENTRY: &str = "session.bin";
+
+pub struct SessionScene {
+    pub name: String,
+    pub meshes: Vec<IndexedMesh>,
+    pub mesh_states: Vec<MeshState>,
+}
+
+// scene snapshot in IndexedDB, local storage of eframe is far too small for meshes
+pub struct Session {}
+impl Session {
+    // little endian counts and values deflated in a zip, fastest level since it runs between frames
+    pub fn to_bytes(name: &str, meshes: &[IndexedMesh], mesh_states: &[MeshState]) -> std::io::Result<Vec<u8>> {
+        fn write_u32(bytes: &mut Vec<u8>, value: u32) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        fn write_str(bytes: &mut Vec<u8>, value: &str) {
+            write_u32(bytes, value.len() as u32);
+            bytes.extend_from_slice(value.as_bytes());
+        }
+        fn write_vectors(bytes: &mut Vec<u8>, vectors: &[Vector3<f32>]) {
+            write_u32(bytes, vectors.len() as u32);
+            for v in vectors {
+                for c in [v.x, v.y, v.z] {
+                    bytes.extend_from_slice(&c.to_le_bytes());
+                }
+            }
+        }
+
+        let mut bytes = SESSION_MAGIC.to_vec();
+        write_u32(&mut bytes, SESSION_VERSION);
+        write_str(&mut bytes, name);
+        write_u32(&mut bytes, meshes.len() as u32);
+        for (mesh, mesh_state) in meshes.iter().zip(mesh_states.iter()) {
+            write_str(&mut bytes, &mesh.name);
+            bytes.push(mesh_state.is_visible as u8);
+            bytes.extend_from_slice(&mesh_state.alpha.to_le_bytes());
+
+            write_vectors(&mut bytes, &mesh.positions);
+            // stale normals are recomputed on restore anyway
+            let is_normals = !mesh.normals_dirty && mesh.normals.len() == mesh.positions.len();
+            write_vectors(&mut bytes, if is_normals { &mesh.normals } else { &[] });
+            write_u32(&mut bytes, mesh.indices.len() as u32);
+            for &idx in mesh.indices.iter() {
+                write_u32(&mut bytes, idx);
+            }
+            write_vectors(&mut bytes, if mesh.has_face_colors() { &mesh.face_colors } else { &[] });
+        }
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let options = zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .compression_level(Some(1));
+        zip.start_file(SESSION_ENTRY, options)?;
+        zip.write_all(&bytes)?;
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> std::io::Result<SessionScene> {
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))?;
+        let mut entry = archive.by_name(SESSION_ENTRY)?;
+        let mut bytes = vec![];
+        entry.read_to_end(&mut bytes)?;
+        let reader = &mut &bytes[..];
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != SESSION_MAGIC || Session::read_u32(reader)? != SESSION_VERSION {
+            return Err(Session::invalid_data("Not a session of this version"));
+        }
+
+        let mut scene = SessionScene {
+            name: Session::read_str(reader)?,
+            meshes: vec![],
+            mesh_states: vec![],
+        };
+        let meshes_cnt = Session::read_u32(reader)?;
+        for _ in 0..meshes_cnt {
+            let mut mesh = IndexedMesh {
+                name: Session::read_str(reader)?,
+                ..Default::default()
+            };
+            let mut is_visible = [0u8; 1];
+            reader.read_exact(&mut is_visible)?;
+            let mut alpha = [0u8; 4];
+            reader.read_exact(&mut alpha)?;
+            let mesh_state = MeshState {
+                is_visible: is_visible[0] != 0,
+                alpha: f32::from_le_bytes(alpha),
+                ..Default::default()
+            };
+
+            mesh.positions = Session::read_vectors(reader)?;
+            mesh.normals = Session::read_vectors(reader)?;
+            let indices_cnt = Session::read_cnt(reader, 4)?;
+            mesh.indices = (0..indices_cnt).map(|_| Session::read_u32(reader)).collect::<Result<_, _>>()?;
+            mesh.face_colors = Session::read_vectors(reader)?;
+            mesh.normals_dirty = mesh.normals.len() != mesh.positions.len();
+
+            if mesh.validate() > 0 {
+                return Err(Session::invalid_data("Faces with out of range indices"));
+            }
+            scene.meshes.push(mesh);
+            scene.mesh_states.push(mesh_state);
+        }
+
+        Ok(scene)
+    }
+
+    fn invalid_data(message: &str) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+    }
+
+    fn read_u32(reader: &mut &[u8]) -> std::io::Result<u32> {
+        let mut value = [0u8; 4];
+        reader.read_exact(&mut value)?;
+        Ok(u32::from_le_bytes(value))
+    }
+
+    // count of items with given size, checked against the rest so broken blob can't allocate a lot
+    fn read_cnt(reader: &mut &[u8], item_size: usize) -> std::io::Result<usize> {
+        let cnt = Session::read_u32(reader)? as usize;
+        match cnt.checked_mul(item_size) {
+            Some(len) if len <= reader.len() => Ok(cnt),
+            _ => Err(Session::invalid_data("Count past the end of session")),
+        }
+    }
+
+    fn read_str(reader: &mut &[u8]) -> std::io::Result<String> {
+        let len = Session::read_cnt(reader, 1)?;
+        let mut bytes = vec![0u8; len];
+        reader.read_exact(&mut bytes)?;
+        String::from_utf8(bytes).map_err(|_| Session::invalid_data("Name is not utf-8"))
+    }
+
+    fn read_vectors(reader: &mut &[u8]) -> std::io::Result<Vec<Vector3<f32>>> {
+        let cnt = Session::read_cnt(reader, 12)?;
+        let (values, rest) = reader.split_at(cnt * 12);
+        *reader = rest;
+
+        Ok(values
+            .chunks_exact(12)
+            .map(|v| Vector3::new(
+                f32::from_le_bytes([v[0], v[1], v[2], v[3]]),
+                f32::from_le_bytes([v[4], v[5], v[6], v[7]]),
+                f32::from_le_bytes([v[8], v[9], v[10], v[11]]),
+            ))
+            .collect())
+    }
+
+    // replaces the stored session, failures are only logged since autosave retries later
+    pub fn save(bytes: Vec<u8>) {
+        Session::open(move |db| {
+            let stored = db
+                .ok_or_else(|| JsValue::from_str("database not opened"))
+                .and_then(|db| db.transaction_with_str_and_mode(STORE_NAME, web_sys::IdbTransactionMode::Readwrite))
+                .and_then(|transaction| transaction.object_store(STORE_NAME))
+                .and_then(|store| store.put_with_key(&js_sys::Uint8Array::from(&bytes[..]), &JsValue::from_str(SESSION_KEY)));
+            if let Err(err) = stored {
+                tracing::warn!("Autosave failed: {:?}", err);
+            }
+        });
+    }
+
+    // sends None when nothing is stored or IndexedDB isn't available
+    pub fn load(sender: oneshot::Sender<Option<Vec<u8>>>) {
+        Session::open(move |db| {
+            let request = db
+                .and_then(|db| db.transaction_with_str(STORE_NAME).ok())
+                .and_then(|transaction| transaction.object_store(STORE_NAME).ok())
+                .and_then(|store| store.get(&JsValue::from_str(SESSION_KEY)).ok());
+            let request = match request {
+                Some(request) => request,
+                None => {
+                    sender.send(None).ok();
+                    return;
+                }
+            };
+
+            // either success or error comes, whichever does takes the sender
+            let sender = Rc::new(RefCell::new(Some(sender)));
+            let (success_request, success_sender) = (request.clone(), sender.clone());
+            let on_success = Closure::once_into_js(move || {
+                let bytes = success_request.result()
+                    .ok()
+                    .and_then(|value| value.dyn_into::<js_sys::Uint8Array>().ok())
+                    .map(|array| array.to_vec());
+                if let Some(sender) = success_sender.borrow_mut().take() {
+                    sender.send(bytes).ok();
+                }
+            });
+            request.set_onsuccess(Some(on_success.unchecked_ref()));
+            let on_error = Closure::once_into_js(move || {
+                if let Some(sender) = sender.borrow_mut().take() {
+                    sender.send(None).ok();
+                }
+            });
+            request.set_onerror(Some(on_error.unchecked_ref()));
+        });
+    }
+
+    pub fn clear() {
+        Session::open(|db| {
+            let deleted = db
+                .ok_or_else(|| JsValue::from_str("database not opened"))
+                .and_then(|db| db.transaction_with_str_and_mode(STORE_NAME, web_sys::IdbTransactionMode::Readwrite))
+                .and_then(|transaction| transaction.object_store(STORE_NAME))
+                .and_then(|store| store.delete(&JsValue::from_str(SESSION_KEY)));
+            if let Err(err) = deleted {
+                tracing::warn!("Stored session not cleared: {:?}", err);
+            }
+        });
+    }
+
+    // on_open gets None when database can't be opened, outside of browser or in some private modes
+    fn open(on_open: impl FnOnce(Option<web_sys::IdbDatabase>) + 'static) {
+        let on_open = Rc::new(RefCell::new(Some(on_open)));
+        let finish = move |db: Option<web_sys::IdbDatabase>| {
+            if let Some(on_open) = on_open.borrow_mut().take() {
+                on_open(db);
+            }
+        };
+        let finish = Rc::new(finish);
+
+        // native build has no IndexedDB, sessions are only kept in browser
+        if !cfg!(target_arch = "wasm32") {
+            return (*finish)(None);
+        }
+        let request = web_sys::window()
+            .and_then(|window| window.indexed_db().ok().flatten())
+            .and_then(|factory| factory.open_with_u32(DB_NAME, 1).ok());
+        let request = match request {
+            Some(request) => request,
+            None => return (*finish)(None),
+        };
+
+        // first open creates the store
+        let upgrade_request = request.clone();
+        let on_upgrade = Closure::once_into_js(move || {
+            let db = upgrade_request.result().ok().and_then(|db| db.dyn_into::<web_sys::IdbDatabase>().ok());
+            if let Some(db) = db {
+                db.create_object_store(STORE_NAME).ok();
+            }
+        });
+        request.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+
+        let (success_request, success_finish) = (request.clone(), finish.clone());
+        let on_success = Closure::once_into_js(move || {
+            (*success_finish)(success_request.result().ok().and_then(|db| db.dyn_into().ok()));
+        });
+        request.set_onsuccess(Some(on_success.unchecked_ref()));
+        let on_error = Closure::once_into_js(move || (*finish)(None));
+        request.set_onerror(Some(on_error.unchecked_ref()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene() -> (Vec<IndexedMesh>, Vec<MeshState>) {
+        let mut mesh = IndexedMesh::box3d(Vector3::new(1.0, 2.0, 3.0));
+        mesh.name = "box".to_string();
+        mesh.ensure_normals();
+        mesh.face_colors = vec![Vector3::new(1.0, 0.5, 0.0); mesh.indices.len() / 3];
+        let mesh_state = MeshState { is_visible: false, alpha: 0.5, ..Default::default() };
+        (vec![mesh, IndexedMesh::default()], vec![mesh_state, MeshState::default()])
+    }
+
+    #[test]
+    fn round_trip() {
+        let (meshes, mesh_states) = scene();
+        let scene = Session::from_bytes(&Session::to_bytes("scene", &meshes, &mesh_states).unwrap()).unwrap();
+
+        assert_eq!(scene.name, "scene");
+        assert_eq!(scene.meshes.len(), 2);
+        for (restored, mesh) in scene.meshes.iter().zip(meshes.iter()) {
+            assert_eq!(restored.name, mesh.name);
+            assert_eq!(restored.positions, mesh.positions);
+            assert_eq!(restored.normals, mesh.normals);
+            assert_eq!(restored.indices, mesh.indices);
+            assert_eq!(restored.face_colors, mesh.face_colors);
+        }
+        assert!(!scene.meshes[0].normals_dirty);
+        assert!(!scene.mesh_states[0].is_visible);
+        assert_eq!(scene.mesh_states[0].alpha, 0.5);
+    }
+
+    #[test]
+    fn truncated_blob_is_error() {
+        let (meshes, mesh_states) = scene();
+        let bytes = Session::to_bytes("scene", &meshes, &mesh_states).unwrap();
+        for len in [0, 4, bytes.len() / 2, bytes.len() - 1] {
+            assert!(Session::from_bytes(&bytes[..len]).is_err());
+        }
+
+        // zip is fine but the name length inside points past the end
+        let mut payload = SESSION_MAGIC.to_vec();
+        payload.extend_from_slice(&SESSION_VERSION.to_le_bytes());
+        payload.extend_from_slice(&0u32.to_le_bytes());
+        payload.extend_from_slice(&1u32.to_le_bytes());
+        payload.extend_from_slice(&u32::MAX.to_le_bytes());
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        zip.start_file(SESSION_ENTRY, zip::write::FileOptions::default()).unwrap();
+        zip.write_all(&payload).unwrap();
+        assert!(Session::from_bytes(&zip.finish().unwrap().into_inner()).is_err());
+    }
+}
@@ -4,8 +4,12 @@ use std::ops::{Index, IndexMut, Add, AddAssign};
 
 use cgmath::*;
 
+use crate::halfedge::HalfEdgeMesh;
 use crate::mesh::IndexedMesh;
 
+// vertices closer than this fraction of aabb diagonal are treated as one
+pub const WELD_EPS_RELATIVE: f32 = 0.000001;
+
 #[derive(Default, Clone, Copy)]
 struct SymetricMatrix {
     m: [f32; 10],
@@ -121,6 +125,13 @@ pub struct Simplify {
     triangles: Vec<Triangle>,
     vertices: Vec<Vertex>,
     refs: Vec<Ref>,
+
+    // keep optimal collapse point near the edge, avoids spikes on noisy meshes
+    pub is_conservative_placement: bool,
+    // dihedral angle in degrees above which edge is kept sharp like a border
+    pub feature_angle: Option<f32>,
+    // smallest corner angle in degrees collapses may leave, unless the triangle was already worse
+    pub min_angle: Option<f32>,
 }
 
 impl Simplify {
@@ -129,7 +140,20 @@ impl Simplify {
             triangles: vec![],
             vertices: vec![],
             refs: vec![],
+            is_conservative_placement: false,
+            feature_angle: None,
+            min_angle: None,
+        };
+
+        // unwelded meshes (e.g. from stl) have only border vertices and barely collapse
+        let mut welded = IndexedMesh {
+            positions: mesh.positions.clone(),
+            indices: mesh.indices.clone(),
+            ..Default::default()
         };
+        let (min, max) = welded.calculate_aabb();
+        welded.weld_vertices((max - min).magnitude() * WELD_EPS_RELATIVE);
+        let mesh = &welded;
 
         for p in mesh.positions.iter() {
             let v = Vertex {
@@ -141,7 +165,21 @@ impl Simplify {
             };
             simp.vertices.push(v);
         }
-        for face_idxs in mesh.indices.windows(3).step_by(3) {
+        for face_idxs in mesh.faces() {
+            if face_idxs[0] == face_idxs[1] || face_idxs[1] == face_idxs[2] || face_idxs[2] == face_idxs[0] {
+                continue;
+            }
+
+            // zero area triangles have no plane and would poison quadrics with NaN
+            let (p0, p1, p2) = (
+                mesh.positions[face_idxs[0] as usize],
+                mesh.positions[face_idxs[1] as usize],
+                mesh.positions[face_idxs[2] as usize]
+            );
+            if !Simplify::is_finite((p1 - p0).cross(p2 - p0).normalize()) {
+                continue;
+            }
+
             let t = Triangle {
                 v: [face_idxs[0], face_idxs[1], face_idxs[2]],
                 err: [0.0; 4],
@@ -166,7 +204,11 @@ impl Simplify {
             if t.deleted != 0 { continue; }
             mesh.indices.extend(t.v);
         }
-        mesh.recalculate_normals();
+        mesh.normals_dirty = true;
+    }
+
+    fn is_finite(v: Vector3<f32>) -> bool {
+        v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
     }
 
     fn vertex_error(q: &SymetricMatrix, v: &Vector3<f32>) -> f32 {
@@ -179,15 +221,39 @@ impl Simplify {
         let mut q = self.vertices[id_v1 as usize].q + self.vertices[id_v2 as usize].q;
         let border = self.vertices[id_v1 as usize].border & self.vertices[id_v2 as usize].border;
         let det = q.det(0, 1, 2, 1, 4, 5, 2, 5, 7);
-        let error;
+        let mut error = std::f32::NAN;
 
         if det != 0.0 && border == 0 {
-            p_result.x = -1.0 / det * q.det(1, 2, 3, 4, 5, 6, 5, 7, 8);
-            p_result.y =  1.0 / det * q.det(0, 2, 3, 1, 5, 6, 2, 7, 8);
-            p_result.z = -1.0 / det * q.det(0, 1, 3, 1, 4, 6, 2, 5, 8);
-            error = Simplify::vertex_error(&q, &p_result);
+            let mut p = Vector3::new(
+                -1.0 / det * q.det(1, 2, 3, 4, 5, 6, 5, 7, 8),
+                 1.0 / det * q.det(0, 2, 3, 1, 5, 6, 2, 7, 8),
+                -1.0 / det * q.det(0, 1, 3, 1, 4, 6, 2, 5, 8)
+            );
+
+            if self.is_conservative_placement {
+                const MAX_DIST_TO_EDGE: f32 = 0.5;
+
+                // snap to closest point of the edge when optimum is too far from it
+                let p1 = self.vertices[id_v1 as usize].p;
+                let edge = self.vertices[id_v2 as usize].p - p1;
+                let edge_len2 = edge.magnitude2();
+                if edge_len2 > 0.0 {
+                    let t = ((p - p1).dot(edge) / edge_len2).clamp(0.0, 1.0);
+                    let closest = p1 + edge * t;
+                    if closest.distance2(p) > MAX_DIST_TO_EDGE * MAX_DIST_TO_EDGE * edge_len2 {
+                        p = closest;
+                    }
+                }
+            }
+
+            // nearly singular quadric overflows, fall back to picking from the edge then
+            if Simplify::is_finite(p) {
+                *p_result = p;
+                error = Simplify::vertex_error(&q, &p);
+            }
         }
-        else {
+
+        if !error.is_finite() {
             let p1 = self.vertices[id_v1 as usize].p;
             let p2 = self.vertices[id_v2 as usize].p;
             let p3 = (p1 + p2) / 2.0;
@@ -306,39 +372,17 @@ impl Simplify {
 
         if iteration == 0 {
 
-            let mut vcount = vec![];
-            let mut vids = vec![];
-
             for v in &mut self.vertices {
                 v.border = 0;
             }
 
-            for i in 0..self.vertices.len() {
-                vcount.clear();
-                vids.clear();
-                for j in 0..self.vertices[i].tcount {
-
-                    let k = self.refs[(self.vertices[i].tstart + j) as usize].tid;
-                    let t = &self.triangles[k as usize];
-                    for k in 0..3 {
-                        let mut ofs = 0;
-                        let id = t.v[k];
-                        while ofs < vcount.len() {
-                            if vids[ofs] == id { break; }
-                            ofs += 1;
-                        }
-                        if ofs == vcount.len() {
-                            vcount.push(1);
-                            vids.push(id);
-                        } else {
-                            vcount[ofs] += 1;
-                        }
-                    }
-                }
-                for j in 0..vcount.len() {
-                    if vcount[j] == 1 {
-                        self.vertices[vids[j] as usize].border = 1;
-                    }
+            // ends of edges without opposite half edge are on the border
+            let faces: Vec<[u32; 3]> = self.triangles.iter().map(|t| t.v).collect();
+            let half_edge_mesh = HalfEdgeMesh::from_faces(self.vertices.len(), &faces);
+            for h in half_edge_mesh.edges() {
+                if half_edge_mesh.is_boundary(h) {
+                    self.vertices[half_edge_mesh.origin(h) as usize].border = 1;
+                    self.vertices[half_edge_mesh.target(h) as usize].border = 1;
                 }
             }
 
@@ -355,6 +399,10 @@ impl Simplify {
                 }
 
                 n = (p[1] - p[0]).cross(p[2] - p[0]).normalize();
+                if !Simplify::is_finite(n) {
+                    t.n = Vector3::new(0.0, 0.0, 0.0);
+                    continue;
+                }
 
                 t.n = n;
                 for j in 0..3 {
@@ -362,10 +410,42 @@ impl Simplify {
                         self.vertices[t.v[j] as usize].q + SymetricMatrix::from_plane(n.x, n.y, n.z, -n.dot(p[0]));
                 }
             }
+
+            // vertices on sharp edges can only collapse along them, same as border ones
+            if let Some(feature_angle) = self.feature_angle {
+                let face_normals: Vec<Vector3<f32>> = self.triangles.iter().map(|t| t.n).collect();
+                for h in half_edge_mesh.feature_edges(&face_normals, feature_angle) {
+                    self.vertices[half_edge_mesh.origin(h) as usize].border = 1;
+                    self.vertices[half_edge_mesh.target(h) as usize].border = 1;
+                }
+            }
+            // errors of triangles are independent of each other
+            #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+            {
+                use rayon::prelude::*;
+
+                let errors: Vec<[f32; 4]> = self.triangles
+                    .par_iter()
+                    .map(|t| {
+                        let mut p = Vector3::new(0.0f32, 0.0, 0.0);
+                        let mut err = [0.0f32; 4];
+                        for j in 0..3 {
+                            err[j] = self.calculate_error(t.v[j], t.v[(j + 1) % 3], &mut p);
+                        }
+                        err[3] = err[0].min(err[1].min(err[2]));
+                        err
+                    })
+                    .collect();
+
+                for (t, err) in self.triangles.iter_mut().zip(errors) {
+                    t.err = err;
+                }
+            }
+            #[cfg(not(all(feature = "rayon", not(target_arch = "wasm32"))))]
             for i in 0..self.triangles.len() {
                 let mut p = Vector3::new(0.0f32, 0.0, 0.0);
                 for j in 0..3 {
-                    self.triangles[j].err[j] =
+                    self.triangles[i].err[j] =
                         self.calculate_error(self.triangles[i].v[j], self.triangles[i].v[(j + 1) % 3], &mut p);
                 }
                 self.triangles[i].err[3] = self.triangles[i].err[0]
@@ -403,6 +483,12 @@ impl Simplify {
         }
     }
 
+    // cosine of the smallest corner angle, the larger the worse the triangle
+    fn max_corner_cos(p0: Vector3<f32>, p1: Vector3<f32>, p2: Vector3<f32>) -> f32 {
+        let corner_cos = |a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>| (b - a).normalize().dot((c - a).normalize());
+        corner_cos(p0, p1, p2).max(corner_cos(p1, p2, p0)).max(corner_cos(p2, p0, p1))
+    }
+
     fn flipped(&mut self, p: &Vector3<f32>, i1: u32, v_idx: usize, deleted: &mut Vec<i32>) -> bool {
         for k in 0..self.vertices[v_idx].tcount {
             let t = &self.triangles[self.refs[(self.vertices[v_idx].tstart + k) as usize].tid as usize];
@@ -434,7 +520,97 @@ impl Simplify {
         false
     }
 
+    // after flipped filled deleted flags of both rings, compares their worst triangle before and after,
+    // so collapses removing slivers pass even when the result is still below min angle
+    fn is_worse_shape(&self, p: &Vector3<f32>, i0: usize, i1: usize, deleted0: &[i32], deleted1: &[i32], min_angle_cos: f32) -> bool {
+        let (mut old_cos, mut new_cos) = (-1.0f32, -1.0f32);
+        for (v_idx, deleted) in [(i0, deleted0), (i1, deleted1)] {
+            for k in 0..self.vertices[v_idx].tcount {
+                let r = &self.refs[(self.vertices[v_idx].tstart + k) as usize];
+                let t = &self.triangles[r.tid as usize];
+                if t.deleted != 0 { continue; }
+
+                let [p0, p1, p2] = t.v.map(|idx| self.vertices[idx as usize].p);
+                old_cos = old_cos.max(Simplify::max_corner_cos(p0, p1, p2));
+                if deleted[k as usize] != 0 { continue; }
+
+                let id1 = t.v[((r.tvertex + 1) % 3) as usize];
+                let id2 = t.v[((r.tvertex + 2) % 3) as usize];
+                new_cos = new_cos.max(Simplify::max_corner_cos(*p, self.vertices[id1 as usize].p, self.vertices[id2 as usize].p));
+            }
+        }
+
+        new_cos > min_angle_cos && new_cos > old_cos
+    }
+
     pub fn simplify_mesh(&mut self, target_count: usize, agr: f32) {
+        self.simplify(target_count, agr, None);
+    }
+
+    // collapse as much as possible while surface moves less than max_error in world units
+    pub fn simplify_mesh_to_error(&mut self, max_error: f32, agr: f32) {
+        self.simplify(0, agr, Some(max_error));
+    }
+
+    // snaps vertices to a uniform grid with grid_resolution cells along the longest side and merges
+    // every occupied cell into centroid of its vertices, far rougher than quadrics but near instant
+    pub fn vertex_cluster(mesh: &IndexedMesh, grid_resolution: usize) -> IndexedMesh {
+        use std::collections::{HashMap, HashSet};
+
+        let mut clustered = IndexedMesh::default();
+        if mesh.positions.is_empty() { return clustered; }
+
+        let (min, max) = mesh.calculate_aabb();
+        let extent = max - min;
+        let cell_size = (extent.x.max(extent.y).max(extent.z) / grid_resolution.max(1) as f32).max(1e-12);
+
+        let mut cells: HashMap<(i64, i64, i64), u32> = HashMap::new();
+        let mut sums: Vec<(Vector3<f32>, u32)> = vec![];
+        let remap: Vec<u32> = mesh.positions
+            .iter()
+            .map(|p| {
+                let cell = (
+                    ((p.x - min.x) / cell_size).floor() as i64,
+                    ((p.y - min.y) / cell_size).floor() as i64,
+                    ((p.z - min.z) / cell_size).floor() as i64,
+                );
+                let idx = *cells.entry(cell).or_insert_with(|| {
+                    sums.push((Vector3::new(0.0, 0.0, 0.0), 0));
+                    sums.len() as u32 - 1
+                });
+                sums[idx as usize].0 += *p;
+                sums[idx as usize].1 += 1;
+                idx
+            })
+            .collect();
+        clustered.positions = sums.iter().map(|&(sum, cnt)| sum / cnt as f32).collect();
+
+        // faces inside one cell collapse, faces sharing all cells with another one are doubled
+        let mut faces: HashSet<[u32; 3]> = HashSet::new();
+        for face_idxs in mesh.faces() {
+            let face = [remap[face_idxs[0] as usize], remap[face_idxs[1] as usize], remap[face_idxs[2] as usize]];
+            if face[0] == face[1] || face[1] == face[2] || face[2] == face[0] { continue; }
+
+            let mut key = face;
+            key.sort_unstable();
+            if faces.insert(key) {
+                clustered.indices.extend_from_slice(&face);
+            }
+        }
+
+        // drop positions left without faces
+        clustered.remove_unused_vertices();
+        clustered.normals_dirty = true;
+        clustered.ensure_normals();
+
+        clustered
+    }
+
+    fn simplify(&mut self, target_count: usize, agr: f32, max_error: Option<f32>) {
+        // quadric error is a squared distance
+        let max_quadric_error = max_error.map(|max_error| max_error * max_error);
+        let min_angle_cos = self.min_angle.map(|min_angle| Deg(min_angle).cos());
+
         for t in &mut self.triangles {
             t.deleted = 0;
         }
@@ -457,6 +633,7 @@ impl Simplify {
 
             // error between new and old mesh
             let threshold = 0.000000001 * ((iteration + 3) as f32).powf(agr);
+            let deleted_triangles_before = deleted_triangles;
 
             for i in 0..self.triangles.len() {
                 if self.triangles[i].err[3] > threshold { continue; }
@@ -471,13 +648,19 @@ impl Simplify {
                         if self.vertices[i0].border != self.vertices[i1].border { continue; }
 
                         let mut p = Vector3::new(0.0f32, 0.0, 0.0);
-                        self.calculate_error(i0 as u32, i1 as u32, &mut p);
+                        let error = self.calculate_error(i0 as u32, i1 as u32, &mut p);
+                        if let Some(max_quadric_error) = max_quadric_error {
+                            if error > max_quadric_error { continue; }
+                        }
 
                         deleted0.resize(self.vertices[i0].tcount as usize, 0);
                         deleted1.resize(self.vertices[i1].tcount as usize, 0);
 
                         if self.flipped(&p, i1 as u32, i0, &mut deleted0) { continue; }
                         if self.flipped(&p, i0 as u32, i1, &mut deleted1) { continue; }
+                        if let Some(min_angle_cos) = min_angle_cos {
+                            if self.is_worse_shape(&p, i0, i1, &deleted0, &deleted1, min_angle_cos) { continue; }
+                        }
 
                         self.vertices[i0].p = p;
                         self.vertices[i0].q = self.vertices[i1].q + self.vertices[i0].q;
@@ -504,8 +687,183 @@ impl Simplify {
 
                 if triangle_count - deleted_triangles <= target_count { break; }
             }
+
+            // nothing left below the tolerance
+            if let Some(max_quadric_error) = max_quadric_error {
+                if threshold > max_quadric_error && deleted_triangles == deleted_triangles_before { break; }
+            }
         }
 
         self.clean_mesh();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // box with every side split into n x n quads and bulged outwards, so collapses cost something
+    fn bulged_box(n: u32) -> IndexedMesh {
+        let mut mesh = IndexedMesh::default();
+        for axis in 0..3 {
+            for side in [-1.0f32, 1.0] {
+                let base = mesh.positions.len() as u32;
+                for j in 0..=n {
+                    for i in 0..=n {
+                        let u = i as f32 / n as f32 * 2.0 - 1.0;
+                        let v = j as f32 / n as f32 * 2.0 - 1.0;
+                        let mut p = [0.0; 3];
+                        p[axis] = side * (1.0 + 0.3 * (1.0 - u * u) * (1.0 - v * v));
+                        p[(axis + 1) % 3] = u;
+                        p[(axis + 2) % 3] = v;
+                        mesh.positions.push(Vector3::new(p[0], p[1], p[2]));
+                    }
+                }
+                for j in 0..n {
+                    for i in 0..n {
+                        let a = base + j * (n + 1) + i;
+                        let (b, c) = (a + 1, a + n + 1);
+                        let d = c + 1;
+                        if side > 0.0 {
+                            mesh.indices.extend_from_slice(&[a, b, c, b, d, c]);
+                        }
+                        else {
+                            mesh.indices.extend_from_slice(&[a, c, b, b, c, d]);
+                        }
+                    }
+                }
+            }
+        }
+        mesh.weld_vertices(1e-5);
+        mesh
+    }
+
+    #[test]
+    fn update_mesh_initializes_every_triangle_error() {
+        let mesh = bulged_box(24);
+        let mut simplify = Simplify::from(&mesh);
+        simplify.update_mesh(0);
+
+        let mut p = Vector3::new(0.0f32, 0.0, 0.0);
+        let mut nonzero_cnt = 0;
+        for t in simplify.triangles.iter() {
+            for j in 0..3 {
+                let expected = simplify.calculate_error(t.v[j], t.v[(j + 1) % 3], &mut p);
+                assert_eq!(t.err[j], expected);
+                if expected > 0.0 { nonzero_cnt += 1; }
+            }
+            assert_eq!(t.err[3], t.err[0].min(t.err[1].min(t.err[2])));
+        }
+        // bulged sides, so zero errors of untouched triangles would show up
+        assert!(nonzero_cnt > simplify.triangles.len());
+    }
+
+    #[test]
+    fn lower_target_never_keeps_more_faces() {
+        let mesh = bulged_box(24);
+        let faces_cnt = mesh.indices.len() / 3;
+        let mut prev_cnt = faces_cnt;
+        for percent in (5..=95).rev().step_by(10) {
+            let mut simplify = Simplify::from(&mesh);
+            simplify.simplify_mesh(faces_cnt * percent / 100, 7.0);
+            let mut simplified = IndexedMesh::default();
+            simplify.to(&mut simplified);
+
+            let cnt = simplified.indices.len() / 3;
+            assert!(cnt <= prev_cnt, "{} faces at {}%, {} before", cnt, percent, prev_cnt);
+            assert!(cnt <= faces_cnt * percent / 100 + 2, "{} faces at {}%", cnt, percent);
+            prev_cnt = cnt;
+        }
+    }
+
+    // cargo test --release --features rayon -- --ignored --nocapture parallel_passes_speedup
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    #[test]
+    #[ignore]
+    fn parallel_passes_speedup() {
+        let mut mesh = bulged_box(400);
+        let mut simplify = Simplify::from(&mesh);
+
+        let mut time = |threads_cnt: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads_cnt).build().unwrap();
+            pool.install(|| {
+                let start = std::time::Instant::now();
+                mesh.recalculate_normals();
+                let normals_time = start.elapsed();
+
+                let start = std::time::Instant::now();
+                simplify.update_mesh(0);
+                (normals_time, start.elapsed())
+            })
+        };
+
+        let (normals_serial, update_serial) = time(1);
+        let (normals_parallel, update_parallel) = time(rayon::current_num_threads());
+        println!(
+            "{} faces, normals {:?} -> {:?} ({:.1}x), update_mesh {:?} -> {:?} ({:.1}x)",
+            mesh.indices.len() / 3,
+            normals_serial, normals_parallel, normals_serial.as_secs_f32() / normals_parallel.as_secs_f32(),
+            update_serial, update_parallel, update_serial.as_secs_f32() / update_parallel.as_secs_f32(),
+        );
+    }
+
+    #[test]
+    fn degenerate_triangle_keeps_result_sane() {
+        let mut mesh = bulged_box(8);
+        // zero area sliver on top of an existing edge
+        let (a, b) = (mesh.indices[0], mesh.indices[1]);
+        let middle = mesh.positions.len() as u32;
+        mesh.positions.push((mesh.positions[a as usize] + mesh.positions[b as usize]) / 2.0);
+        mesh.indices.extend_from_slice(&[a, middle, b]);
+
+        let faces_cnt = mesh.indices.len() / 3;
+        let mut simplify = Simplify::from(&mesh);
+        simplify.simplify_mesh(faces_cnt / 4, 7.0);
+        let mut simplified = IndexedMesh::default();
+        simplify.to(&mut simplified);
+
+        assert!(!simplified.indices.is_empty() && simplified.indices.len() / 3 < faces_cnt);
+        assert!(simplified.positions.iter().all(|p| Simplify::is_finite(*p)));
+        // surface stays near the bulged box, nothing flew away
+        assert!(simplified.positions.iter().all(|p| p.x.abs().max(p.y.abs()).max(p.z.abs()) < 1.5));
+    }
+
+    #[test]
+    fn vertex_cluster_keeps_valid_faces() {
+        // uv sphere, poles repeat their vertex along the whole ring
+        let (rings, segments) = (32, 64);
+        let mut sphere = IndexedMesh::default();
+        for ring in 0..=rings {
+            let theta = std::f32::consts::PI * ring as f32 / rings as f32;
+            for segment in 0..segments {
+                let phi = 2.0 * std::f32::consts::PI * segment as f32 / segments as f32;
+                sphere.positions.push(Vector3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()));
+            }
+        }
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let a = ring * segments + segment;
+                let b = ring * segments + (segment + 1) % segments;
+                let (c, d) = (a + segments, b + segments);
+                sphere.indices.extend_from_slice(&[a, c, b, b, c, d]);
+            }
+        }
+
+        let clustered = Simplify::vertex_cluster(&sphere, 8);
+        assert!(clustered.indices.len() < sphere.indices.len());
+        assert!(!clustered.indices.is_empty());
+        assert_eq!(clustered.normals.len(), clustered.positions.len());
+        for face in clustered.faces() {
+            assert!(face[0] != face[1] && face[1] != face[2] && face[2] != face[0]);
+            let [v0, v1, v2] = face.map(|idx| clustered.positions[idx as usize]);
+            assert!((v1 - v0).cross(v2 - v0).magnitude2() > 0.0);
+        }
+
+        // every kept position is used by a face
+        let mut is_used = vec![false; clustered.positions.len()];
+        for &idx in clustered.indices.iter() {
+            is_used[idx as usize] = true;
+        }
+        assert!(is_used.iter().all(|&is_used| is_used));
+    }
+}
@@ -0,0 +1,379 @@
+use cgmath::*;
+
+use crate::mesh::IndexedMesh;
+
+const MAX_FACES_PER_LEAF: usize = 4;
+
+#[derive(Clone, Copy)]
+struct Node {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+    // leaf holds faces [start, start + count), inner node has children at left and left + 1
+    start: u32,
+    count: u32,
+    left: u32,
+}
+
+// bounding volume hierarchy over mesh faces, keeps own copy of triangles
+pub struct AabbTree {
+    nodes: Vec<Node>,
+    triangles: Vec<[Vector3<f32>; 3]>,
+    // index of mesh face for every triangle, they get reordered by the build
+    faces: Vec<u32>,
+}
+
+impl AabbTree {
+    // big meshes log how far the build got, it blocks the page until it's done
+    pub fn from(mesh: &IndexedMesh) -> Self {
+        const PROGRESS_MIN_FACES: usize = 1_000_000;
+        let is_big = mesh.indices.len() / 3 >= PROGRESS_MIN_FACES;
+        AabbTree::from_with_progress(mesh, |fraction| if is_big {
+            tracing::info!("Building spatial tree: {:.0}%", fraction * 100.0);
+        })
+    }
+
+    // progress gets fraction of faces already placed into leaves
+    pub fn from_with_progress(mesh: &IndexedMesh, mut progress: impl FnMut(f32)) -> Self {
+        let mut triangles: Vec<[Vector3<f32>; 3]> = mesh.faces()
+            .map(|face_idxs| [
+                mesh.positions[face_idxs[0] as usize],
+                mesh.positions[face_idxs[1] as usize],
+                mesh.positions[face_idxs[2] as usize],
+            ])
+            .collect();
+        let mut centroids: Vec<Vector3<f32>> = triangles.iter().map(|t| (t[0] + t[1] + t[2]) / 3.0).collect();
+        let mut faces: Vec<u32> = (0..triangles.len() as u32).collect();
+
+        let mut tree = AabbTree {
+            nodes: vec![],
+            triangles: vec![],
+            faces: vec![],
+        };
+        if triangles.is_empty() {
+            return tree;
+        }
+
+        let faces_cnt = triangles.len();
+        let mut placed_cnt = 0;
+        let mut last_reported = 0;
+
+        tree.nodes.push(AabbTree::leaf(&triangles, 0, faces_cnt));
+        let mut stack = vec![0usize];
+        while let Some(node_id) = stack.pop() {
+            let node = tree.nodes[node_id];
+            let (start, count) = (node.start as usize, node.count as usize);
+
+            if count <= MAX_FACES_PER_LEAF {
+                placed_cnt += count;
+                // report about a hundred times over the build
+                if placed_cnt - last_reported > faces_cnt / 100 || placed_cnt == faces_cnt {
+                    last_reported = placed_cnt;
+                    progress(placed_cnt as f32 / faces_cnt as f32);
+                }
+                continue;
+            }
+
+            // median split of centroids along the longest axis
+            let extent = node.max - node.min;
+            let axis = if extent.x > extent.y && extent.x > extent.z {
+                0
+            } else if extent.y > extent.z {
+                1
+            } else {
+                2
+            };
+
+            let mid = count / 2;
+            let mut order: Vec<usize> = (start..start + count).collect();
+            order.select_nth_unstable_by(mid, |&a, &b| {
+                centroids[a][axis].partial_cmp(&centroids[b][axis]).unwrap_or(std::cmp::Ordering::Equal)
+            });
+            let sorted_triangles: Vec<_> = order.iter().map(|&i| triangles[i]).collect();
+            let sorted_centroids: Vec<_> = order.iter().map(|&i| centroids[i]).collect();
+            let sorted_faces: Vec<_> = order.iter().map(|&i| faces[i]).collect();
+            triangles[start..start + count].copy_from_slice(&sorted_triangles);
+            centroids[start..start + count].copy_from_slice(&sorted_centroids);
+            faces[start..start + count].copy_from_slice(&sorted_faces);
+
+            let left = tree.nodes.len();
+            tree.nodes.push(AabbTree::leaf(&triangles, start, mid));
+            tree.nodes.push(AabbTree::leaf(&triangles, start + mid, count - mid));
+
+            tree.nodes[node_id].count = 0;
+            tree.nodes[node_id].left = left as u32;
+            stack.push(left);
+            stack.push(left + 1);
+        }
+
+        tree.triangles = triangles;
+        tree.faces = faces;
+        tree
+    }
+
+    fn leaf(triangles: &[[Vector3<f32>; 3]], start: usize, count: usize) -> Node {
+        let (mut min, mut max) = (
+            Vector3::new(std::f32::MAX, std::f32::MAX, std::f32::MAX),
+            Vector3::new(std::f32::MIN, std::f32::MIN, std::f32::MIN)
+        );
+        for p in triangles[start..start + count].iter().flatten() {
+            min = Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+
+        Node { min, max, start: start as u32, count: count as u32, left: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    // distance along the ray to the closest hit
+    pub fn ray_intersect(&self, origin: Vector3<f32>, dir: Vector3<f32>) -> Option<f32> {
+        self.ray_intersect_face(origin, dir).map(|(t, _)| t)
+    }
+
+    // distance along the ray to the closest hit and index of the hit mesh face
+    pub fn ray_intersect_face(&self, origin: Vector3<f32>, dir: Vector3<f32>) -> Option<(f32, u32)> {
+        if self.is_empty() { return None; }
+
+        let inv_dir = Vector3::new(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+        let mut closest: Option<(f32, u32)> = None;
+
+        let mut stack = vec![0usize];
+        while let Some(node_id) = stack.pop() {
+            let node = &self.nodes[node_id];
+
+            let t_box = AabbTree::ray_box(node, origin, inv_dir);
+            if t_box.map_or(true, |t_box| closest.map_or(false, |(closest, _)| t_box > closest)) {
+                continue;
+            }
+
+            if node.count > 0 {
+                for i in node.start as usize..(node.start + node.count) as usize {
+                    if let Some(t) = AabbTree::ray_triangle(&self.triangles[i], origin, dir) {
+                        if closest.map_or(true, |(closest, _)| t < closest) {
+                            closest = Some((t, self.faces[i]));
+                        }
+                    }
+                }
+            } else {
+                stack.push(node.left as usize);
+                stack.push(node.left as usize + 1);
+            }
+        }
+
+        closest
+    }
+
+    // any hit closer than max_dist, stops at the first one found
+    pub fn is_hit(&self, origin: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> bool {
+        if self.is_empty() { return false; }
+
+        let inv_dir = Vector3::new(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+        let mut stack = vec![0usize];
+        while let Some(node_id) = stack.pop() {
+            let node = &self.nodes[node_id];
+
+            if AabbTree::ray_box(node, origin, inv_dir).map_or(true, |t_box| t_box > max_dist) {
+                continue;
+            }
+
+            if node.count > 0 {
+                let triangles = &self.triangles[node.start as usize..(node.start + node.count) as usize];
+                if triangles.iter().any(|t| AabbTree::ray_triangle(t, origin, dir).map_or(false, |t| t <= max_dist)) {
+                    return true;
+                }
+            } else {
+                stack.push(node.left as usize);
+                stack.push(node.left as usize + 1);
+            }
+        }
+
+        false
+    }
+
+    // closest point of the surface to p
+    // closest point of the surface to p and index of the mesh face it lies on
+    pub fn nearest_point_face(&self, p: Vector3<f32>) -> Option<(Vector3<f32>, u32)> {
+        if self.is_empty() { return None; }
+
+        let mut nearest: Option<(Vector3<f32>, f32, u32)> = None;
+
+        let mut stack = vec![0usize];
+        while let Some(node_id) = stack.pop() {
+            let node = &self.nodes[node_id];
+
+            let box_dist2 = AabbTree::box_distance2(node, p);
+            if nearest.map_or(false, |(_, dist2, _)| box_dist2 >= dist2) {
+                continue;
+            }
+
+            if node.count > 0 {
+                for i in node.start as usize..(node.start + node.count) as usize {
+                    let closest = AabbTree::closest_point_on_triangle(&self.triangles[i], p);
+                    let dist2 = closest.distance2(p);
+                    if nearest.map_or(true, |(_, nearest_dist2, _)| dist2 < nearest_dist2) {
+                        nearest = Some((closest, dist2, self.faces[i]));
+                    }
+                }
+            } else {
+                // visit closer child first, so the other one is more likely pruned
+                let (left, right) = (node.left as usize, node.left as usize + 1);
+                if AabbTree::box_distance2(&self.nodes[left], p) < AabbTree::box_distance2(&self.nodes[right], p) {
+                    stack.push(right);
+                    stack.push(left);
+                } else {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+
+        nearest.map(|(p, _, face)| (p, face))
+    }
+
+    // slab test, distance to the box entry or None when missed
+    fn ray_box(node: &Node, origin: Vector3<f32>, inv_dir: Vector3<f32>) -> Option<f32> {
+        let mut t_min = 0.0f32;
+        let mut t_max = std::f32::MAX;
+        for axis in 0..3 {
+            let t1 = (node.min[axis] - origin[axis]) * inv_dir[axis];
+            let t2 = (node.max[axis] - origin[axis]) * inv_dir[axis];
+            // NaN from 0 * inf keeps the old value
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+        }
+
+        if t_min <= t_max { Some(t_min) } else { None }
+    }
+
+    fn box_distance2(node: &Node, p: Vector3<f32>) -> f32 {
+        let dx = (node.min.x - p.x).max(0.0).max(p.x - node.max.x);
+        let dy = (node.min.y - p.y).max(0.0).max(p.y - node.max.y);
+        let dz = (node.min.z - p.z).max(0.0).max(p.z - node.max.z);
+        dx * dx + dy * dy + dz * dz
+    }
+
+    // Moller-Trumbore
+    pub fn ray_triangle(t: &[Vector3<f32>; 3], origin: Vector3<f32>, dir: Vector3<f32>) -> Option<f32> {
+        let e1 = t[1] - t[0];
+        let e2 = t[2] - t[0];
+        let p = dir.cross(e2);
+        let det = e1.dot(p);
+        if det.abs() < std::f32::EPSILON { return None; }
+
+        let inv_det = 1.0 / det;
+        let s = origin - t[0];
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) { return None; }
+
+        let q = s.cross(e1);
+        let v = dir.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 { return None; }
+
+        let dist = e2.dot(q) * inv_det;
+        if dist > 0.0 { Some(dist) } else { None }
+    }
+
+    // Ericson, Real-Time Collision Detection 5.1.5
+    fn closest_point_on_triangle(t: &[Vector3<f32>; 3], p: Vector3<f32>) -> Vector3<f32> {
+        let (a, b, c) = (t[0], t[1], t[2]);
+        let ab = b - a;
+        let ac = c - a;
+
+        let ap = p - a;
+        let d1 = ab.dot(ap);
+        let d2 = ac.dot(ap);
+        if d1 <= 0.0 && d2 <= 0.0 { return a; }
+
+        let bp = p - b;
+        let d3 = ab.dot(bp);
+        let d4 = ac.dot(bp);
+        if d3 >= 0.0 && d4 <= d3 { return b; }
+
+        let vc = d1 * d4 - d3 * d2;
+        if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+            return a + ab * (d1 / (d1 - d3));
+        }
+
+        let cp = p - c;
+        let d5 = ab.dot(cp);
+        let d6 = ac.dot(cp);
+        if d6 >= 0.0 && d5 <= d6 { return c; }
+
+        let vb = d5 * d2 - d1 * d6;
+        if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+            return a + ac * (d2 / (d2 - d6));
+        }
+
+        let va = d3 * d6 - d5 * d4;
+        if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+            return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+        }
+
+        let denom = 1.0 / (va + vb + vc);
+        let v = vb * denom;
+        let w = vc * denom;
+        if !(v.is_finite() && w.is_finite()) { return a; }
+
+        a + ab * v + ac * w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // triangle soup in unit cube, lcg keeps it the same on every run
+    fn random_mesh(seed: &mut u32, faces_cnt: usize) -> IndexedMesh {
+        let mut mesh = IndexedMesh::default();
+        for _ in 0..faces_cnt * 3 {
+            mesh.indices.push(mesh.positions.len() as u32);
+            mesh.positions.push(random_vector(seed));
+        }
+        mesh
+    }
+
+    fn random_vector(seed: &mut u32) -> Vector3<f32> {
+        let mut next = || {
+            *seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (*seed >> 8) as f32 / (1 << 24) as f32
+        };
+        Vector3::new(next(), next(), next())
+    }
+
+    // closest hit and its face over all faces, what the tree must agree with
+    fn linear_scan(mesh: &IndexedMesh, origin: Vector3<f32>, dir: Vector3<f32>) -> Option<(f32, u32)> {
+        let mut closest: Option<(f32, u32)> = None;
+        for (f, face_idxs) in mesh.faces().enumerate() {
+            let triangle = face_idxs.map(|idx| mesh.positions[idx as usize]);
+            if let Some(t) = AabbTree::ray_triangle(&triangle, origin, dir) {
+                if closest.map_or(true, |(closest, _)| t < closest) {
+                    closest = Some((t, f as u32));
+                }
+            }
+        }
+        closest
+    }
+
+    #[test]
+    fn ray_intersect_matches_linear_scan() {
+        let mut seed = 7;
+        let mesh = random_mesh(&mut seed, 300);
+        let aabb_tree = AabbTree::from(&mesh);
+
+        let mut hits_cnt = 0;
+        for _ in 0..2000 {
+            let origin = random_vector(&mut seed) * 2.0 - Vector3::new(0.5, 0.5, 0.5);
+            let dir = (random_vector(&mut seed) - Vector3::new(0.5, 0.5, 0.5)).normalize();
+
+            let expected = linear_scan(&mesh, origin, dir);
+            assert_eq!(aabb_tree.ray_intersect_face(origin, dir), expected);
+            for max_dist in [0.1, 0.5] {
+                assert_eq!(aabb_tree.is_hit(origin, dir, max_dist), expected.map_or(false, |(t, _)| t <= max_dist));
+            }
+            hits_cnt += expected.is_some() as usize;
+        }
+        assert!(hits_cnt > 100);
+    }
+}